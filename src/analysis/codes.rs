@@ -0,0 +1,114 @@
+//! One-time passcode and magic-link extraction.
+//!
+//! Patterns come from `FAUXMAIL_CODE_PATTERNS` (regexes separated by `;;`)
+//! or the defaults below. The first capture group is the code, or the whole
+//! match when a pattern has no groups. Earlier patterns rank higher.
+
+use super::links::{self, visible_text};
+use crate::models::{
+  analysis::codes_report::{CodeSource, CodesReport, FoundCode, MagicLink},
+  email::db_email::DbEmail,
+};
+use regex::Regex;
+use std::sync::OnceLock;
+use tracing::error;
+
+const DEFAULT_PATTERNS: &[&str] = &[
+  // "Your verification code is 123456", "OTP: 4821"
+  r"(?i)(?:code|otp|passcode|pin|password|verification|one[- ]time)\D{0,24}?\b(\d{4,8})\b",
+  // "Code: 123-456"
+  r"(?i)(?:code|otp|passcode)\D{0,24}?\b(\d{3}[- ]\d{3})\b",
+  // A bare six-digit number anywhere
+  r"\b(\d{6})\b",
+];
+
+/// Path segments and query keys that mark a link as a magic link.
+const LINK_WORDS: &[&str] = &[
+  "token", "magic", "verify", "confirm", "login", "signin", "auth", "reset", "activate", "invite",
+  "code",
+];
+
+fn patterns() -> &'static [Regex] {
+  static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+  PATTERNS.get_or_init(|| {
+    let configured = std::env::var("FAUXMAIL_CODE_PATTERNS").ok();
+    let sources: Vec<&str> = match configured.as_deref() {
+      Some(v) if !v.trim().is_empty() => v.split(";;").map(str::trim).collect(),
+      _ => DEFAULT_PATTERNS.to_vec(),
+    };
+    sources
+      .into_iter()
+      .filter(|p| !p.is_empty())
+      .filter_map(|p| {
+        Regex::new(p)
+          .map_err(|e| error!("invalid FAUXMAIL_CODE_PATTERNS entry {p:?}: {e}"))
+          .ok()
+      })
+      .collect()
+  })
+}
+
+/// Codes and magic links in a stored message.
+pub fn extract(m: &DbEmail) -> CodesReport {
+  let html_text = m.html_body.as_deref().map(visible_text);
+  let fields = [
+    (CodeSource::Subject, m.subject.as_deref()),
+    (CodeSource::Text, m.text_body.as_deref()),
+    (CodeSource::Html, html_text.as_deref()),
+  ];
+  let mut codes: Vec<FoundCode> = Vec::new();
+  for (pattern, re) in patterns().iter().enumerate() {
+    for (source, value) in fields {
+      for caps in re.captures_iter(value.unwrap_or_default()) {
+        let Some(found) = caps.get(1).or_else(|| caps.get(0)) else {
+          continue;
+        };
+        let code = found.as_str().to_string();
+        if !codes.iter().any(|c| c.code == code) {
+          codes.push(FoundCode {
+            code,
+            source,
+            pattern,
+          });
+        }
+      }
+    }
+  }
+
+  let magic_links = links::extract(m.text_body.as_deref(), m.html_body.as_deref(), None)
+    .into_iter()
+    .filter_map(|l| magic_link(&l.url))
+    .collect();
+
+  CodesReport {
+    message_id: m.id,
+    codes,
+    magic_links,
+  }
+}
+
+fn magic_link(url: &str) -> Option<MagicLink> {
+  let (_, rest) = url.split_once("://")?;
+  let (before_query, query) = rest.split_once('?').unwrap_or((rest, ""));
+  let path = before_query
+    .split_once('/')
+    .map_or("", |(_, p)| p)
+    .to_ascii_lowercase();
+  let token = query
+    .split('#')
+    .next()
+    .unwrap_or_default()
+    .split('&')
+    .find_map(|pair| {
+      let (key, value) = pair.split_once('=')?;
+      let key = key.to_ascii_lowercase();
+      (LINK_WORDS.iter().any(|w| key.contains(w)) && value.len() >= 6).then(|| value.to_string())
+    });
+  let path_match = path
+    .split(['/', '-', '_', '.'])
+    .any(|seg| LINK_WORDS.contains(&seg));
+  (token.is_some() || path_match).then(|| MagicLink {
+    url: url.to_string(),
+    token,
+  })
+}
@@ -0,0 +1,116 @@
+//! Bundled email client support data.
+//!
+//! A hand-maintained subset of what caniemail.com tracks, reduced to the
+//! three client families we report on. "Partial" means the feature works in
+//! some versions or only in restricted forms; the note says which.
+
+use crate::models::analysis::compat_report::{
+  Client,
+  Support::{self, Partial as P, Supported as S, Unsupported as U},
+};
+
+/// How a feature is recognised in an HTML body.
+#[derive(Debug, Clone, Copy)]
+pub enum Matcher {
+  /// An element, matched on its opening tag.
+  Element(&'static str),
+  /// An attribute on any element.
+  Attribute(&'static str),
+  /// A CSS property declaration in a `<style>` block or `style` attribute.
+  CssProperty(&'static str),
+  /// A CSS property declared with a value starting with the given text.
+  CssValue(&'static str, &'static str),
+  /// Any other CSS text: at-rules, pseudo-classes, functions.
+  CssToken(&'static str),
+  /// An image referenced by `src` or `url()` with the given extension.
+  ImageFormat(&'static str),
+}
+
+#[derive(Debug)]
+pub struct Feature {
+  pub id: &'static str,
+  pub title: &'static str,
+  pub matcher: Matcher,
+  pub outlook: Support,
+  pub gmail: Support,
+  pub apple_mail: Support,
+  pub note: &'static str,
+}
+
+impl Feature {
+  pub fn support(&self, client: Client) -> Support {
+    match client {
+      Client::Outlook => self.outlook,
+      Client::Gmail => self.gmail,
+      Client::AppleMail => self.apple_mail,
+    }
+  }
+}
+
+const fn feature(
+  id: &'static str,
+  title: &'static str,
+  matcher: Matcher,
+  [outlook, gmail, apple_mail]: [Support; 3],
+  note: &'static str,
+) -> Feature {
+  Feature {
+    id,
+    title,
+    matcher,
+    outlook,
+    gmail,
+    apple_mail,
+    note,
+  }
+}
+
+use Matcher::*;
+
+#[rustfmt::skip]
+pub const FEATURES: &[Feature] = &[
+  // HTML elements
+  feature("html-script", "<script>", Element("script"), [U, U, U], "Scripts are stripped by every client."),
+  feature("html-iframe", "<iframe>", Element("iframe"), [U, U, U], "Embedded frames are removed."),
+  feature("html-object", "<object>", Element("object"), [U, U, U], "Plugins are not loaded."),
+  feature("html-embed", "<embed>", Element("embed"), [U, U, U], "Plugins are not loaded."),
+  feature("html-form", "<form>", Element("form"), [U, P, S], "Gmail shows forms but warns and blocks most submissions."),
+  feature("html-video", "<video>", Element("video"), [U, U, S], "Provide a linked poster image as fallback."),
+  feature("html-audio", "<audio>", Element("audio"), [U, U, S], "Link to the audio instead."),
+  feature("html-svg", "<svg>", Element("svg"), [U, U, S], "Inline SVG is dropped; use PNG."),
+  feature("html-picture", "<picture>", Element("picture"), [U, U, S], "Only the fallback <img> is shown."),
+  feature("html-link-stylesheet", "<link> stylesheets", Element("link"), [U, U, S], "External stylesheets are not fetched; inline the CSS."),
+  feature("html-style", "<style>", Element("style"), [S, P, S], "Gmail drops the whole block on any syntax error and in non-Google accounts."),
+  feature("html-srcset", "srcset attribute", Attribute("srcset"), [U, U, S], "Only src is used."),
+  feature("image-svg", "SVG images", ImageFormat("svg"), [U, U, S], "Use PNG or JPEG."),
+  feature("image-webp", "WebP images", ImageFormat("webp"), [U, S, S], "Outlook shows a broken image."),
+  // CSS properties
+  feature("css-border-radius", "border-radius", CssProperty("border-radius"), [U, S, S], "Outlook renders square corners; use VML for rounded buttons."),
+  feature("css-background-image", "background-image", CssProperty("background-image"), [P, S, S], "Outlook needs a VML fallback."),
+  feature("css-background-size", "background-size", CssProperty("background-size"), [U, P, S], "Gmail ignores it in non-Google accounts."),
+  feature("css-box-shadow", "box-shadow", CssProperty("box-shadow"), [U, P, S], "Gmail only renders it on desktop web."),
+  feature("css-text-shadow", "text-shadow", CssProperty("text-shadow"), [U, S, S], ""),
+  feature("css-max-width", "max-width", CssProperty("max-width"), [P, S, S], "Outlook only honours it on images and tables with fixed widths."),
+  feature("css-float", "float", CssProperty("float"), [U, S, S], "Use table cells or align instead."),
+  feature("css-position", "position", CssProperty("position"), [U, U, S], "Positioned layouts collapse in Outlook and Gmail."),
+  feature("css-z-index", "z-index", CssProperty("z-index"), [U, S, S], ""),
+  feature("css-opacity", "opacity", CssProperty("opacity"), [U, S, S], ""),
+  feature("css-transform", "transform", CssProperty("transform"), [U, P, S], "Gmail supports only 2D transforms."),
+  feature("css-transition", "transition", CssProperty("transition"), [U, U, S], ""),
+  feature("css-animation", "animation", CssProperty("animation"), [U, U, S], ""),
+  feature("css-object-fit", "object-fit", CssProperty("object-fit"), [U, P, S], "Gmail ignores it on mobile apps."),
+  feature("css-overflow", "overflow", CssProperty("overflow"), [U, P, S], "Gmail ignores overflow: hidden on mobile apps."),
+  feature("css-display-flex", "display: flex", CssValue("display", "flex"), [U, P, S], "Gmail ignores flex in non-Google accounts; use tables."),
+  feature("css-display-grid", "display: grid", CssValue("display", "grid"), [U, P, S], "Gmail ignores grid in non-Google accounts; use tables."),
+  feature("css-display-none", "display: none", CssValue("display", "none"), [P, S, S], "Outlook still shows hidden table cells; add mso-hide: all."),
+  // CSS at-rules, selectors and functions
+  feature("css-at-media", "@media queries", CssToken("@media"), [U, P, S], "Gmail supports width queries only; Outlook ignores them."),
+  feature("css-at-font-face", "@font-face", CssToken("@font-face"), [U, U, S], "Web fonts fall back to the font-family stack."),
+  feature("css-at-import", "@import", CssToken("@import"), [U, U, S], ""),
+  feature("css-at-keyframes", "@keyframes", CssToken("@keyframes"), [U, U, S], ""),
+  feature("css-at-supports", "@supports", CssToken("@supports"), [U, U, S], ""),
+  feature("css-pseudo-hover", ":hover", CssToken(":hover"), [U, P, S], "Gmail supports :hover on desktop web only."),
+  feature("css-variables", "CSS variables", CssToken("var("), [U, U, S], ""),
+  feature("css-calc", "calc()", CssToken("calc("), [U, P, S], "Gmail rejects calc() mixing units."),
+  feature("css-linear-gradient", "linear-gradient()", CssToken("linear-gradient("), [U, P, S], "Set a solid background-color fallback."),
+];
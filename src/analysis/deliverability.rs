@@ -0,0 +1,262 @@
+//! Offline spam-score and deliverability lint.
+//!
+//! A small fixed rule set in the spirit of SpamAssassin: each matching rule
+//! adds to the score, and the total is compared against [`THRESHOLD`].
+
+use super::links::{anchors, find_urls, host_of, visible_text};
+use crate::{
+  models::{
+    analysis::deliverability_report::{Finding, Severity},
+    email::db_email::DbEmail,
+  },
+  util::parse_addresses,
+};
+use std::{collections::HashMap, net::IpAddr};
+
+pub const THRESHOLD: f32 = 5.0;
+
+/// Messages above this size are rejected or junked by many providers.
+const MAX_MESSAGE_BYTES: i64 = 10 * 1024 * 1024;
+/// Gmail clips HTML bodies beyond roughly this size.
+const GMAIL_CLIP_BYTES: usize = 102 * 1024;
+
+const SHORTENERS: &[&str] = &[
+  "bit.ly",
+  "tinyurl.com",
+  "goo.gl",
+  "t.co",
+  "ow.ly",
+  "is.gd",
+  "buff.ly",
+  "cutt.ly",
+  "rb.gy",
+];
+
+/// Run every rule over a stored message.
+pub fn analyze(m: &DbEmail) -> Vec<Finding> {
+  let headers: HashMap<String, String> = m
+    .headers_json
+    .as_deref()
+    .and_then(|j| serde_json::from_str::<HashMap<String, String>>(j).ok())
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(k, v)| (k.to_ascii_lowercase(), v))
+    .collect();
+  let mut out = Vec::new();
+  let mut add = |rule, severity, score, message: String| {
+    out.push(Finding {
+      rule,
+      severity,
+      score,
+      message,
+    })
+  };
+
+  if !headers.contains_key("message-id") {
+    add(
+      "missing-message-id",
+      Severity::Warning,
+      1.0,
+      "No Message-ID header.".into(),
+    );
+  }
+  if !headers.contains_key("date") {
+    add(
+      "missing-date",
+      Severity::Warning,
+      1.0,
+      "No Date header.".into(),
+    );
+  }
+
+  let from_domain = m.from_addr.as_deref().and_then(domain_of);
+  let return_path = headers
+    .get("return-path")
+    .cloned()
+    .or_else(|| m.envelope_from.clone());
+  if let (Some(from), Some(rp)) = (&from_domain, return_path.as_deref().and_then(domain_of)) {
+    if !same_org(from, &rp) {
+      add(
+        "from-return-path-mismatch",
+        Severity::Warning,
+        1.0,
+        format!("From domain {from} does not match Return-Path domain {rp}."),
+      );
+    }
+  }
+
+  let html = m.html_body.as_deref().filter(|h| !h.trim().is_empty());
+  let text = m.text_body.as_deref().filter(|t| !t.trim().is_empty());
+  if let Some(html) = html {
+    let visible = visible_text(html);
+    let images = html.to_ascii_lowercase().matches("<img").count();
+    if images > 0 && visible.chars().filter(|c| c.is_alphanumeric()).count() < 40 {
+      add(
+        "image-only-body",
+        Severity::Error,
+        2.0,
+        format!("HTML body is {images} image(s) with almost no text."),
+      );
+    }
+    if text.is_none() {
+      add(
+        "missing-text-alternative",
+        Severity::Warning,
+        1.0,
+        "HTML body has no text/plain alternative.".into(),
+      );
+    }
+    if html.len() > GMAIL_CLIP_BYTES {
+      add(
+        "gmail-clipping",
+        Severity::Warning,
+        0.5,
+        format!(
+          "HTML body is {} KB; Gmail clips above 102 KB.",
+          html.len() / 1024
+        ),
+      );
+    }
+  }
+
+  if let Some(subject) = m.subject.as_deref() {
+    let letters: Vec<char> = subject.chars().filter(|c| c.is_alphabetic()).collect();
+    let upper = letters.iter().filter(|c| c.is_uppercase()).count();
+    if letters.len() >= 8 && upper * 10 >= letters.len() * 7 {
+      add(
+        "shouty-subject",
+        Severity::Warning,
+        1.5,
+        "Subject is mostly capital letters.".into(),
+      );
+    }
+    if subject.contains("!!") || subject.contains("$$") {
+      add(
+        "subject-punctuation",
+        Severity::Info,
+        0.5,
+        "Subject has repeated !! or $$.".into(),
+      );
+    }
+  }
+
+  let mut urls = find_urls(text.unwrap_or_default());
+  for url in html.map(find_urls).unwrap_or_default() {
+    if !urls.contains(&url) {
+      urls.push(url);
+    }
+  }
+  for url in &urls {
+    if let Some(reason) = suspicious_url(url) {
+      add(
+        "suspicious-url",
+        Severity::Warning,
+        1.0,
+        format!("{url}: {reason}."),
+      );
+    }
+  }
+  for a in html.map(anchors).unwrap_or_default() {
+    let shown = a.text.trim_start_matches("www.");
+    let shown_host = if shown.contains("://") {
+      host_of(shown)
+    } else if shown.contains('.') && !shown.contains(' ') {
+      host_of(&format!("http://{shown}"))
+    } else {
+      None
+    };
+    if let (Some(shown), Some(target)) = (shown_host, host_of(&a.href)) {
+      if !same_org(
+        shown.trim_start_matches("www."),
+        target.trim_start_matches("www."),
+      ) {
+        add(
+          "link-text-mismatch",
+          Severity::Error,
+          2.0,
+          format!("Link text shows {shown} but points to {target}."),
+        );
+      }
+    }
+  }
+
+  let bulk = headers.contains_key("list-id")
+    || headers.get("precedence").is_some_and(|p| {
+      matches!(
+        p.trim().to_ascii_lowercase().as_str(),
+        "bulk" | "list" | "junk"
+      )
+    })
+    || [text, html]
+      .into_iter()
+      .flatten()
+      .any(|b| b.to_ascii_lowercase().contains("unsubscribe"));
+  match headers.get("list-unsubscribe") {
+    None if bulk => add(
+      "missing-list-unsubscribe",
+      Severity::Error,
+      1.5,
+      "Bulk mail without a List-Unsubscribe header.".into(),
+    ),
+    Some(_) if !headers.contains_key("list-unsubscribe-post") => add(
+      "missing-one-click-unsubscribe",
+      Severity::Info,
+      0.5,
+      "List-Unsubscribe without List-Unsubscribe-Post one-click support.".into(),
+    ),
+    _ => {}
+  }
+
+  // Messages posted as JSON have no raw size; count their bodies instead.
+  let size = if m.raw_len > 0 {
+    m.raw_len
+  } else {
+    [text, html]
+      .into_iter()
+      .flatten()
+      .map(|b| b.len() as i64)
+      .sum()
+  };
+  if size > MAX_MESSAGE_BYTES {
+    add(
+      "oversized-message",
+      Severity::Error,
+      1.5,
+      format!(
+        "Message is {} MB; many providers reject above 10 MB.",
+        size / (1024 * 1024)
+      ),
+    );
+  }
+
+  out
+}
+
+fn domain_of(value: &str) -> Option<String> {
+  let addr = parse_addresses(value).into_iter().next()?.address;
+  let (_, domain) = addr.rsplit_once('@')?;
+  Some(domain.trim_end_matches('>').to_ascii_lowercase())
+}
+
+/// Domains are treated as one sender when either is a subdomain of the other.
+fn same_org(a: &str, b: &str) -> bool {
+  a == b || a.ends_with(&format!(".{b}")) || b.ends_with(&format!(".{a}"))
+}
+
+fn suspicious_url(url: &str) -> Option<&'static str> {
+  let authority = url.split_once("://")?.1.split(['/', '?', '#']).next()?;
+  if authority.contains('@') {
+    return Some("credentials before the host");
+  }
+  let host = host_of(url)?;
+  if host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok() {
+    return Some("bare IP address host");
+  }
+  if host.split('.').any(|label| label.starts_with("xn--")) {
+    return Some("punycode host");
+  }
+  if SHORTENERS.contains(&host.as_str()) {
+    return Some("URL shortener");
+  }
+  None
+}
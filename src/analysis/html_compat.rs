@@ -0,0 +1,229 @@
+//! HTML/CSS compatibility check against the bundled client database.
+//!
+//! This is a text scan, not a browser: CSS features are only looked for in
+//! `<style>` blocks and `style` attributes, and every hit is reported with
+//! the line it starts on.
+
+use super::compat_db::{FEATURES, Matcher};
+use crate::models::analysis::compat_report::{Client, ClientReport, CompatIssue, Support};
+use std::ops::Range;
+
+/// Check an HTML body and group the problems by client family.
+pub fn check(html: &str) -> Vec<ClientReport> {
+  let scan = Scan::new(html);
+  let found: Vec<_> = FEATURES
+    .iter()
+    .filter_map(|f| {
+      let mut lines: Vec<usize> = scan
+        .hits(f.matcher)
+        .into_iter()
+        .map(|o| scan.line_of(o))
+        .collect();
+      lines.sort_unstable();
+      lines.dedup();
+      (!lines.is_empty()).then_some((f, lines))
+    })
+    .collect();
+
+  Client::ALL
+    .into_iter()
+    .map(|client| {
+      let issues: Vec<CompatIssue> = found
+        .iter()
+        .filter(|(f, _)| f.support(client) != Support::Supported)
+        .map(|(f, lines)| CompatIssue {
+          feature: f.id,
+          title: f.title,
+          support: f.support(client),
+          note: f.note,
+          lines: lines.clone(),
+        })
+        .collect();
+      ClientReport {
+        client,
+        label: client.label(),
+        unsupported: issues
+          .iter()
+          .filter(|i| i.support == Support::Unsupported)
+          .count(),
+        partial: issues
+          .iter()
+          .filter(|i| i.support == Support::Partial)
+          .count(),
+        issues,
+      }
+    })
+    .collect()
+}
+
+struct Scan {
+  /// ASCII-lowercased copy; byte offsets match the original.
+  lower: String,
+  css: Vec<Range<usize>>,
+  line_starts: Vec<usize>,
+}
+
+impl Scan {
+  fn new(html: &str) -> Self {
+    let lower = html.to_ascii_lowercase();
+    let line_starts = std::iter::once(0)
+      .chain(lower.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+    let css = css_regions(&lower);
+    Scan {
+      lower,
+      css,
+      line_starts,
+    }
+  }
+
+  fn line_of(&self, offset: usize) -> usize {
+    self.line_starts.partition_point(|&start| start <= offset)
+  }
+
+  fn in_css(&self, offset: usize) -> bool {
+    self.css.iter().any(|r| r.contains(&offset))
+  }
+
+  fn in_tag(&self, offset: usize) -> bool {
+    let before = &self.lower[..offset];
+    match (before.rfind('<'), before.rfind('>')) {
+      (Some(lt), Some(gt)) => lt > gt,
+      (Some(_), None) => true,
+      _ => false,
+    }
+  }
+
+  fn hits(&self, matcher: Matcher) -> Vec<usize> {
+    let bytes = self.lower.as_bytes();
+    match matcher {
+      Matcher::Element(name) => self
+        .lower
+        .match_indices(&format!("<{name}"))
+        .map(|(i, _)| i)
+        .filter(|&i| {
+          bytes
+            .get(i + name.len() + 1)
+            .is_none_or(|b| !b.is_ascii_alphanumeric() && *b != b'-')
+        })
+        .collect(),
+      Matcher::Attribute(name) => self
+        .lower
+        .match_indices(name)
+        .map(|(i, _)| i)
+        .filter(|&i| i > 0 && bytes[i - 1].is_ascii_whitespace())
+        .filter(|&i| next_non_ws(bytes, i + name.len()) == Some(b'='))
+        .filter(|&i| self.in_tag(i))
+        .collect(),
+      Matcher::CssProperty(name) => self.declarations(name).map(|(i, _)| i).collect(),
+      Matcher::CssValue(name, value) => self
+        .declarations(name)
+        .filter(|&(_, value_at)| self.lower[value_at..].starts_with(value))
+        .map(|(i, _)| i)
+        .collect(),
+      Matcher::CssToken(token) => self
+        .lower
+        .match_indices(token)
+        .map(|(i, _)| i)
+        .filter(|&i| self.in_css(i))
+        .collect(),
+      Matcher::ImageFormat(ext) => self
+        .image_refs()
+        .filter(|(_, url)| url.ends_with(&format!(".{ext}")))
+        .map(|(i, _)| i)
+        .collect(),
+    }
+  }
+
+  /// Declarations of a CSS property as (offset of the name, offset of the value).
+  fn declarations<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    let bytes = self.lower.as_bytes();
+    self
+      .lower
+      .match_indices(name)
+      .map(|(i, _)| i)
+      .filter(move |&i| self.in_css(i))
+      .filter(move |&i| {
+        i == 0
+          || matches!(bytes[i - 1], b';' | b'{' | b'"' | b'\'')
+          || bytes[i - 1].is_ascii_whitespace()
+      })
+      .filter_map(move |i| {
+        let colon = skip_ws(bytes, i + name.len());
+        (bytes.get(colon) == Some(&b':')).then(|| (i, skip_ws(bytes, colon + 1)))
+      })
+  }
+
+  /// URLs in `src` attributes and CSS `url()`, without query or fragment.
+  fn image_refs(&self) -> impl Iterator<Item = (usize, &str)> {
+    let bytes = self.lower.as_bytes();
+    let src = self
+      .lower
+      .match_indices("src=")
+      .map(|(i, _)| i)
+      .filter(move |&i| i > 0 && bytes[i - 1].is_ascii_whitespace() && self.in_tag(i))
+      .map(|i| (i, i + 4));
+    let url = self.lower.match_indices("url(").map(|(i, _)| (i, i + 4));
+    src.chain(url).map(move |(i, start)| {
+      let start = skip_ws(bytes, start);
+      let start = if matches!(bytes.get(start), Some(b'"' | b'\'')) {
+        start + 1
+      } else {
+        start
+      };
+      let rest = &self.lower[start..];
+      let end = rest
+        .find(|c: char| matches!(c, '"' | '\'' | ')' | '>' | '?' | '#') || c.is_ascii_whitespace())
+        .unwrap_or(rest.len());
+      (i, &rest[..end])
+    })
+  }
+}
+
+fn skip_ws(bytes: &[u8], mut i: usize) -> usize {
+  while bytes.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+    i += 1;
+  }
+  i
+}
+
+fn next_non_ws(bytes: &[u8], i: usize) -> Option<u8> {
+  bytes.get(skip_ws(bytes, i)).copied()
+}
+
+/// Byte ranges holding CSS: `<style>` contents and `style` attribute values.
+fn css_regions(lower: &str) -> Vec<Range<usize>> {
+  let bytes = lower.as_bytes();
+  let mut out = Vec::new();
+  let mut pos = 0;
+  while let Some(at) = lower[pos..].find("<style").map(|i| i + pos) {
+    let Some(open_end) = lower[at..].find('>').map(|i| at + i + 1) else {
+      break;
+    };
+    let close = lower[open_end..]
+      .find("</style")
+      .map_or(lower.len(), |i| open_end + i);
+    out.push(open_end..close);
+    pos = close;
+    if pos >= lower.len() {
+      break;
+    }
+  }
+  for (i, _) in lower.match_indices("style") {
+    if i == 0 || !bytes[i - 1].is_ascii_whitespace() {
+      continue;
+    }
+    let eq = skip_ws(bytes, i + 5);
+    if bytes.get(eq) != Some(&b'=') {
+      continue;
+    }
+    let q = skip_ws(bytes, eq + 1);
+    if let Some(&quote @ (b'"' | b'\'')) = bytes.get(q) {
+      let end = lower[q + 1..]
+        .find(quote as char)
+        .map_or(lower.len(), |e| q + 1 + e);
+      out.push(q + 1..end);
+    }
+  }
+  out
+}
@@ -0,0 +1,274 @@
+//! URL and anchor extraction from message bodies, and link checking.
+
+use crate::{
+  models::analysis::message_link::{LinkCheck, LinkSource, MessageLink},
+  util::{find_ascii_ci, percent_decode},
+};
+use reqwest::{StatusCode, redirect::Policy};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An `<a href>` with its visible text.
+#[derive(Debug, Clone)]
+pub struct Anchor {
+  pub href: String,
+  pub text: String,
+}
+
+/// Every link in a message, merged by URL in order of first appearance.
+pub fn extract(
+  text: Option<&str>,
+  html: Option<&str>,
+  list_unsubscribe: Option<&str>,
+) -> Vec<MessageLink> {
+  let mut out: Vec<MessageLink> = Vec::new();
+  let mut add = |url: String, source: LinkSource, anchor: Option<String>| {
+    if let Some(link) = out.iter_mut().find(|l| l.url == url) {
+      if !link.sources.contains(&source) {
+        link.sources.push(source);
+      }
+      if link.text.is_none() {
+        link.text = anchor;
+      }
+      return;
+    }
+    out.push(MessageLink {
+      redirect_target: redirect_target(&url),
+      url,
+      sources: vec![source],
+      text: anchor,
+      check: None,
+    });
+  };
+
+  if let Some(html) = html {
+    for a in anchors(html) {
+      let lower = a.href.to_ascii_lowercase();
+      if lower.starts_with("http://") || lower.starts_with("https://") {
+        add(
+          a.href,
+          LinkSource::Html,
+          Some(a.text).filter(|t| !t.is_empty()),
+        );
+      }
+    }
+    for url in find_urls(html) {
+      add(url, LinkSource::Html, None);
+    }
+  }
+  for url in find_urls(text.unwrap_or_default()) {
+    add(url, LinkSource::Text, None);
+  }
+  for value in list_unsubscribe.unwrap_or_default().split(',') {
+    let url = value
+      .trim()
+      .trim_start_matches('<')
+      .trim_end_matches('>')
+      .trim();
+    if !url.is_empty() {
+      add(url.to_string(), LinkSource::ListUnsubscribe, None);
+    }
+  }
+  out
+}
+
+/// A URL carried in the query string, as used by click-tracking redirects.
+fn redirect_target(url: &str) -> Option<String> {
+  let query = url.split_once('?')?.1.split('#').next()?;
+  query.split('&').find_map(|pair| {
+    let value = percent_decode(pair.split_once('=')?.1);
+    let lower = value.to_ascii_lowercase();
+    (lower.starts_with("http://") || lower.starts_with("https://")).then_some(value)
+  })
+}
+
+/// Request every http(s) link concurrently. HEAD is tried first and GET is
+/// used when the server does not allow HEAD.
+pub async fn check_all(links: &mut [MessageLink]) {
+  let client = match reqwest::Client::builder()
+    .redirect(Policy::none())
+    .timeout(CHECK_TIMEOUT)
+    .build()
+  {
+    Ok(c) => c,
+    Err(e) => {
+      tracing::error!("link check client error: {e}");
+      return;
+    }
+  };
+  let mut tasks = JoinSet::new();
+  for (i, link) in links.iter().enumerate() {
+    let lower = link.url.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+      let client = client.clone();
+      let url = link.url.clone();
+      tasks.spawn(async move { (i, check(&client, &url).await) });
+    }
+  }
+  while let Some(done) = tasks.join_next().await {
+    if let Ok((i, result)) = done {
+      links[i].check = Some(result);
+    }
+  }
+}
+
+async fn check(client: &reqwest::Client, url: &str) -> LinkCheck {
+  let mut method = "HEAD";
+  let mut res = client.head(url).send().await;
+  if let Ok(r) = &res {
+    if matches!(
+      r.status(),
+      StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+      method = "GET";
+      res = client.get(url).send().await;
+    }
+  }
+  match res {
+    Ok(r) => LinkCheck {
+      method,
+      status: Some(r.status().as_u16()),
+      location: r
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string),
+      error: None,
+    },
+    Err(e) => LinkCheck {
+      method,
+      status: None,
+      location: None,
+      error: Some(e.to_string()),
+    },
+  }
+}
+
+/// Absolute http(s) URLs in text, in order of first appearance.
+pub fn find_urls(s: &str) -> Vec<String> {
+  let mut out: Vec<String> = Vec::new();
+  let mut pos = 0;
+  while let Some(start) = find_ascii_ci(&s[pos..], "http").map(|i| pos + i) {
+    let rest = &s[start..];
+    let scheme_len = if rest
+      .get(..8)
+      .is_some_and(|p| p.eq_ignore_ascii_case("https://"))
+    {
+      8
+    } else if rest
+      .get(..7)
+      .is_some_and(|p| p.eq_ignore_ascii_case("http://"))
+    {
+      7
+    } else {
+      pos = start + 4;
+      continue;
+    };
+    let end = rest
+      .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '`'))
+      .unwrap_or(rest.len());
+    let url = rest[..end]
+      .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']'])
+      .replace("&amp;", "&");
+    if url.len() > scheme_len && !out.contains(&url) {
+      out.push(url);
+    }
+    pos = start + end.max(scheme_len);
+  }
+  out
+}
+
+/// Anchors in an HTML body; text is tag-stripped and whitespace-collapsed.
+pub fn anchors(html: &str) -> Vec<Anchor> {
+  let mut out = Vec::new();
+  let mut pos = 0;
+  while let Some(open) = find_ascii_ci(&html[pos..], "<a").map(|i| pos + i) {
+    pos = open + 2;
+    if !html[pos..].starts_with(|c: char| c.is_ascii_whitespace()) {
+      continue;
+    }
+    let Some(tag_end) = html[pos..].find('>').map(|i| pos + i) else {
+      break;
+    };
+    let Some(href) = attribute(&html[pos..tag_end], "href") else {
+      continue;
+    };
+    let close = find_ascii_ci(&html[tag_end..], "</a").map_or(html.len(), |i| tag_end + i);
+    out.push(Anchor {
+      href: href.replace("&amp;", "&"),
+      text: visible_text(&html[tag_end + 1..close]),
+    });
+    pos = close;
+  }
+  out
+}
+
+/// Value of an attribute inside an opening tag's source.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+  let mut pos = 0;
+  while let Some(at) = find_ascii_ci(&tag[pos..], name).map(|i| pos + i) {
+    pos = at + name.len();
+    let preceded = at == 0 || tag.as_bytes()[at - 1].is_ascii_whitespace();
+    let rest = tag[pos..].trim_start();
+    let (true, Some(rest)) = (preceded, rest.strip_prefix('=')) else {
+      continue;
+    };
+    let rest = rest.trim_start();
+    let value = match rest.chars().next() {
+      Some(q @ ('"' | '\'')) => rest[1..].split(q).next().unwrap_or_default(),
+      _ => rest
+        .split(|c: char| c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default(),
+    };
+    return Some(value.trim().to_string());
+  }
+  None
+}
+
+/// Text content of an HTML fragment: tags, comments, styles and scripts removed.
+pub fn visible_text(html: &str) -> String {
+  let mut text = String::new();
+  let mut rest = html;
+  while let Some(lt) = rest.find('<') {
+    text.push_str(&rest[..lt]);
+    rest = &rest[lt..];
+    if let Some(after) = rest.strip_prefix("<!--") {
+      rest = after.find("-->").map_or("", |i| &after[i + 3..]);
+    } else {
+      for (open, end) in [("<style", "</style"), ("<script", "</script")] {
+        if rest
+          .get(..open.len())
+          .is_some_and(|p| p.eq_ignore_ascii_case(open))
+        {
+          rest = find_ascii_ci(rest, end).map_or("", |i| &rest[i..]);
+          break;
+        }
+      }
+      rest = rest.find('>').map_or("", |gt| &rest[gt + 1..]);
+    }
+    text.push(' ');
+  }
+  text.push_str(rest);
+  let text = text
+    .replace("&nbsp;", " ")
+    .replace("&amp;", "&")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">");
+  text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercased host of an http(s) URL, without userinfo or port.
+pub fn host_of(url: &str) -> Option<String> {
+  let (_, rest) = url.split_once("://")?;
+  let authority = rest.split(['/', '?', '#']).next()?;
+  let host = authority.rsplit('@').next()?;
+  let host = if host.starts_with('[') {
+    host.split(']').next().map(|h| format!("{h}]"))?
+  } else {
+    host.split(':').next()?.to_string()
+  };
+  (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
@@ -0,0 +1,7 @@
+//! Offline analyzers that run over stored messages.
+
+pub mod codes;
+pub mod compat_db;
+pub mod deliverability;
+pub mod html_compat;
+pub mod links;
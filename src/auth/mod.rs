@@ -0,0 +1,285 @@
+//! Optional API tokens and dashboard login.
+//!
+//! Off unless `FAUXMAIL_API_TOKENS` or `FAUXMAIL_DASHBOARD_USER` and
+//! `FAUXMAIL_DASHBOARD_PASS` are set. Every route except `/healthz` and
+//! `/readyz` then needs `Authorization: Bearer <token>` or basic auth whose
+//! scopes cover it: `read` for lookups, `send` for the ingest endpoints and
+//! `admin` for deletes and token management. `admin` implies the others.
+//!
+//! Browsers replay cached basic auth on any request to the origin, so writes
+//! carrying it are refused when they come from another site.
+
+use crate::app::AppState;
+use axum::{
+  extract::{Request, State},
+  http::{HeaderMap, Method, StatusCode, header},
+  middleware::Next,
+  response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as B64};
+use sha2::{Digest, Sha256};
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+  Read,
+  Send,
+  Admin,
+}
+
+impl Scope {
+  pub fn parse(s: &str) -> Option<Self> {
+    match s.trim().to_ascii_lowercase().as_str() {
+      "read" => Some(Scope::Read),
+      "send" => Some(Scope::Send),
+      "admin" => Some(Scope::Admin),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Scope::Read => "read",
+      Scope::Send => "send",
+      Scope::Admin => "admin",
+    }
+  }
+}
+
+/// Scopes separated by commas or spaces, e.g. `read,send`.
+pub fn parse_scopes(s: &str) -> Result<Vec<Scope>, String> {
+  let mut scopes = Vec::new();
+  for name in s.split([',', ' ']).filter(|n| !n.trim().is_empty()) {
+    let scope = Scope::parse(name).ok_or_else(|| format!("unknown scope {name:?}"))?;
+    if !scopes.contains(&scope) {
+      scopes.push(scope);
+    }
+  }
+  if scopes.is_empty() {
+    return Err("no scopes given".into());
+  }
+  Ok(scopes)
+}
+
+/// Whether `granted` covers `needed`.
+pub fn allows(granted: &[Scope], needed: Scope) -> bool {
+  granted.iter().any(|s| *s == needed || *s == Scope::Admin)
+}
+
+/// Hex SHA-256 of a token; only this is stored or compared.
+pub fn hash_token(token: &str) -> String {
+  format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// A fresh random secret for `POST /tokens`.
+pub fn generate_token() -> String {
+  format!("fm_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+#[derive(Debug)]
+struct DashboardLogin {
+  user: String,
+  pass_sha256: String,
+  scopes: Vec<Scope>,
+}
+
+/// Static credentials from the environment. Tokens managed through
+/// `/tokens` live in the store and are honoured whenever this is enabled.
+#[derive(Debug, Default)]
+pub struct AuthConfig {
+  /// Hash of each static token with its scopes.
+  tokens: Vec<(String, Vec<Scope>)>,
+  dashboard: Option<DashboardLogin>,
+}
+
+impl AuthConfig {
+  /// `FAUXMAIL_API_TOKENS` (`token=scopes` entries separated by `;`), and
+  /// `FAUXMAIL_DASHBOARD_USER`/`_PASS` with `FAUXMAIL_DASHBOARD_SCOPES`
+  /// (default `admin`). Malformed values are startup errors so a typo never
+  /// leaves the API open.
+  pub fn from_env() -> Result<Self, String> {
+    let var = |name: &str| {
+      std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    };
+    let mut config = AuthConfig::default();
+    if let Some(tokens) = var("FAUXMAIL_API_TOKENS") {
+      for entry in tokens.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let (token, scopes) = entry
+          .split_once('=')
+          .ok_or("FAUXMAIL_API_TOKENS entries must look like token=scope,scope")?;
+        if token.trim().is_empty() {
+          return Err("FAUXMAIL_API_TOKENS has an entry without a token".into());
+        }
+        let scopes = parse_scopes(scopes).map_err(|e| format!("FAUXMAIL_API_TOKENS: {e}"))?;
+        config = config.with_token(token.trim(), &scopes);
+      }
+    }
+    match (
+      var("FAUXMAIL_DASHBOARD_USER"),
+      var("FAUXMAIL_DASHBOARD_PASS"),
+    ) {
+      (Some(user), Some(pass)) => {
+        let scopes = match var("FAUXMAIL_DASHBOARD_SCOPES") {
+          Some(s) => parse_scopes(&s).map_err(|e| format!("FAUXMAIL_DASHBOARD_SCOPES: {e}"))?,
+          None => vec![Scope::Admin],
+        };
+        config = config.with_dashboard_login(&user, &pass, &scopes);
+      }
+      (None, None) => {}
+      _ => return Err("set both FAUXMAIL_DASHBOARD_USER and FAUXMAIL_DASHBOARD_PASS".into()),
+    }
+    Ok(config)
+  }
+
+  /// Accept `token` as a bearer token with these scopes.
+  pub fn with_token(mut self, token: &str, scopes: &[Scope]) -> Self {
+    self.tokens.push((hash_token(token), scopes.to_vec()));
+    self
+  }
+
+  /// Accept basic auth with this user and password.
+  pub fn with_dashboard_login(mut self, user: &str, pass: &str, scopes: &[Scope]) -> Self {
+    self.dashboard = Some(DashboardLogin {
+      user: user.to_string(),
+      pass_sha256: hash_token(pass),
+      scopes: scopes.to_vec(),
+    });
+    self
+  }
+
+  pub fn enabled(&self) -> bool {
+    !self.tokens.is_empty() || self.dashboard.is_some()
+  }
+}
+
+/// Scope a request needs, or `None` for the open probe endpoints.
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+  match path {
+    "/healthz" | "/readyz" => None,
+    "/tokens" => Some(Scope::Admin),
+    _ if path.starts_with("/tokens/") || method == Method::DELETE => Some(Scope::Admin),
+    "/send" | "/send/raw" | "/import" => Some(Scope::Send),
+    _ => Some(Scope::Read),
+  }
+}
+
+/// Scopes granted by the request's `Authorization` header, if it is valid.
+async fn granted_scopes(
+  state: &AppState,
+  headers: &HeaderMap,
+) -> Result<Option<Vec<Scope>>, sqlx::Error> {
+  let Some(value) = headers
+    .get(header::AUTHORIZATION)
+    .and_then(|v| v.to_str().ok())
+  else {
+    return Ok(None);
+  };
+  let (kind, credentials) = value.trim().split_once(' ').unwrap_or((value, ""));
+  let credentials = credentials.trim();
+  if credentials.is_empty() {
+    return Ok(None);
+  }
+  if kind.eq_ignore_ascii_case("bearer") {
+    let sha = hash_token(credentials);
+    if let Some((_, scopes)) = state.auth.tokens.iter().find(|(t, _)| *t == sha) {
+      return Ok(Some(scopes.clone()));
+    }
+    let managed = state.store.token_by_hash(&sha).await?;
+    return Ok(managed.map(|t| {
+      t.scopes
+        .split_whitespace()
+        .filter_map(Scope::parse)
+        .collect()
+    }));
+  }
+  if kind.eq_ignore_ascii_case("basic") {
+    let Some(login) = &state.auth.dashboard else {
+      return Ok(None);
+    };
+    let decoded = B64
+      .decode(credentials)
+      .ok()
+      .and_then(|d| String::from_utf8(d).ok());
+    let Some((user, pass)) = decoded.as_deref().and_then(|d| d.split_once(':')) else {
+      return Ok(None);
+    };
+    if user == login.user && hash_token(pass) == login.pass_sha256 {
+      return Ok(Some(login.scopes.clone()));
+    }
+  }
+  Ok(None)
+}
+
+/// Whether a browser sent this request on behalf of another site, judged by
+/// `Sec-Fetch-Site` or, from older browsers, `Origin` against `Host`.
+fn cross_site(headers: &HeaderMap) -> bool {
+  let get = |name| headers.get(name).and_then(|v| v.to_str().ok());
+  if let Some(site) = get(header::HeaderName::from_static("sec-fetch-site")) {
+    return !matches!(site, "same-origin" | "none");
+  }
+  let Some(origin) = get(header::ORIGIN) else {
+    return false;
+  };
+  let origin_host = origin.split_once("://").map(|(_, host)| host);
+  match (origin_host, get(header::HOST)) {
+    (Some(origin), Some(host)) => !origin.eq_ignore_ascii_case(host),
+    _ => true,
+  }
+}
+
+/// Basic auth on a state-changing request from another site.
+fn forged_basic_auth(req: &Request) -> bool {
+  let basic = req
+    .headers()
+    .get(header::AUTHORIZATION)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.trim().get(..6))
+    .is_some_and(|kind| kind.eq_ignore_ascii_case("basic "));
+  basic && !req.method().is_safe() && cross_site(req.headers())
+}
+
+/// Middleware enforcing [`AuthConfig`] on every route.
+pub async fn authorize(State(state): State<AppState>, req: Request, next: Next) -> Response {
+  if !state.auth.enabled() {
+    return next.run(req).await;
+  }
+  let Some(needed) = required_scope(req.method(), req.uri().path()) else {
+    return next.run(req).await;
+  };
+  if forged_basic_auth(&req) {
+    return (StatusCode::FORBIDDEN, "cross-site request rejected").into_response();
+  }
+  let granted = match granted_scopes(&state, req.headers()).await {
+    Ok(Some(granted)) => granted,
+    Ok(None) => {
+      // Browsers only show a login prompt for a Basic challenge.
+      let challenge = if state.auth.dashboard.is_some() {
+        "Basic realm=\"fauxmail\""
+      } else {
+        "Bearer realm=\"fauxmail\""
+      };
+      return (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, challenge)],
+        "authentication required",
+      )
+        .into_response();
+    }
+    Err(e) => {
+      error!("authorize error: {e}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+    }
+  };
+  if !allows(&granted, needed) {
+    return (
+      StatusCode::FORBIDDEN,
+      format!("{} scope required", needed.as_str()),
+    )
+      .into_response();
+  }
+  next.run(req).await
+}
@@ -0,0 +1,226 @@
+//! Content-addressed storage for attachment bodies and raw sources.
+//!
+//! Enabled by `FAUXMAIL_BLOB_DIR`; bodies are written once per SHA-256 under
+//! `<dir>/<first two hex digits>/<hash>` and the database keeps only the
+//! hash. `FAUXMAIL_BLOB_RAW=true` stores raw message sources there as well.
+
+use crate::{app::AppState, models::email::new_message::NewMessage};
+use sha2::{Digest, Sha256};
+use std::{
+  collections::HashSet,
+  io,
+  path::PathBuf,
+  time::{Duration, SystemTime},
+};
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// Files younger than this are never pruned: their message may still be
+/// on its way into the database.
+pub const PRUNE_GRACE: Duration = Duration::from_secs(600);
+
+/// A stored body: inline bytes from the database or a blob reference.
+#[derive(Debug, Clone)]
+pub enum StoredBody {
+  Bytes(Vec<u8>),
+  Blob(String),
+}
+
+impl StoredBody {
+  /// The blob when a hash is recorded, otherwise the inline content.
+  pub fn new(content: Vec<u8>, sha256: Option<String>) -> Self {
+    match sha256 {
+      Some(sha) => StoredBody::Blob(sha),
+      None => StoredBody::Bytes(content),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+  dir: PathBuf,
+  /// Also keep raw message sources out of the database.
+  pub raw_sources: bool,
+}
+
+impl BlobStore {
+  pub fn new(dir: impl Into<PathBuf>, raw_sources: bool) -> Self {
+    BlobStore {
+      dir: dir.into(),
+      raw_sources,
+    }
+  }
+
+  /// Store from `FAUXMAIL_BLOB_DIR` / `FAUXMAIL_BLOB_RAW`; `None` when unset.
+  pub fn from_env() -> Option<Self> {
+    let dir = std::env::var("FAUXMAIL_BLOB_DIR")
+      .ok()
+      .filter(|d| !d.trim().is_empty())?;
+    let raw = std::env::var("FAUXMAIL_BLOB_RAW")
+      .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+      .unwrap_or(false);
+    Some(BlobStore::new(dir, raw))
+  }
+
+  /// Where a blob lives; `None` for anything that is not a SHA-256 hex digest.
+  pub fn path(&self, sha256: &str) -> Option<PathBuf> {
+    let valid = sha256.len() == 64
+      && sha256
+        .bytes()
+        .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    valid.then(|| self.dir.join(&sha256[..2]).join(sha256))
+  }
+
+  /// Write `data` unless an identical blob exists; returns its hash.
+  pub async fn put(&self, data: &[u8]) -> io::Result<String> {
+    Ok(self.write(data).await?.0)
+  }
+
+  /// Like [`BlobStore::put`], also returning the modification time of a
+  /// newly created file so [`BlobStore::discard`] can undo the write.
+  async fn write(&self, data: &[u8]) -> io::Result<(String, Option<SystemTime>)> {
+    let sha = format!("{:x}", Sha256::digest(data));
+    let path = self.path(&sha).expect("sha256 hex digest");
+    if tokio::fs::try_exists(&path).await? {
+      // Refresh the age so a concurrent prune (or discard) leaves it alone.
+      let file = std::fs::File::open(&path)?;
+      file.set_modified(SystemTime::now())?;
+      return Ok((sha, None));
+    }
+    let parent = path.parent().expect("blob path has a parent");
+    tokio::fs::create_dir_all(parent).await?;
+    let tmp = parent.join(format!(".tmp-{}", Uuid::new_v4()));
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+    let modified = tokio::fs::metadata(&path).await?.modified()?;
+    Ok((sha, Some(modified)))
+  }
+
+  /// Remove blobs written by a failed ingest. A blob whose age was refreshed
+  /// since is in use by another message and stays.
+  pub async fn discard(&self, created: &[(String, SystemTime)]) {
+    for (sha, written) in created {
+      let Some(path) = self.path(sha) else {
+        continue;
+      };
+      let unchanged = tokio::fs::metadata(&path)
+        .await
+        .and_then(|m| m.modified())
+        .is_ok_and(|m| m == *written);
+      if unchanged {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+          error!("blob discard error: {sha}: {e}");
+        }
+      }
+    }
+  }
+
+  pub async fn read(&self, sha256: &str) -> io::Result<Vec<u8>> {
+    match self.path(sha256) {
+      Some(path) => tokio::fs::read(path).await,
+      None => Err(io::Error::new(io::ErrorKind::InvalidInput, "bad blob hash")),
+    }
+  }
+
+  /// Remove blobs not in `referenced` that are older than `min_age`.
+  pub async fn prune(&self, referenced: &HashSet<String>, min_age: Duration) -> io::Result<u64> {
+    let mut removed = 0;
+    let Ok(mut shards) = tokio::fs::read_dir(&self.dir).await else {
+      return Ok(0);
+    };
+    while let Some(shard) = shards.next_entry().await? {
+      if !shard.file_type().await?.is_dir() {
+        continue;
+      }
+      let mut files = tokio::fs::read_dir(shard.path()).await?;
+      while let Some(file) = files.next_entry().await? {
+        let name = file.file_name().to_string_lossy().into_owned();
+        if referenced.contains(&name) {
+          continue;
+        }
+        let age = file
+          .metadata()
+          .await?
+          .modified()?
+          .elapsed()
+          .unwrap_or_default();
+        if age >= min_age {
+          tokio::fs::remove_file(file.path()).await?;
+          removed += 1;
+        }
+      }
+    }
+    Ok(removed)
+  }
+}
+
+/// Move attachment bodies (and the raw source, if configured) of a message
+/// about to be stored into the blob store. MIME parts carrying the same bytes
+/// as an attachment reference its blob instead of keeping a second copy.
+///
+/// Returns the blobs this call created, to [`BlobStore::discard`] if the
+/// message is not stored after all.
+pub async fn offload(
+  blobs: &BlobStore,
+  msg: &mut NewMessage,
+) -> io::Result<Vec<(String, SystemTime)>> {
+  let mut created = Vec::new();
+  let result = offload_into(blobs, msg, &mut created).await;
+  if result.is_err() {
+    blobs.discard(&created).await;
+  }
+  result.map(|()| created)
+}
+
+async fn offload_into(
+  blobs: &BlobStore,
+  msg: &mut NewMessage,
+  created: &mut Vec<(String, SystemTime)>,
+) -> io::Result<()> {
+  let mut put = async |data: &[u8]| -> io::Result<String> {
+    let (sha, written) = blobs.write(data).await?;
+    if let Some(written) = written {
+      created.push((sha.clone(), written));
+    }
+    Ok(sha)
+  };
+  let mut stored = HashSet::new();
+  for a in &mut msg.attachments {
+    let sha = put(&a.data).await?;
+    stored.insert(sha.clone());
+    a.sha256 = Some(sha);
+  }
+  for p in &mut msg.parts {
+    if let Some(body) = &p.body {
+      let sha = format!("{:x}", Sha256::digest(body));
+      if stored.contains(&sha) {
+        p.sha256 = Some(sha);
+      }
+    }
+  }
+  if blobs.raw_sources {
+    if let Some(raw) = &msg.raw {
+      msg.raw_sha256 = Some(put(raw).await?);
+    }
+  }
+  Ok(())
+}
+
+/// Delete blobs no stored message refers to any more.
+pub async fn prune_unreferenced(state: &AppState) {
+  let Some(blobs) = &state.blobs else {
+    return;
+  };
+  let referenced = match state.store.blob_refs().await {
+    Ok(refs) => refs,
+    Err(e) => {
+      error!("blob prune error: {e}");
+      return;
+    }
+  };
+  match blobs.prune(&referenced, PRUNE_GRACE).await {
+    Ok(0) => {}
+    Ok(n) => info!("pruned {n} unreferenced blobs"),
+    Err(e) => error!("blob prune error: {e}"),
+  }
+}
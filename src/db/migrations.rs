@@ -0,0 +1,377 @@
+//! Ordered schema migrations for both SQL backends.
+//!
+//! Each migration runs once, in its own transaction, and is recorded in
+//! `schema_version`. Databases created before versioning existed already
+//! hold some of these tables and columns, so every step is idempotent.
+
+/// One change to the schema.
+pub enum Step {
+  Sql(&'static str),
+  /// `ALTER TABLE .. ADD COLUMN`, skipped when the column already exists.
+  AddColumn {
+    table: &'static str,
+    column: &'static str,
+    decl: &'static str,
+  },
+}
+
+pub struct Migration {
+  pub version: i64,
+  pub name: &'static str,
+  pub steps: &'static [Step],
+}
+
+const fn add(table: &'static str, column: &'static str, decl: &'static str) -> Step {
+  Step::AddColumn {
+    table,
+    column,
+    decl,
+  }
+}
+
+pub const SQLITE: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "initial schema",
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS messages (
+            id TEXT PRIMARY KEY,
+            received_at TEXT NOT NULL,
+            from_addr TEXT NULL,
+            to_recipients TEXT NOT NULL,
+            subject TEXT NULL,
+            text_body TEXT NULL,
+            html_body TEXT NULL,
+            headers_json TEXT NULL,
+            raw_len INTEGER NOT NULL
+        )"#,
+      ),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS logs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            ts TEXT NOT NULL,
+            level TEXT NOT NULL,
+            message TEXT NOT NULL
+        )"#,
+      ),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS attachments (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL,
+            filename TEXT NULL,
+            content_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            content BLOB NOT NULL
+        )"#,
+      ),
+    ],
+  },
+  Migration {
+    version: 2,
+    name: "smtp session transcripts",
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS smtp_sessions (
+            id TEXT PRIMARY KEY,
+            started_at TEXT NOT NULL,
+            ended_at TEXT NULL,
+            client_addr TEXT NOT NULL,
+            helo TEXT NULL,
+            auth_user TEXT NULL,
+            transcript_json TEXT NOT NULL
+        )"#,
+      ),
+      add("messages", "session_id", "TEXT NULL"),
+    ],
+  },
+  Migration {
+    version: 3,
+    name: "smtp envelope",
+    steps: &[
+      add("messages", "envelope_from", "TEXT NULL"),
+      add("messages", "envelope_to", "TEXT NULL"),
+      add("messages", "helo", "TEXT NULL"),
+      add("messages", "client_addr", "TEXT NULL"),
+    ],
+  },
+  Migration {
+    version: 4,
+    name: "parsed recipients",
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS recipients (
+            message_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            name TEXT NULL,
+            address TEXT NOT NULL
+        )"#,
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS recipients_message_id ON recipients (message_id)"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS recipients_address ON recipients (address)"),
+    ],
+  },
+  Migration {
+    version: 5,
+    name: "mime parts and inline attachments",
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS parts (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL,
+            seq INTEGER NOT NULL,
+            path TEXT NOT NULL,
+            parent_path TEXT NULL,
+            content_type TEXT NOT NULL,
+            charset TEXT NULL,
+            transfer_encoding TEXT NULL,
+            content_id TEXT NULL,
+            disposition TEXT NULL,
+            filename TEXT NULL,
+            headers_json TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            content BLOB NULL
+        )"#,
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS parts_message_id ON parts (message_id)"),
+      add("attachments", "content_id", "TEXT NULL"),
+      add("attachments", "inline", "INTEGER NOT NULL DEFAULT 0"),
+    ],
+  },
+  Migration {
+    version: 6,
+    name: "raw source",
+    steps: &[add("messages", "raw_source", "BLOB NULL")],
+  },
+  Migration {
+    version: 7,
+    name: "threading headers",
+    steps: &[
+      add("messages", "message_id_header", "TEXT NULL"),
+      add("messages", "in_reply_to", "TEXT NULL"),
+      add("messages", "references_json", "TEXT NULL"),
+      add("messages", "thread_id", "TEXT NULL"),
+      Step::Sql(
+        "CREATE INDEX IF NOT EXISTS messages_message_id_header ON messages (message_id_header)",
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS messages_thread_id ON messages (thread_id)"),
+    ],
+  },
+  Migration {
+    version: 8,
+    name: "list and lookup indexes",
+    steps: &[
+      Step::Sql("CREATE INDEX IF NOT EXISTS messages_received_at ON messages (received_at)"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS attachments_message_id ON attachments (message_id)"),
+    ],
+  },
+  Migration {
+    version: 9,
+    name: "attachments cascade with their message",
+    // SQLite cannot add a constraint in place; rebuild the table, dropping
+    // attachments whose message is already gone.
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE attachments_new (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL REFERENCES messages (id) ON DELETE CASCADE,
+            filename TEXT NULL,
+            content_type TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            content BLOB NOT NULL,
+            content_id TEXT NULL,
+            inline INTEGER NOT NULL DEFAULT 0
+        )"#,
+      ),
+      Step::Sql(
+        "INSERT INTO attachments_new (id, message_id, filename, content_type, size, content, content_id, inline) SELECT id, message_id, filename, content_type, size, content, content_id, inline FROM attachments WHERE message_id IN (SELECT id FROM messages)",
+      ),
+      Step::Sql("DROP TABLE attachments"),
+      Step::Sql("ALTER TABLE attachments_new RENAME TO attachments"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS attachments_message_id ON attachments (message_id)"),
+    ],
+  },
+  Migration {
+    version: 10,
+    name: "blob store references",
+    steps: &[
+      add("attachments", "sha256", "TEXT NULL"),
+      add("parts", "sha256", "TEXT NULL"),
+      add("messages", "raw_sha256", "TEXT NULL"),
+    ],
+  },
+  Migration {
+    version: 11,
+    name: "structured log entries",
+    steps: &[
+      add("logs", "target", "TEXT NULL"),
+      add("logs", "fields_json", "TEXT NULL"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS logs_ts ON logs (ts)"),
+    ],
+  },
+  Migration {
+    version: 12,
+    name: "api tokens",
+    steps: &[Step::Sql(
+      r#"CREATE TABLE IF NOT EXISTS api_tokens (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            token_sha256 TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )"#,
+    )],
+  },
+];
+
+pub const POSTGRES: &[Migration] = &[
+  Migration {
+    version: 1,
+    name: "initial schema",
+    steps: &[
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS messages (
+            id UUID PRIMARY KEY,
+            received_at TIMESTAMPTZ NOT NULL,
+            from_addr TEXT NULL,
+            to_recipients TEXT NOT NULL,
+            subject TEXT NULL,
+            text_body TEXT NULL,
+            html_body TEXT NULL,
+            headers_json TEXT NULL,
+            raw_len BIGINT NOT NULL,
+            session_id UUID NULL,
+            envelope_from TEXT NULL,
+            envelope_to TEXT NULL,
+            helo TEXT NULL,
+            client_addr TEXT NULL,
+            raw_source BYTEA NULL,
+            message_id_header TEXT NULL,
+            in_reply_to TEXT NULL,
+            references_json TEXT NULL,
+            thread_id UUID NULL
+        )"#,
+      ),
+      Step::Sql(
+        "CREATE INDEX IF NOT EXISTS messages_message_id_header ON messages (message_id_header)",
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS messages_thread_id ON messages (thread_id)"),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS logs (
+            id BIGSERIAL PRIMARY KEY,
+            ts TIMESTAMPTZ NOT NULL,
+            level TEXT NOT NULL,
+            message TEXT NOT NULL
+        )"#,
+      ),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS attachments (
+            id UUID PRIMARY KEY,
+            message_id UUID NOT NULL,
+            filename TEXT NULL,
+            content_type TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            content BYTEA NOT NULL,
+            content_id TEXT NULL,
+            inline BOOLEAN NOT NULL DEFAULT FALSE
+        )"#,
+      ),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS recipients (
+            message_id UUID NOT NULL,
+            kind TEXT NOT NULL,
+            position BIGINT NOT NULL,
+            name TEXT NULL,
+            address TEXT NOT NULL
+        )"#,
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS recipients_message_id ON recipients (message_id)"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS recipients_address ON recipients (address)"),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS parts (
+            id UUID PRIMARY KEY,
+            message_id UUID NOT NULL,
+            seq BIGINT NOT NULL,
+            path TEXT NOT NULL,
+            parent_path TEXT NULL,
+            content_type TEXT NOT NULL,
+            charset TEXT NULL,
+            transfer_encoding TEXT NULL,
+            content_id TEXT NULL,
+            disposition TEXT NULL,
+            filename TEXT NULL,
+            headers_json TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            content BYTEA NULL
+        )"#,
+      ),
+      Step::Sql("CREATE INDEX IF NOT EXISTS parts_message_id ON parts (message_id)"),
+      Step::Sql(
+        r#"CREATE TABLE IF NOT EXISTS smtp_sessions (
+            id UUID PRIMARY KEY,
+            started_at TIMESTAMPTZ NOT NULL,
+            ended_at TIMESTAMPTZ NULL,
+            client_addr TEXT NOT NULL,
+            helo TEXT NULL,
+            auth_user TEXT NULL,
+            transcript_json TEXT NOT NULL
+        )"#,
+      ),
+    ],
+  },
+  Migration {
+    version: 2,
+    name: "list and lookup indexes",
+    steps: &[
+      Step::Sql("CREATE INDEX IF NOT EXISTS messages_received_at ON messages (received_at)"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS attachments_message_id ON attachments (message_id)"),
+    ],
+  },
+  Migration {
+    version: 3,
+    name: "attachments cascade with their message",
+    steps: &[
+      Step::Sql("DELETE FROM attachments WHERE message_id NOT IN (SELECT id FROM messages)"),
+      Step::Sql(
+        "ALTER TABLE attachments ADD CONSTRAINT attachments_message_id_fkey FOREIGN KEY (message_id) REFERENCES messages (id) ON DELETE CASCADE",
+      ),
+    ],
+  },
+  Migration {
+    version: 4,
+    name: "blob store references",
+    steps: &[
+      add("attachments", "sha256", "TEXT NULL"),
+      add("parts", "sha256", "TEXT NULL"),
+      add("messages", "raw_sha256", "TEXT NULL"),
+    ],
+  },
+  Migration {
+    version: 5,
+    name: "structured log entries",
+    steps: &[
+      add("logs", "target", "TEXT NULL"),
+      add("logs", "fields_json", "TEXT NULL"),
+      Step::Sql("CREATE INDEX IF NOT EXISTS logs_ts ON logs (ts)"),
+    ],
+  },
+  Migration {
+    version: 6,
+    name: "api tokens",
+    steps: &[Step::Sql(
+      r#"CREATE TABLE IF NOT EXISTS api_tokens (
+            id UUID PRIMARY KEY,
+            name TEXT NOT NULL,
+            token_sha256 TEXT NOT NULL UNIQUE,
+            scopes TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL
+        )"#,
+    )],
+  },
+];
+
+/// Latest version in a migration list.
+pub fn latest(migrations: &[Migration]) -> i64 {
+  migrations.last().map_or(0, |m| m.version)
+}
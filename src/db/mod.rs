@@ -1,52 +1,108 @@
-//! Database helpers: migrations and path handling.
+//! Database helpers: migrations, deletes and path handling.
+//!
+//! The functions here target SQLite; `postgres` has the PostgreSQL versions.
 
+pub mod migrations;
+pub mod postgres;
+
+use chrono::Utc;
+use migrations::Step;
 use sqlx::SqlitePool;
-use std::path::Path;
+use std::{collections::HashSet, path::Path};
+use tracing::{info, warn};
+use uuid::Uuid;
 
-/// Run SQLite migrations to create tables if absent.
+/// Bring a SQLite database up to the latest schema version.
 pub async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   sqlx::query(
-    r#"CREATE TABLE IF NOT EXISTS messages (
-            id TEXT PRIMARY KEY,
-            received_at TEXT NOT NULL,
-            from_addr TEXT NULL,
-            to_recipients TEXT NOT NULL,
-            subject TEXT NULL,
-            text_body TEXT NULL,
-            html_body TEXT NULL,
-            headers_json TEXT NULL,
-            raw_len INTEGER NOT NULL
-        )"#,
-  )
-  .execute(pool)
-  .await?;
-
-  sqlx::query(
-    r#"CREATE TABLE IF NOT EXISTS logs (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            ts TEXT NOT NULL,
-            level TEXT NOT NULL,
-            message TEXT NOT NULL
-        )"#,
-  )
-  .execute(pool)
-  .await?;
-
-  sqlx::query(
-    r#"CREATE TABLE IF NOT EXISTS attachments (
-            id TEXT PRIMARY KEY,
-            message_id TEXT NOT NULL,
-            filename TEXT NULL,
-            content_type TEXT NOT NULL,
-            size INTEGER NOT NULL,
-            content BLOB NOT NULL
-        )"#,
+    "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at TEXT NOT NULL)",
   )
   .execute(pool)
   .await?;
+  let current: i64 = sqlx::query_scalar("SELECT coalesce(MAX(version), 0) FROM schema_version")
+    .fetch_one(pool)
+    .await?;
+  let latest = migrations::latest(migrations::SQLITE);
+  if current > latest {
+    warn!("database schema v{current} is newer than this fauxmail (v{latest})");
+  }
+  for m in migrations::SQLITE.iter().filter(|m| m.version > current) {
+    let mut tx = pool.begin().await?;
+    for step in m.steps {
+      match step {
+        Step::Sql(sql) => {
+          sqlx::query(sql).execute(&mut *tx).await?;
+        }
+        Step::AddColumn {
+          table,
+          column,
+          decl,
+        } => {
+          let exists: Option<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+              .bind(table)
+              .bind(column)
+              .fetch_optional(&mut *tx)
+              .await?;
+          if exists.is_none() {
+            sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))
+              .execute(&mut *tx)
+              .await?;
+          }
+        }
+      }
+    }
+    sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)")
+      .bind(m.version)
+      .bind(m.name)
+      .bind(Utc::now())
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    info!("applied migration {}: {}", m.version, m.name);
+  }
   Ok(())
 }
 
+/// Delete messages with their recipients, parts, attachments (by cascade),
+/// and the ended SMTP sessions they came from once no other message refers
+/// to them. Returns the number of messages removed.
+pub async fn delete_messages(pool: &SqlitePool, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+  let mut deleted = 0;
+  let mut sessions = HashSet::new();
+  let mut tx = pool.begin().await?;
+  for id in ids {
+    let session: Option<Option<Uuid>> =
+      sqlx::query_scalar("SELECT session_id FROM messages WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+    sessions.extend(session.flatten());
+    for sql in [
+      "DELETE FROM recipients WHERE message_id = ?",
+      "DELETE FROM parts WHERE message_id = ?",
+    ] {
+      sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+    }
+    deleted += sqlx::query("DELETE FROM messages WHERE id = ?")
+      .bind(id)
+      .execute(&mut *tx)
+      .await?
+      .rows_affected();
+  }
+  for session in sessions {
+    sqlx::query(
+      "DELETE FROM smtp_sessions WHERE id = ? AND ended_at IS NOT NULL AND NOT EXISTS (SELECT 1 FROM messages WHERE session_id = ?)",
+    )
+    .bind(session)
+    .bind(session)
+    .execute(&mut *tx)
+    .await?;
+  }
+  tx.commit().await?;
+  Ok(deleted)
+}
+
 /// Ensure SQLite file and parent folder exist for a given sqlx URL.
 pub fn ensure_sqlite_path(db_url: &str) -> String {
   if !db_url.starts_with("sqlite:") {
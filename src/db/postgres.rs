@@ -0,0 +1,100 @@
+//! PostgreSQL migrations and deletes, mirroring the SQLite helpers.
+
+use super::migrations::{self, Step};
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Arbitrary key serialising schema changes across replicas starting at once.
+const MIGRATION_LOCK: i64 = 0x6661_7578_6d61_696c;
+
+/// Bring a PostgreSQL database up to the latest schema version. Each
+/// migration takes an advisory lock so replicas starting at once apply it
+/// exactly once.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
+  let mut tx = pool.begin().await?;
+  lock(&mut tx).await?;
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS schema_version (version BIGINT PRIMARY KEY, name TEXT NOT NULL, applied_at TIMESTAMPTZ NOT NULL)",
+  )
+  .execute(&mut *tx)
+  .await?;
+  let current = current_version(&mut tx).await?;
+  tx.commit().await?;
+  let latest = migrations::latest(migrations::POSTGRES);
+  if current > latest {
+    warn!("database schema v{current} is newer than this fauxmail (v{latest})");
+  }
+
+  for m in migrations::POSTGRES.iter().filter(|m| m.version > current) {
+    let mut tx = pool.begin().await?;
+    lock(&mut tx).await?;
+    // Another replica may have applied it while we waited for the lock.
+    if current_version(&mut tx).await? >= m.version {
+      continue;
+    }
+    for step in m.steps {
+      let sql = match step {
+        Step::Sql(sql) => sql.to_string(),
+        Step::AddColumn {
+          table,
+          column,
+          decl,
+        } => format!("ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {column} {decl}"),
+      };
+      sqlx::query(&sql).execute(&mut *tx).await?;
+    }
+    sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES ($1, $2, $3)")
+      .bind(m.version)
+      .bind(m.name)
+      .bind(Utc::now())
+      .execute(&mut *tx)
+      .await?;
+    tx.commit().await?;
+    info!("applied migration {}: {}", m.version, m.name);
+  }
+  Ok(())
+}
+
+async fn current_version(tx: &mut Transaction<'_, Postgres>) -> Result<i64, sqlx::Error> {
+  sqlx::query_scalar("SELECT coalesce(MAX(version), 0) FROM schema_version")
+    .fetch_one(&mut **tx)
+    .await
+}
+
+async fn lock(tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+  sqlx::query("SELECT pg_advisory_xact_lock($1)")
+    .bind(MIGRATION_LOCK)
+    .execute(&mut **tx)
+    .await?;
+  Ok(())
+}
+
+/// Delete messages with their recipients, parts, attachments (by cascade),
+/// and the ended SMTP sessions they came from once no other message refers
+/// to them. Returns the number of messages removed.
+pub async fn delete_messages(pool: &PgPool, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+  let mut tx = pool.begin().await?;
+  for sql in [
+    "DELETE FROM recipients WHERE message_id = ANY($1)",
+    "DELETE FROM parts WHERE message_id = ANY($1)",
+  ] {
+    sqlx::query(sql).bind(ids).execute(&mut *tx).await?;
+  }
+  let sessions: Vec<Option<Uuid>> =
+    sqlx::query_scalar("DELETE FROM messages WHERE id = ANY($1) RETURNING session_id")
+      .bind(ids)
+      .fetch_all(&mut *tx)
+      .await?;
+  let deleted = sessions.len() as u64;
+  let sessions: Vec<Uuid> = sessions.into_iter().flatten().collect();
+  sqlx::query(
+    "DELETE FROM smtp_sessions WHERE id = ANY($1) AND ended_at IS NOT NULL AND NOT EXISTS (SELECT 1 FROM messages WHERE session_id = smtp_sessions.id)",
+  )
+  .bind(&sessions)
+  .execute(&mut *tx)
+  .await?;
+  tx.commit().await?;
+  Ok(deleted)
+}
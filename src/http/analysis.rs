@@ -0,0 +1,129 @@
+//! Analysis APIs over stored messages.
+
+use crate::{
+  analysis::{codes, deliverability, html_compat, links},
+  app::AppState,
+  models::{
+    analysis::{
+      compat_report::CompatReport, deliverability_report::DeliverabilityReport,
+      message_link::MessageLink,
+    },
+    email::db_email::DbEmail,
+  },
+};
+use axum::{
+  Json,
+  extract::{Path as AxumPath, Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::error;
+use uuid::Uuid;
+
+/// Email client compatibility report for the HTML body.
+pub async fn get_html_check(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  match state.store.get_message(id).await {
+    Ok(Some(DbEmail {
+      html_body: html, ..
+    })) => Json(CompatReport {
+      message_id: id,
+      has_html: html.is_some(),
+      clients: html_compat::check(html.as_deref().unwrap_or_default()),
+    })
+    .into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_html_check error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+/// Spam score and deliverability findings.
+pub async fn get_analysis(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  let row = state.store.get_message(id).await;
+  match row {
+    Ok(Some(m)) => {
+      let findings = deliverability::analyze(&m);
+      let score = findings.iter().map(|f| f.score).sum::<f32>();
+      Json(DeliverabilityReport {
+        message_id: id,
+        score,
+        threshold: deliverability::THRESHOLD,
+        likely_spam: score >= deliverability::THRESHOLD,
+        findings,
+      })
+      .into_response()
+    }
+    Ok(None) => (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_analysis error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinksParams {
+  /// Request each http(s) link and report its status.
+  pub check: Option<bool>,
+}
+
+/// Links from the bodies and `List-Unsubscribe`, optionally checked.
+pub async fn get_links(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+  Query(params): Query<LinksParams>,
+) -> impl IntoResponse {
+  let row = state.store.get_message(id).await;
+  let m = match row {
+    Ok(Some(m)) => m,
+    Ok(None) => return (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_links error: {e}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+    }
+  };
+  let headers: HashMap<String, String> = m
+    .headers_json
+    .as_deref()
+    .and_then(|j| serde_json::from_str(j).ok())
+    .unwrap_or_default();
+  let list_unsubscribe = headers
+    .iter()
+    .find(|(k, _)| k.eq_ignore_ascii_case("list-unsubscribe"))
+    .map(|(_, v)| v.as_str());
+  let mut found: Vec<MessageLink> = links::extract(
+    m.text_body.as_deref(),
+    m.html_body.as_deref(),
+    list_unsubscribe,
+  );
+  if params.check.unwrap_or(false) {
+    links::check_all(&mut found).await;
+  }
+  Json(found).into_response()
+}
+
+/// One-time passcodes and magic links.
+pub async fn get_codes(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  let row = state.store.get_message(id).await;
+  match row {
+    Ok(Some(m)) => Json(codes::extract(&m)).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_codes error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
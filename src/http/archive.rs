@@ -0,0 +1,205 @@
+//! mbox export and mbox / zip / Maildir import.
+
+use crate::{
+  app::AppState,
+  blob::StoredBody,
+  http::{logs::log_db, messages::ListParams},
+  ingest::{self, IngestError, Source},
+  models::{email::db_email::DbEmail, response::import_result::ImportResult},
+  store::SortKey,
+  util::{
+    archive::{UnpackError, is_mbox, is_zip, split_mbox, unpack_zip, write_mbox_entry},
+    params::content_disposition,
+  },
+};
+use axum::{
+  Json,
+  body::Bytes,
+  extract::{Query, State},
+  http::{HeaderMap, StatusCode, header},
+  response::IntoResponse,
+};
+use std::collections::HashMap;
+use tracing::error;
+
+/// Request body limit for `/import`.
+pub const IMPORT_MAX_BYTES: usize = 512 * 1024 * 1024;
+
+/// All messages matching the list filters as one mbox file, oldest first.
+/// Paging only applies when `page` or `limit` is given.
+pub async fn export_mbox(
+  State(state): State<AppState>,
+  Query(params): Query<ListParams>,
+) -> impl IntoResponse {
+  let mut query = params.to_query();
+  query.sort = SortKey::ReceivedAt;
+  query.ascending = true;
+  if params.page.is_none() && params.limit.is_none() {
+    query.limit = None;
+    query.offset = 0;
+  }
+  let rows = match state.store.list_messages(&query).await {
+    Ok(rows) => rows,
+    Err(e) => {
+      error!("export_mbox error: {e}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+    }
+  };
+
+  let mut out = Vec::new();
+  for m in &rows {
+    let raw = match state.store.raw_source(m.id).await.ok().flatten().flatten() {
+      Some(StoredBody::Bytes(raw)) => Some(raw),
+      Some(StoredBody::Blob(sha)) => match &state.blobs {
+        Some(blobs) => blobs
+          .read(&sha)
+          .await
+          .inspect_err(|e| error!("export_mbox error: blob {sha}: {e}"))
+          .ok(),
+        None => None,
+      },
+      None => None,
+    };
+    let raw = raw.unwrap_or_else(|| synthesize_eml(m));
+    let sender = m
+      .envelope_from
+      .as_deref()
+      .or(m.from_addr.as_deref())
+      .and_then(|f| crate::util::parse_addresses(f).into_iter().next())
+      .map(|a| a.address)
+      .unwrap_or_else(|| "MAILER-DAEMON".to_string());
+    write_mbox_entry(&mut out, &sender, m.received_at, &raw);
+  }
+
+  let mut headers = HeaderMap::new();
+  headers.insert(header::CONTENT_TYPE, "application/mbox".parse().unwrap());
+  if let Ok(v) = content_disposition("attachment", "fauxmail.mbox").parse() {
+    headers.insert(header::CONTENT_DISPOSITION, v);
+  }
+  (headers, out).into_response()
+}
+
+/// Rebuild an RFC 822 message for rows posted as JSON, which keep no source.
+fn synthesize_eml(m: &DbEmail) -> Vec<u8> {
+  let mut lines: Vec<String> = Vec::new();
+  if let Some(from) = &m.from_addr {
+    lines.push(format!("From: {from}"));
+  }
+  let to: Vec<String> = serde_json::from_str(&m.to_recipients).unwrap_or_default();
+  if !to.is_empty() {
+    lines.push(format!("To: {}", to.join(", ")));
+  }
+  if let Some(subject) = &m.subject {
+    lines.push(format!("Subject: {subject}"));
+  }
+  lines.push(format!("Date: {}", m.received_at.to_rfc2822()));
+  let extra: HashMap<String, String> = m
+    .headers_json
+    .as_deref()
+    .and_then(|j| serde_json::from_str(j).ok())
+    .unwrap_or_default();
+  let mut extra: Vec<_> = extra
+    .into_iter()
+    .filter(|(k, _)| {
+      let k = k.to_ascii_lowercase();
+      !matches!(
+        k.as_str(),
+        "from"
+          | "to"
+          | "subject"
+          | "date"
+          | "mime-version"
+          | "content-type"
+          | "content-transfer-encoding"
+      )
+    })
+    .collect();
+  extra.sort();
+  lines.extend(extra.into_iter().map(|(k, v)| format!("{k}: {v}")));
+  lines.push("MIME-Version: 1.0".to_string());
+
+  let text = m.text_body.as_deref();
+  let html = m.html_body.as_deref();
+  let single = |ctype: &str, body: &str| {
+    format!("Content-Type: {ctype}; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{body}")
+  };
+  let body = match (text, html) {
+    (Some(text), Some(html)) => {
+      let boundary = format!("fauxmail-{}", m.id.simple());
+      format!(
+        "Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\r\n--{boundary}\r\n{}\r\n--{boundary}\r\n{}\r\n--{boundary}--\r\n",
+        single("text/plain", text),
+        single("text/html", html)
+      )
+    }
+    (None, Some(html)) => single("text/html", html),
+    (text, None) => single("text/plain", text.unwrap_or_default()),
+  };
+  format!("{}\r\n{body}", lines.join("\r\n")).into_bytes()
+}
+
+/// Import an mbox file, a zip of `.eml` files, a zipped Maildir, or a single EML.
+/// A zip may expand to at most [`IMPORT_MAX_BYTES`].
+pub async fn import_archive(State(state): State<AppState>, body: Bytes) -> impl IntoResponse {
+  let messages = if is_zip(&body) {
+    let data = body.clone();
+    let unpacked =
+      tokio::task::spawn_blocking(move || unpack_zip(&data, IMPORT_MAX_BYTES as u64)).await;
+    match unpacked {
+      Ok(Ok(m)) => m,
+      Ok(Err(e @ UnpackError::TooLarge)) => {
+        return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response();
+      }
+      Ok(Err(e)) => {
+        error!("import zip error: {e}");
+        return (StatusCode::BAD_REQUEST, "invalid zip archive").into_response();
+      }
+      Err(e) => {
+        error!("import zip error: {e}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "import failed").into_response();
+      }
+    }
+  } else if is_mbox(&body) {
+    split_mbox(&body)
+  } else if body.is_empty() {
+    Vec::new()
+  } else {
+    vec![body.to_vec()]
+  };
+  if messages.is_empty() {
+    return (StatusCode::BAD_REQUEST, "no messages found").into_response();
+  }
+
+  let mut result = ImportResult {
+    imported: 0,
+    failed: 0,
+    ids: Vec::new(),
+    errors: Vec::new(),
+  };
+  for (i, raw) in messages.into_iter().enumerate() {
+    match ingest::ingest_raw(&state, Source::Import, raw, None).await {
+      Ok(id) => {
+        result.imported += 1;
+        result.ids.push(id);
+      }
+      Err(e) => {
+        if matches!(e, IngestError::Db(_) | IngestError::Blob(_)) {
+          error!("import message {} {e}", i + 1);
+        }
+        result.failed += 1;
+        result.errors.push(format!("message {}: {e}", i + 1));
+      }
+    }
+  }
+  log_db(
+    &state,
+    "INFO",
+    &format!(
+      "imported {} messages ({} failed)",
+      result.imported, result.failed
+    ),
+  )
+  .await
+  .ok();
+  Json(result).into_response()
+}
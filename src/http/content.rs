@@ -0,0 +1,135 @@
+//! Serving stored bodies with single-range `Range` support.
+
+use crate::{app::AppState, blob::StoredBody};
+use axum::{
+  body::Body,
+  http::{HeaderMap, StatusCode, header},
+  response::{IntoResponse, Response},
+};
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use tracing::error;
+
+/// What a `Range` header asks for, given the body length.
+enum RangeRequest {
+  Full,
+  Part(u64, u64),
+  Unsatisfiable,
+}
+
+/// Single byte ranges only (`bytes=a-b`, `bytes=a-`, `bytes=-n`); anything
+/// else, including multiple ranges, is answered with the whole body.
+fn parse_range(request: &HeaderMap, len: u64) -> RangeRequest {
+  let Some(spec) = request
+    .get(header::RANGE)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.trim().strip_prefix("bytes="))
+  else {
+    return RangeRequest::Full;
+  };
+  if spec.contains(',') {
+    return RangeRequest::Full;
+  }
+  let Some((start, end)) = spec.split_once('-') else {
+    return RangeRequest::Full;
+  };
+  let (start, end) = (start.trim(), end.trim());
+  let range = if start.is_empty() {
+    match end.parse::<u64>() {
+      Ok(0) => return RangeRequest::Unsatisfiable,
+      Ok(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+      Err(_) => return RangeRequest::Full,
+    }
+  } else {
+    let Ok(start) = start.parse::<u64>() else {
+      return RangeRequest::Full;
+    };
+    let end = match end {
+      "" => len.saturating_sub(1),
+      e => match e.parse::<u64>() {
+        Ok(e) if e >= start => e.min(len.saturating_sub(1)),
+        _ => return RangeRequest::Full,
+      },
+    };
+    (start, end)
+  };
+  if len == 0 || range.0 >= len {
+    return RangeRequest::Unsatisfiable;
+  }
+  RangeRequest::Part(range.0, range.1)
+}
+
+/// Respond with `body`, honouring the request's `Range` header. Blob bodies
+/// are streamed from disk; a missing blob is a 404.
+pub async fn serve_body(
+  state: &AppState,
+  request: &HeaderMap,
+  mut headers: HeaderMap,
+  body: StoredBody,
+) -> Response {
+  headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+  match body {
+    StoredBody::Bytes(bytes) => {
+      let len = bytes.len() as u64;
+      match parse_range(request, len) {
+        RangeRequest::Full => (headers, bytes).into_response(),
+        RangeRequest::Part(start, end) => {
+          set_content_range(&mut headers, start, end, len);
+          let slice = bytes[start as usize..=end as usize].to_vec();
+          (StatusCode::PARTIAL_CONTENT, headers, slice).into_response()
+        }
+        RangeRequest::Unsatisfiable => unsatisfiable(headers, len),
+      }
+    }
+    StoredBody::Blob(sha) => {
+      let Some(path) = state.blobs.as_ref().and_then(|b| b.path(&sha)) else {
+        error!("serve_body error: blob {sha} referenced but no blob store configured");
+        return (StatusCode::NOT_FOUND, "content not available").into_response();
+      };
+      let mut file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(e) => {
+          error!("serve_body error: blob {sha}: {e}");
+          return (StatusCode::NOT_FOUND, "content not available").into_response();
+        }
+      };
+      let len = match file.metadata().await {
+        Ok(m) => m.len(),
+        Err(e) => {
+          error!("serve_body error: blob {sha}: {e}");
+          return (StatusCode::INTERNAL_SERVER_ERROR, "blob error").into_response();
+        }
+      };
+      let (status, start, end) = match parse_range(request, len) {
+        RangeRequest::Full => (StatusCode::OK, 0, len.saturating_sub(1)),
+        RangeRequest::Part(start, end) => {
+          set_content_range(&mut headers, start, end, len);
+          (StatusCode::PARTIAL_CONTENT, start, end)
+        }
+        RangeRequest::Unsatisfiable => return unsatisfiable(headers, len),
+      };
+      if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+        error!("serve_body error: blob {sha}: {e}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "blob error").into_response();
+      }
+      let count = if len == 0 { 0 } else { end - start + 1 };
+      headers.insert(header::CONTENT_LENGTH, count.into());
+      let stream = ReaderStream::new(file.take(count));
+      (status, headers, Body::from_stream(stream)).into_response()
+    }
+  }
+}
+
+fn set_content_range(headers: &mut HeaderMap, start: u64, end: u64, len: u64) {
+  if let Ok(v) = format!("bytes {start}-{end}/{len}").parse() {
+    headers.insert(header::CONTENT_RANGE, v);
+  }
+}
+
+fn unsatisfiable(mut headers: HeaderMap, len: u64) -> Response {
+  if let Ok(v) = format!("bytes */{len}").parse() {
+    headers.insert(header::CONTENT_RANGE, v);
+  }
+  (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()
+}
@@ -0,0 +1,66 @@
+//! Liveness, readiness and build/runtime info for probes.
+
+use crate::{
+  app::AppState,
+  models::response::server_info::{Features, InfoResponse, ReadyResponse},
+};
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use tracing::error;
+
+/// The process is up and serving HTTP.
+pub async fn healthz() -> impl IntoResponse {
+  "ok"
+}
+
+/// 200 once the database answers and the SMTP listener is bound, else 503.
+pub async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+  let database = match state.store.ping().await {
+    Ok(()) => true,
+    Err(e) => {
+      error!("readyz error: {e}");
+      false
+    }
+  };
+  let smtp = state.server.smtp_addr.get().is_some();
+  let ready = database && smtp;
+  let status = if ready {
+    StatusCode::OK
+  } else {
+    StatusCode::SERVICE_UNAVAILABLE
+  };
+  (
+    status,
+    Json(ReadyResponse {
+      ready,
+      database,
+      smtp,
+    }),
+  )
+}
+
+pub async fn info(State(state): State<AppState>) -> impl IntoResponse {
+  let messages = match state.store.stats().await {
+    Ok(stats) => Some(stats.messages),
+    Err(e) => {
+      error!("info error: {e}");
+      None
+    }
+  };
+  let smtp_auth =
+    std::env::var("FAUXMAIL_SMTP_USER").is_ok() && std::env::var("FAUXMAIL_SMTP_PASS").is_ok();
+  Json(InfoResponse {
+    version: env!("CARGO_PKG_VERSION"),
+    http_addr: state.server.http_addr.get().copied(),
+    smtp_addr: state.server.smtp_addr.get().copied(),
+    database: state.server.database.clone(),
+    messages,
+    features: Features {
+      smtp_auth,
+      blob_store: state.blobs.is_some(),
+      blob_raw_sources: state.blobs.as_ref().is_some_and(|b| b.raw_sources),
+      retention: state.server.retention,
+      ingest_hooks: state.hooks.iter().count(),
+      api_auth: state.auth.enabled(),
+    },
+  })
+}
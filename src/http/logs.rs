@@ -1,35 +1,68 @@
-//! Logs API and DB helper.
+//! Logs API and store helper.
 
-use crate::{app::AppState, models::log::log_entry::LogEntry};
-use axum::{Json, response::IntoResponse};
-use chrono::Utc;
+use crate::{app::AppState, models::log::new_log_entry::NewLogEntry, store::LogQuery};
+use axum::{
+  Json,
+  extract::{Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
 use tracing::error;
 
+/// Levels from most to least severe.
+const LEVELS: [&str; 5] = ["ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+
+#[derive(Debug, Deserialize)]
+pub struct LogParams {
+  /// Minimum level: `level=warn` returns WARN and ERROR entries.
+  pub level: Option<String>,
+  /// Only entries after this id, paged forward (for tailing).
+  pub since_id: Option<i64>,
+  pub since: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  /// Text in the message, target or fields.
+  pub q: Option<String>,
+  pub page: Option<u32>,
+  pub limit: Option<u32>,
+}
+
+/// Log entries, oldest first within a page of 200 (at most 1000).
 pub async fn list_logs(
-  axum::extract::State(state): axum::extract::State<AppState>,
+  State(state): State<AppState>,
+  Query(params): Query<LogParams>,
 ) -> impl IntoResponse {
-  let rows: Result<Vec<LogEntry>, _> =
-    sqlx::query_as("SELECT id, ts, level, message FROM logs ORDER BY id DESC LIMIT 200")
-      .fetch_all(&state.db)
-      .await;
-  match rows {
-    Ok(mut logs) => {
-      logs.reverse();
-      Json(logs).into_response()
-    }
+  let levels = match params.level.as_deref().map(str::trim) {
+    None | Some("") => Vec::new(),
+    Some(level) => match LEVELS.iter().position(|l| l.eq_ignore_ascii_case(level)) {
+      Some(i) => LEVELS[..=i].iter().map(|l| l.to_string()).collect(),
+      None => return (StatusCode::BAD_REQUEST, "unknown level").into_response(),
+    },
+  };
+  let page = params.page.unwrap_or(1).max(1);
+  let limit = params.limit.unwrap_or(200).clamp(1, 1000);
+  let query = LogQuery {
+    levels,
+    since_id: params.since_id,
+    since: params.since,
+    until: params.until,
+    q: params.q,
+    limit,
+    offset: (page - 1) * limit,
+  };
+  match state.store.list_logs(&query).await {
+    Ok(logs) => Json(logs).into_response(),
     Err(e) => {
       error!("list_logs error: {e}");
-      (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
     }
   }
 }
 
 pub async fn log_db(state: &AppState, level: &str, message: &str) -> Result<(), sqlx::Error> {
-  sqlx::query("INSERT INTO logs (ts, level, message) VALUES (?, ?, ?)")
-    .bind(Utc::now())
-    .bind(level)
-    .bind(message)
-    .execute(&state.db)
-    .await?;
-  Ok(())
+  state
+    .store
+    .append_log(&NewLogEntry::new(level, message))
+    .await
 }
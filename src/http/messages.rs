@@ -1,13 +1,19 @@
-//! Message JSON APIs and HTML message view.
+//! Message JSON APIs.
 
 use crate::{
   app::AppState,
+  http::content::serve_body,
   models::{
-    attachment::attachment_meta::AttachmentMeta,
-    email::{api_email::ApiEmail, db_email::DbEmail},
+    email::{
+      address::{Address, Addresses},
+      api_email::ApiEmail,
+      db_email::DbEmail,
+      header_field::HeaderField,
+    },
     response::message_with_attachments::MessageWithAttachments,
   },
-  util::html_escape,
+  store::{MessageFilter, MessageQuery, SortKey},
+  util::params::content_disposition,
 };
 use axum::{
   Json,
@@ -42,67 +48,81 @@ pub struct ListParams {
   pub sort: Option<String>,
   pub dir: Option<String>,
   pub q: Option<String>,
+  /// Filter on sender name or address.
+  pub from: Option<String>,
+  /// Filter on any To/Cc/Bcc name or address.
+  pub to: Option<String>,
+  /// Dashboard layout; `threads` groups messages by conversation.
+  pub view: Option<String>,
 }
 
-pub fn compute_list_params(
-  p: &ListParams,
-) -> (u32, u32, &'static str, &'static str, Option<String>) {
-  let page = p.page.unwrap_or(1).max(1);
-  let limit = p.limit.unwrap_or(50).clamp(1, 200);
-  let offset = (page - 1) * limit;
-  let order_by = match p.sort.as_deref() {
-    Some("subject") => "subject",
-    Some("from") => "from_addr",
-    Some("to") => "to_recipients",
-    _ => "received_at",
-  };
-  let dir = match p.dir.as_deref() {
-    Some("asc") => "ASC",
-    _ => "DESC",
-  };
-  let like = p.q.as_ref().and_then(|s| {
-    let t = s.trim();
-    if t.is_empty() {
-      None
-    } else {
-      Some(format!("%{t}%"))
+impl ListParams {
+  /// Store query for one page of the list (50 by default, at most 200).
+  pub fn to_query(&self) -> MessageQuery {
+    let page = self.page.unwrap_or(1).max(1);
+    let limit = self.limit.unwrap_or(50).clamp(1, 200);
+    MessageQuery {
+      filter: self.filter(),
+      sort: match self.sort.as_deref() {
+        Some("subject") => SortKey::Subject,
+        Some("from") => SortKey::From,
+        Some("to") => SortKey::To,
+        _ => SortKey::ReceivedAt,
+      },
+      ascending: self.dir.as_deref() == Some("asc"),
+      limit: Some(limit),
+      offset: (page - 1) * limit,
+    }
+  }
+
+  pub fn filter(&self) -> MessageFilter {
+    MessageFilter {
+      q: self.q.clone(),
+      from: self.from.clone(),
+      to: self.to.clone(),
     }
+  }
+}
+
+/// Convert rows to API emails, attaching their stored address lists.
+pub async fn to_api_emails(state: &AppState, rows: Vec<DbEmail>) -> Vec<ApiEmail> {
+  if rows.is_empty() {
+    return Vec::new();
+  }
+  let ids: Vec<Uuid> = rows.iter().map(|r| r.id).collect();
+  let recipients = state.store.recipients(&ids).await.unwrap_or_else(|e| {
+    error!("recipients lookup error: {e}");
+    Vec::new()
   });
-  (limit, offset, order_by, dir, like)
+  let mut by_message: std::collections::HashMap<Uuid, Addresses> = Default::default();
+  for r in recipients {
+    let addresses = by_message.entry(r.message_id).or_default();
+    if let Some(list) = addresses.get_mut(&r.kind) {
+      list.push(Address {
+        name: r.name,
+        address: r.address,
+      });
+    }
+  }
+  rows
+    .into_iter()
+    .map(|d| {
+      let stored = by_message.remove(&d.id);
+      let mut email = ApiEmail::from(d);
+      if let Some(addresses) = stored {
+        email.addresses = addresses;
+      }
+      email
+    })
+    .collect()
 }
 
 pub async fn list_messages(
   State(state): State<AppState>,
   Query(params): Query<ListParams>,
 ) -> impl IntoResponse {
-  let (limit, offset, order_by, dir, like) = compute_list_params(&params);
-  let sql = if like.is_some() {
-    format!(
-      "SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages WHERE coalesce(from_addr,'') LIKE ? OR coalesce(subject,'') LIKE ? OR coalesce(text_body,'') LIKE ? OR to_recipients LIKE ? ORDER BY {order_by} {dir} LIMIT ? OFFSET ?"
-    )
-  } else {
-    format!(
-      "SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages ORDER BY {order_by} {dir} LIMIT ? OFFSET ?"
-    )
-  };
-  let mut query = sqlx::query_as::<_, DbEmail>(&sql);
-  if let Some(like_val) = like.as_ref() {
-    query = query
-      .bind(like_val)
-      .bind(like_val)
-      .bind(like_val)
-      .bind(like_val);
-  }
-  match query
-    .bind(limit as i64)
-    .bind(offset as i64)
-    .fetch_all(&state.db)
-    .await
-  {
-    Ok(rows) => {
-      let out: Vec<ApiEmail> = rows.into_iter().map(ApiEmail::from).collect();
-      Json(out).into_response()
-    }
+  match state.store.list_messages(&params.to_query()).await {
+    Ok(rows) => Json(to_api_emails(&state, rows).await).into_response(),
     Err(e) => {
       error!("list_messages error: {e}");
       (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
@@ -111,13 +131,14 @@ pub async fn list_messages(
 }
 
 pub async fn clear_messages(State(state): State<AppState>) -> impl IntoResponse {
-  if let Err(e) = sqlx::query("DELETE FROM messages").execute(&state.db).await {
+  if let Err(e) = state.store.clear_messages().await {
     error!("clear_messages error: {e}");
     return StatusCode::INTERNAL_SERVER_ERROR;
   }
   crate::http::logs::log_db(&state, "INFO", "cleared all messages")
     .await
     .ok();
+  tokio::spawn(async move { crate::blob::prune_unreferenced(&state).await });
   StatusCode::NO_CONTENT
 }
 
@@ -125,12 +146,13 @@ pub async fn get_message(
   State(state): State<AppState>,
   AxumPath(id): AxumPath<Uuid>,
 ) -> impl IntoResponse {
-  let row = sqlx::query_as::<_, DbEmail>("SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages WHERE id = ?").bind(id).fetch_optional(&state.db).await;
+  let row = state.store.get_message(id).await;
   match row {
     Ok(Some(m)) => {
-      let attachments: Vec<AttachmentMeta> = sqlx::query_as("SELECT id, message_id, filename, content_type, size FROM attachments WHERE message_id = ? ORDER BY id").bind(id).fetch_all(&state.db).await.unwrap_or_default();
+      let attachments = state.store.list_attachments(id).await.unwrap_or_default();
+      let message = to_api_emails(&state, vec![m]).await.remove(0);
       Json(MessageWithAttachments {
-        message: ApiEmail::from(m),
+        message,
         attachments,
       })
       .into_response()
@@ -143,75 +165,48 @@ pub async fn get_message(
   }
 }
 
-pub async fn get_message_html(
+/// Original message source as received, for SMTP and EML submissions.
+pub async fn get_message_raw(
   State(state): State<AppState>,
   AxumPath(id): AxumPath<Uuid>,
+  request: HeaderMap,
 ) -> impl IntoResponse {
-  let row = sqlx::query_as::<_, DbEmail>("SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages WHERE id = ?").bind(id).fetch_optional(&state.db).await.ok().flatten();
-  if let Some(m) = row {
-    let html = m
-      .html_body
-      .clone()
-      .or_else(|| {
-        m.text_body
-          .clone()
-          .map(|t| format!("<pre>{}</pre>", html_escape(&t)))
-      })
-      .unwrap_or_else(|| "<em>No content</em>".to_string());
-    let tmpl = r#"<!doctype html>
-<html lang="en"><head><meta charset="utf-8" />
-<meta name="viewport" content="width=device-width, initial-scale=1" />
-<title>{SUBJECT}</title>
-<style>body { font-family: system-ui, sans-serif; margin: 1.5rem; }</style>
-</head>
-<body>
-  <p><a href="/">← back</a></p>
-  <h2>{SUBJECT}</h2>
-  <p><strong>From:</strong> {FROM} &nbsp; <strong>To:</strong> {TO}</p>
-  <hr/>
-  <div>{HTML}</div>
-  <h3>Attachments</h3>
-  <div id="atts"></div>
-  <script>
-    (async () => {
-      const res = await fetch('/messages/{ID}/attachments');
-      const atts = await res.json();
-      const el = document.getElementById('atts');
-      if (!atts.length) { el.textContent = 'None'; return; }
-      el.innerHTML = atts.map(a => {
-        if (a.content_type.startsWith('image/')) {
-          return `<div><a href="/attachments/${'${'}a.id}/download" target="_blank">${'${'}a.filename || '(image)'} (${'${'}a.size} bytes)</a><br/><img src="/attachments/${'${'}a.id}/download" style="max-width:600px;max-height:400px;border:1px solid #ddd;margin:.5rem 0;"/></div>`;
-        }
-        return `<div><a href="/attachments/${'${'}a.id}/download" target="_blank">${'${'}a.filename || '(attachment)'} (${'${'}a.size} bytes, ${'${'}a.content_type})</a></div>`;
-      }).join('');
-    })();
-  </script>
-</body></html>"#;
-    let page = tmpl
-      .replace(
-        "{SUBJECT}",
-        &m.subject.clone().unwrap_or_else(|| "(no subject)".into()),
-      )
-      .replace(
-        "{FROM}",
-        &m.from_addr.clone().unwrap_or_else(|| "(unknown)".into()),
-      )
-      .replace("{TO}", &{
-        let v: Vec<String> = serde_json::from_str(&m.to_recipients).unwrap_or_default();
-        if v.is_empty() {
-          "(none)".into()
-        } else {
-          v.join(", ")
-        }
-      })
-      .replace("{HTML}", &html)
-      .replace("{ID}", &m.id.to_string());
-    let mut headers = HeaderMap::new();
-    headers.insert(
-      header::CONTENT_TYPE,
-      "text/html; charset=utf-8".parse().unwrap(),
-    );
-    return (headers, page).into_response();
+  let row = state.store.raw_source(id).await;
+  match row {
+    Ok(Some(Some(raw))) => {
+      let mut headers = HeaderMap::new();
+      headers.insert(header::CONTENT_TYPE, "message/rfc822".parse().unwrap());
+      if let Ok(v) = content_disposition("inline", &format!("{id}.eml")).parse() {
+        headers.insert(header::CONTENT_DISPOSITION, v);
+      }
+      serve_body(&state, &request, headers, raw).await
+    }
+    Ok(Some(_)) => (StatusCode::NOT_FOUND, "no raw source for message").into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_message_raw error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
   }
-  (StatusCode::NOT_FOUND, "message not found").into_response()
+}
+
+/// Top-level headers in their original order. Messages posted as JSON have
+/// no MIME tree, so their stored header map is returned sorted by name.
+pub async fn get_message_headers(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  let pairs = match state.store.message_headers(id).await {
+    Ok(Some(pairs)) => pairs,
+    Ok(None) => return (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_message_headers error: {e}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+    }
+  };
+  let fields: Vec<HeaderField> = pairs
+    .into_iter()
+    .map(|(name, value)| HeaderField { name, value })
+    .collect();
+  Json(fields).into_response()
 }
@@ -0,0 +1,26 @@
+//! Prometheus scrape endpoint.
+
+use crate::app::AppState;
+use axum::{
+  extract::State,
+  http::{HeaderMap, header},
+  response::IntoResponse,
+};
+use tracing::error;
+
+/// Counters since startup plus store totals; the totals are left out when
+/// the store cannot be queried.
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+  let stats = state
+    .store
+    .stats()
+    .await
+    .inspect_err(|e| error!("metrics error: {e}"))
+    .ok();
+  let mut headers = HeaderMap::new();
+  headers.insert(
+    header::CONTENT_TYPE,
+    "text/plain; version=0.0.4".parse().unwrap(),
+  );
+  (headers, state.metrics.render(stats.as_ref()))
+}
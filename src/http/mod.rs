@@ -3,15 +3,28 @@
 use crate::app::AppState;
 use axum::{
   Router,
-  routing::{get, post},
+  extract::DefaultBodyLimit,
+  middleware,
+  routing::{delete, get, post},
 };
 
+pub mod analysis;
+pub mod archive;
 pub mod attachments;
+pub mod content;
+pub mod health;
 pub mod logs;
 pub mod messages;
+pub mod metrics;
+pub mod parts;
 pub mod search;
 pub mod send;
+pub mod sessions;
+pub mod threads;
+pub mod tokens;
 pub mod ui;
+pub mod viewer;
+pub mod wait;
 
 /// Assemble the HTTP router with all routes.
 pub fn build_router(state: AppState) -> Router {
@@ -22,18 +35,49 @@ pub fn build_router(state: AppState) -> Router {
       get(messages::list_messages).delete(messages::clear_messages),
     )
     .route("/messages/:id", get(messages::get_message))
-    .route("/messages/:id/html", get(messages::get_message_html))
+    .route("/messages/:id/html", get(viewer::get_message_html))
+    .route("/messages/:id/body", get(viewer::get_message_body))
+    .route("/messages/:id/raw", get(messages::get_message_raw))
+    .route("/messages/:id/headers", get(messages::get_message_headers))
+    .route("/messages/:id/analysis", get(analysis::get_analysis))
+    .route("/messages/:id/codes", get(analysis::get_codes))
+    .route("/messages/:id/links", get(analysis::get_links))
+    .route("/messages/:id/html-check", get(analysis::get_html_check))
+    .route("/messages/:id/session", get(sessions::get_message_session))
     .route(
       "/messages/:id/attachments",
       get(attachments::list_attachments),
     )
+    .route("/messages/:id/parts", get(parts::list_parts))
+    .route("/parts/:part_id/download", get(parts::download_part))
     .route(
       "/attachments/:att_id/download",
       get(attachments::download_attachment),
     )
+    .route("/threads", get(threads::list_threads))
+    .route("/threads/:id", get(threads::get_thread))
+    .route("/wait", get(wait::wait_for_message))
     .route("/search", get(search::search_messages))
     .route("/send", post(send::send_message))
     .route("/send/raw", post(send::send_raw))
+    .route("/export.mbox", get(archive::export_mbox))
+    .route(
+      "/import",
+      post(archive::import_archive).layer(DefaultBodyLimit::max(archive::IMPORT_MAX_BYTES)),
+    )
     .route("/logs", get(logs::list_logs))
+    .route("/metrics", get(metrics::metrics))
+    .route("/healthz", get(health::healthz))
+    .route("/readyz", get(health::readyz))
+    .route("/info", get(health::info))
+    .route(
+      "/tokens",
+      get(tokens::list_tokens).post(tokens::create_token),
+    )
+    .route("/tokens/:id", delete(tokens::delete_token))
+    .layer(middleware::from_fn_with_state(
+      state.clone(),
+      crate::auth::authorize,
+    ))
     .with_state(state)
 }
@@ -0,0 +1,77 @@
+//! MIME parts API: tree view and per-part download.
+
+use crate::{
+  app::AppState,
+  blob::StoredBody,
+  http::{attachments::sandbox_headers, content::serve_body},
+  models::part::{part_node::PartNode, part_row::PartRow},
+  util::params::content_disposition,
+};
+use axum::{
+  Json,
+  extract::{Path as AxumPath, State},
+  http::{HeaderMap, StatusCode, header},
+  response::IntoResponse,
+};
+use tracing::error;
+use uuid::Uuid;
+
+/// MIME tree of a message, rooted at part `1`.
+pub async fn list_parts(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  match state.store.list_parts(id).await {
+    Ok(parts) => match PartNode::build(parts) {
+      Some(tree) => Json(tree).into_response(),
+      None => (StatusCode::NOT_FOUND, "no mime parts for message").into_response(),
+    },
+    Err(e) => {
+      error!("list_parts error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+/// Decoded body of a single part.
+pub async fn download_part(
+  State(state): State<AppState>,
+  AxumPath(part_id): AxumPath<Uuid>,
+  request: HeaderMap,
+) -> impl IntoResponse {
+  match state.store.get_part(part_id).await {
+    Ok(Some(PartRow {
+      content: Some(content),
+      filename,
+      content_type,
+      charset,
+      sha256,
+      ..
+    })) => {
+      let mut headers = HeaderMap::new();
+      sandbox_headers(&mut headers, &content_type);
+      let ctype = match charset {
+        Some(cs) if content_type.starts_with("text/") => format!("{content_type}; charset={cs}"),
+        _ => content_type,
+      };
+      headers.insert(
+        header::CONTENT_TYPE,
+        ctype
+          .parse()
+          .unwrap_or("application/octet-stream".parse().unwrap()),
+      );
+      if let Some(name) = filename {
+        if let Ok(v) = content_disposition("inline", &name).parse() {
+          headers.insert(header::CONTENT_DISPOSITION, v);
+        }
+      }
+      serve_body(&state, &request, headers, StoredBody::new(content, sha256)).await
+    }
+    Ok(Some(_)) => (StatusCode::NOT_FOUND, "multipart container has no body").into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, "not found").into_response(),
+    Err(e) => {
+      error!("download_part error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
@@ -0,0 +1,26 @@
+//! SMTP session transcript API.
+
+use crate::{app::AppState, models::session::api_session::ApiSession};
+use axum::{
+  Json,
+  extract::{Path as AxumPath, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use tracing::error;
+use uuid::Uuid;
+
+/// Transcript of the SMTP session that delivered a message.
+pub async fn get_message_session(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  match state.store.message_session(id).await {
+    Ok(Some(s)) => Json(ApiSession::from(s)).into_response(),
+    Ok(None) => (StatusCode::NOT_FOUND, "no smtp session for message").into_response(),
+    Err(e) => {
+      error!("get_message_session error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
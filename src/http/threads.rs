@@ -0,0 +1,111 @@
+//! Conversation APIs, grouped by Message-ID / In-Reply-To / References.
+
+use crate::{
+  app::AppState,
+  http::messages::{ListParams, to_api_emails},
+  models::{
+    email::api_email::ApiEmail,
+    thread::{api_thread::ApiThread, thread_detail::ThreadDetail},
+  },
+};
+use axum::{
+  Json,
+  extract::{Path as AxumPath, Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use std::collections::HashMap;
+use tracing::error;
+use uuid::Uuid;
+
+/// Threads with at least one message matching the list filters, most recent first.
+pub async fn load_threads(
+  state: &AppState,
+  params: &ListParams,
+) -> Result<Vec<ApiThread>, sqlx::Error> {
+  state.store.list_threads(&params.to_query()).await
+}
+
+/// Messages of the given threads, oldest first.
+pub async fn load_thread_messages(
+  state: &AppState,
+  ids: &[Uuid],
+) -> Result<Vec<ApiEmail>, sqlx::Error> {
+  let rows = state.store.thread_messages(ids).await?;
+  Ok(to_api_emails(state, rows).await)
+}
+
+/// Reply depth of each message within its thread; messages whose parent
+/// was not captured start at depth 0.
+pub fn reply_depths<'a>(messages: impl IntoIterator<Item = &'a ApiEmail>) -> Vec<usize> {
+  let messages: Vec<&ApiEmail> = messages.into_iter().collect();
+  let by_id: HashMap<&str, usize> = messages
+    .iter()
+    .enumerate()
+    .filter_map(|(i, m)| Some((m.message_id.as_deref()?, i)))
+    .collect();
+  let parent = |m: &ApiEmail| {
+    m.in_reply_to
+      .as_deref()
+      .or(m.references.last().map(String::as_str))
+      .and_then(|p| by_id.get(p).copied())
+  };
+  messages
+    .iter()
+    .map(|m| {
+      let mut depth = 0;
+      let mut current = parent(m);
+      // Bounded walk so a reference cycle cannot loop forever.
+      while let Some(i) = current.filter(|_| depth < messages.len()) {
+        depth += 1;
+        current = parent(messages[i]);
+      }
+      depth
+    })
+    .collect()
+}
+
+pub async fn list_threads(
+  State(state): State<AppState>,
+  Query(params): Query<ListParams>,
+) -> impl IntoResponse {
+  match load_threads(&state, &params).await {
+    Ok(threads) => Json(threads).into_response(),
+    Err(e) => {
+      error!("list_threads error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+pub async fn get_thread(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  let messages = match load_thread_messages(&state, &[id]).await {
+    Ok(m) if m.is_empty() => {
+      return (StatusCode::NOT_FOUND, "thread not found").into_response();
+    }
+    Ok(m) => m,
+    Err(e) => {
+      error!("get_thread error: {e}");
+      return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+    }
+  };
+  let mut missing_references: Vec<String> = Vec::new();
+  for m in &messages {
+    for r in m.references.iter().chain(&m.in_reply_to) {
+      let captured = messages.iter().any(|o| o.message_id.as_ref() == Some(r));
+      if !captured && !missing_references.contains(r) {
+        missing_references.push(r.clone());
+      }
+    }
+  }
+  Json(ThreadDetail {
+    id,
+    subject: messages[0].subject.clone(),
+    messages,
+    missing_references,
+  })
+  .into_response()
+}
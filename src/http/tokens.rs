@@ -0,0 +1,91 @@
+//! Managed API tokens (`admin` scope when auth is enabled).
+
+use crate::{
+  app::AppState,
+  auth::{self, Scope},
+  models::token::{api_token::ApiToken, created_token::CreatedToken, db_token::DbToken},
+};
+use axum::{
+  Json,
+  extract::{Path as AxumPath, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use tracing::{error, info};
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct NewTokenRequest {
+  pub name: String,
+  /// Any of `read`, `send`, `admin`.
+  pub scopes: Vec<String>,
+}
+
+pub async fn list_tokens(State(state): State<AppState>) -> impl IntoResponse {
+  match state.store.list_tokens().await {
+    Ok(tokens) => Json(tokens.into_iter().map(ApiToken::from).collect::<Vec<_>>()).into_response(),
+    Err(e) => {
+      error!("list_tokens error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+/// Create a token; the secret is only returned here.
+pub async fn create_token(
+  State(state): State<AppState>,
+  Json(req): Json<NewTokenRequest>,
+) -> impl IntoResponse {
+  let name = req.name.trim();
+  if name.is_empty() {
+    return (StatusCode::BAD_REQUEST, "name is required").into_response();
+  }
+  let scopes = match auth::parse_scopes(&req.scopes.join(",")) {
+    Ok(scopes) => scopes,
+    Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+  };
+  let secret = auth::generate_token();
+  let row = DbToken {
+    id: Uuid::new_v4(),
+    name: name.to_string(),
+    token_sha256: auth::hash_token(&secret),
+    scopes: scopes
+      .iter()
+      .map(|s| Scope::as_str(*s))
+      .collect::<Vec<_>>()
+      .join(" "),
+    created_at: Utc::now(),
+  };
+  if let Err(e) = state.store.insert_token(&row).await {
+    error!("create_token error: {e}");
+    return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+  }
+  info!("created api token {:?} ({})", row.name, row.scopes);
+  (
+    StatusCode::CREATED,
+    Json(CreatedToken {
+      info: row.into(),
+      token: secret,
+    }),
+  )
+    .into_response()
+}
+
+pub async fn delete_token(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  match state.store.delete_token(id).await {
+    Ok(true) => {
+      info!("revoked api token {id}");
+      StatusCode::NO_CONTENT.into_response()
+    }
+    Ok(false) => (StatusCode::NOT_FOUND, "token not found").into_response(),
+    Err(e) => {
+      error!("delete_token error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
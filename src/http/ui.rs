@@ -1,53 +1,108 @@
 //! Dashboard HTML.
 
-use crate::{app::AppState, http::messages::ListParams, models::email::db_email::DbEmail};
-use axum::{extract::Query, response::Html};
+use crate::{
+  app::AppState,
+  http::{messages::ListParams, threads},
+  models::email::{address::Address, api_email::ApiEmail},
+  util::html_escape,
+};
+use axum::{
+  extract::Query,
+  http::header,
+  response::{Html, IntoResponse},
+};
+use uuid::Uuid;
 
-pub async fn ui_index(
-  axum::extract::State(state): axum::extract::State<AppState>,
-  Query(params): Query<ListParams>,
-) -> Html<String> {
-  let (limit, offset, order_by, dir, like) = super::messages::compute_list_params(&params);
-  let sql = if like.is_some() {
-    format!(
-      "SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages WHERE coalesce(from_addr,'') LIKE ? OR coalesce(subject,'') LIKE ? OR coalesce(text_body,'') LIKE ? OR to_recipients LIKE ? ORDER BY {order_by} {dir} LIMIT ? OFFSET ?"
-    )
-  } else {
-    format!(
-      "SELECT id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len FROM messages ORDER BY {order_by} {dir} LIMIT ? OFFSET ?"
-    )
-  };
-  let mut query = sqlx::query_as::<_, DbEmail>(&sql);
-  if let Some(like_val) = like.as_ref() {
-    query = query
-      .bind(like_val)
-      .bind(like_val)
-      .bind(like_val)
-      .bind(like_val);
+/// Content-Security-Policy for fauxmail's own pages. Message bodies are never
+/// inlined into these pages; they load in a sandboxed frame (see `body_csp`).
+pub const PAGE_CSP: &str = "default-src 'self'; script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; img-src 'self' data:; frame-src 'self'; object-src 'none'; base-uri 'none'; form-action 'self'";
+
+/// Display names (falling back to addresses) with the full mailbox as tooltip.
+fn address_cell(list: &[Address], empty: &str) -> String {
+  if list.is_empty() {
+    return empty.to_string();
   }
-  let msgs: Vec<DbEmail> = query
-    .bind(limit as i64)
-    .bind(offset as i64)
-    .fetch_all(&state.db)
+  list
+    .iter()
+    .map(|a| {
+      format!(
+        "<span title=\"{}\">{}</span>",
+        html_escape(&a.address),
+        html_escape(a.name.as_deref().unwrap_or(&a.address))
+      )
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// One row per message, as ordered by the list parameters.
+async fn message_rows(state: &AppState, params: &ListParams) -> String {
+  let msgs = state
+    .store
+    .list_messages(&params.to_query())
     .await
     .unwrap_or_default();
+  let msgs = super::messages::to_api_emails(state, msgs).await;
 
   let mut rows = String::new();
-  for d in msgs.iter() {
-    let subj = d.subject.as_deref().unwrap_or("(no subject)");
-    let from = d.from_addr.as_deref().unwrap_or("(unknown)");
-    let to_list: Vec<String> = serde_json::from_str(&d.to_recipients).unwrap_or_default();
-    let to = if to_list.is_empty() {
-      "(none)".to_string()
-    } else {
-      to_list.join(", ")
-    };
+  for m in msgs.iter() {
+    let subj = html_escape(m.subject.as_deref().unwrap_or("(no subject)"));
+    let from = address_cell(&m.addresses.from, "(unknown)");
+    let to = address_cell(&m.addresses.to, "(none)");
     rows.push_str(&format!(
             "<tr><td><a href=\"/messages/{id}/html\">{id}</a></td><td>{when}</td><td>{from}</td><td>{to}</td><td>{subj}</td></tr>",
-            id = d.id,
-            when = d.received_at
+            id = m.id,
+            when = m.received_at
         ));
   }
+  rows
+}
+
+/// Thread header rows, each followed by its messages indented by reply depth.
+async fn thread_rows(state: &AppState, params: &ListParams) -> String {
+  let threads = threads::load_threads(state, params)
+    .await
+    .unwrap_or_default();
+  let ids: Vec<Uuid> = threads.iter().map(|t| t.id).collect();
+  let messages = threads::load_thread_messages(state, &ids)
+    .await
+    .unwrap_or_default();
+  let mut rows = String::new();
+  for t in &threads {
+    let members: Vec<&ApiEmail> = messages.iter().filter(|m| m.thread_id == t.id).collect();
+    rows.push_str(&format!(
+      "<tr class=\"thread\"><td colspan=\"4\"><a href=\"/threads/{id}\">{subj}</a> ({count})</td><td>{last}</td></tr>",
+      id = t.id,
+      subj = html_escape(t.subject.as_deref().unwrap_or("(no subject)")),
+      count = t.message_count,
+      last = t.last_at
+    ));
+    let depths = threads::reply_depths(members.iter().copied());
+    for (m, depth) in members.iter().zip(depths) {
+      rows.push_str(&format!(
+        "<tr><td style=\"padding-left:{pad}rem\"><a href=\"/messages/{id}/html\">{id}</a></td><td>{when}</td><td>{from}</td><td>{to}</td><td>{subj}</td></tr>",
+        pad = 0.5 + 1.5 * depth as f32,
+        id = m.id,
+        when = m.received_at,
+        from = address_cell(&m.addresses.from, "(unknown)"),
+        to = address_cell(&m.addresses.to, "(none)"),
+        subj = html_escape(m.subject.as_deref().unwrap_or("(no subject)")),
+      ));
+    }
+  }
+  rows
+}
+
+pub async fn ui_index(
+  axum::extract::State(state): axum::extract::State<AppState>,
+  Query(params): Query<ListParams>,
+) -> impl IntoResponse {
+  let threaded = params.view.as_deref() == Some("threads");
+  let rows = if threaded {
+    thread_rows(&state, &params).await
+  } else {
+    message_rows(&state, &params).await
+  };
   let template = r#"<!doctype html>
 <html lang="en">
 <head>
@@ -60,6 +115,7 @@ pub async fn ui_index(
     table { width: 100%; border-collapse: collapse; }
     th, td { border-bottom: 1px solid #ddd; text-align: left; padding: .5rem; }
     .actions { margin: 1rem 0; }
+    tr.thread td { background: #f6f8fa; font-weight: 600; }
     code { background: #f6f8fa; padding: .2rem .4rem; border-radius: 4px; }
     .logs { background:#0b1020; color:#e6edf3; padding:1rem; border-radius:8px; white-space:pre-wrap; font-family: ui-monospace, SFMono-Regular, Menlo, Monaco, Consolas, monospace; font-size: 12px; }
     .lvl-INFO { color:#7ee787; }
@@ -68,6 +124,7 @@ pub async fn ui_index(
     .lvl-DEBUG { color:#79c0ff; }
   </style>
   <script>
+    const esc = s => String(s ?? '').replace(/[&<>"']/g, c => ({'&':'&amp;','<':'&lt;','>':'&gt;','"':'&quot;',"'":'&#39;'}[c]));
     async function clearAll() {
       if (!confirm('Delete all messages?')) return;
       await fetch('/messages', { method: 'DELETE' });
@@ -77,7 +134,7 @@ pub async fn ui_index(
       const res = await fetch('/logs');
       const logs = await res.json();
       const el = document.getElementById('logs');
-      el.innerHTML = logs.map(l => `\n<span class=\"lvl-${l.level}\">[${l.level}]</span> ${l.ts} — ${l.message}`).join('');
+      el.innerHTML = logs.map(l => `\n<span class=\"lvl-${esc(l.level)}\">[${esc(l.level)}]</span> ${esc(l.ts)} — ${l.target ? esc(l.target) + ': ' : ''}${esc(l.message)}`).join('');
     }
     setInterval(loadLogs, 2000);
     window.addEventListener('load', loadLogs);
@@ -87,21 +144,31 @@ pub async fn ui_index(
       const rows = await res.json();
       const tbody = document.getElementById('rows');
       tbody.innerHTML = rows.map(m => {
-        const to = (m.to && m.to.length) ? m.to.join(', ') : '(none)';
-        const subj = m.subject || '(no subject)';
-        const from = m.from || '(unknown)';
-        return `<tr><td><a href=\"/messages/${m.id}/html\">${m.id}</a></td><td>${m.received_at}</td><td>${from}</td><td>${to}</td><td>${subj}</td></tr>`;
+        const names = (list, empty) => list.length
+          ? list.map(a => `<span title="${esc(a.address)}">${esc(a.name || a.address)}</span>`).join(', ')
+          : empty;
+        const to = names(m.addresses.to, '(none)');
+        const subj = esc(m.subject || '(no subject)');
+        const from = names(m.addresses.from, '(unknown)');
+        return `<tr><td><a href=\"/messages/${esc(m.id)}/html\">${esc(m.id)}</a></td><td>${esc(m.received_at)}</td><td>${from}</td><td>${to}</td><td>${subj}</td></tr>`;
       }).join('');
     }
   </script>
   </head>
 <body>
   <h1>fauxmail</h1>
+  <nav><a href="/">Messages</a> · <a href="/?view=threads">Threads</a></nav>
   <div class="actions">
     <button onclick="clearAll()">Clear All</button>
     <input id="q" placeholder="Search subject, from, text" onkeydown="if(event.key==='Enter')doSearch()" />
     <button onclick="doSearch()">Search</button>
   </div>
+  <form class="actions" method="get">
+    <input name="from" placeholder="From name or address" />
+    <input name="to" placeholder="To / Cc / Bcc" />
+    <input type="hidden" name="view" value="__VIEW__" />
+    <button type="submit">Filter</button>
+  </form>
   <p>Send via REST: <code>POST /send</code> JSON {"to":["you@example.com"],"subject":"Hi"}</p>
   <table>
     <thead><tr><th>ID</th><th>Received</th><th>From</th><th>To</th><th>Subject</th></tr></thead>
@@ -112,5 +179,12 @@ pub async fn ui_index(
 </body>
 </html>
 "#;
-  Html(template.replace("__ROWS__", &rows))
+  (
+    [(header::CONTENT_SECURITY_POLICY, PAGE_CSP)],
+    Html(
+      template
+        .replace("__VIEW__", if threaded { "threads" } else { "" })
+        .replace("__ROWS__", &rows),
+    ),
+  )
 }
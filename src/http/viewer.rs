@@ -0,0 +1,359 @@
+//! HTML message viewer and the sandboxed body it frames.
+//!
+//! The viewer page only carries escaped metadata; every tab loads its
+//! content from the JSON/raw endpoints, and the message body is served
+//! separately under `body_csp`.
+
+use crate::{
+  app::AppState,
+  models::email::db_email::DbEmail,
+  util::{find_ascii_ci, html_escape, rewrite_cid_urls},
+};
+use axum::{
+  extract::{Path as AxumPath, Query, State},
+  http::{HeaderMap, StatusCode, header},
+  response::IntoResponse,
+};
+use serde::Deserialize;
+use tracing::error;
+use uuid::Uuid;
+
+/// Message HTML with `cid:` references resolved; text bodies are escaped into `<pre>`.
+async fn render_body(state: &AppState, m: &DbEmail) -> String {
+  let inline = state.store.list_attachments(m.id).await.unwrap_or_default();
+  let cid_urls: std::collections::HashMap<String, String> = inline
+    .into_iter()
+    .filter_map(|a| Some((a.content_id?, format!("/attachments/{}/download", a.id))))
+    .collect();
+  m.html_body
+    .as_deref()
+    .map(|h| rewrite_cid_urls(h, &cid_urls))
+    .or_else(|| {
+      m.text_body
+        .as_deref()
+        .map(|t| format!("<pre>{}</pre>", html_escape(t)))
+    })
+    .unwrap_or_else(|| "<em>No content</em>".to_string())
+}
+
+/// Make links inside the sandboxed frame open in a new window.
+fn with_base_target(html: &str) -> String {
+  const BASE: &str = r#"<base target="_blank">"#;
+  let at = find_ascii_ci(html, "<head")
+    .or_else(|| find_ascii_ci(html, "<!doctype"))
+    .and_then(|start| html[start..].find('>').map(|end| start + end + 1))
+    .unwrap_or(0);
+  format!("{}{BASE}{}", &html[..at], &html[at..])
+}
+
+/// Content-Security-Policy for untrusted message bodies.
+///
+/// The `sandbox` directive applies even when the body URL is opened
+/// directly: scripts, forms and same-origin access are all disabled.
+pub fn body_csp(remote_images: bool) -> String {
+  let img = if remote_images {
+    "'self' data: http: https:"
+  } else {
+    "'self' data:"
+  };
+  format!(
+    "sandbox allow-popups allow-popups-to-escape-sandbox; default-src 'none'; img-src {img}; style-src 'unsafe-inline' http: https:; font-src data: http: https:; base-uri 'none'; form-action 'none'"
+  )
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BodyParams {
+  /// Load remote images (default `true`).
+  pub remote_images: Option<bool>,
+}
+
+/// Raw message body for the viewer's sandboxed iframe.
+pub async fn get_message_body(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+  Query(params): Query<BodyParams>,
+) -> impl IntoResponse {
+  let row = state.store.get_message(id).await;
+  match row {
+    Ok(Some(m)) => {
+      let body = with_base_target(&render_body(&state, &m).await);
+      let mut headers = HeaderMap::new();
+      headers.insert(
+        header::CONTENT_TYPE,
+        "text/html; charset=utf-8".parse().unwrap(),
+      );
+      headers.insert(
+        header::CONTENT_SECURITY_POLICY,
+        body_csp(params.remote_images.unwrap_or(true))
+          .parse()
+          .unwrap(),
+      );
+      headers.insert(header::X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+      headers.insert(header::REFERRER_POLICY, "no-referrer".parse().unwrap());
+      (headers, body).into_response()
+    }
+    Ok(None) => (StatusCode::NOT_FOUND, "message not found").into_response(),
+    Err(e) => {
+      error!("get_message_body error: {e}");
+      (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response()
+    }
+  }
+}
+
+/// Tabbed message viewer.
+pub async fn get_message_html(
+  State(state): State<AppState>,
+  AxumPath(id): AxumPath<Uuid>,
+) -> impl IntoResponse {
+  let row = state.store.get_message(id).await.ok().flatten();
+  let Some(m) = row else {
+    return (StatusCode::NOT_FOUND, "message not found").into_response();
+  };
+  let to: Vec<String> = serde_json::from_str(&m.to_recipients).unwrap_or_default();
+  let to = if to.is_empty() {
+    "(none)".to_string()
+  } else {
+    to.join(", ")
+  };
+  let page = fill_template(VIEWER_TEMPLATE, |name| match name {
+    "ID" => Some(m.id.to_string()),
+    "SUBJECT" => Some(html_escape(m.subject.as_deref().unwrap_or("(no subject)"))),
+    "FROM" => Some(html_escape(m.from_addr.as_deref().unwrap_or("(unknown)"))),
+    "TO" => Some(html_escape(&to)),
+    "WHEN" => Some(m.received_at.to_rfc3339()),
+    _ => None,
+  });
+  let mut headers = HeaderMap::new();
+  headers.insert(
+    header::CONTENT_TYPE,
+    "text/html; charset=utf-8".parse().unwrap(),
+  );
+  headers.insert(
+    header::CONTENT_SECURITY_POLICY,
+    super::ui::PAGE_CSP.parse().unwrap(),
+  );
+  (headers, page).into_response()
+}
+
+/// Substitute `{NAME}` placeholders in a single pass, so a value that itself
+/// looks like a placeholder is left as written. Unknown names stay verbatim.
+fn fill_template(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(start) = rest.find('{') {
+    out.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let filled = rest[1..]
+      .find('}')
+      .and_then(|end| Some((end, value(&rest[1..1 + end])?)));
+    match filled {
+      Some((end, v)) => {
+        out.push_str(&v);
+        rest = &rest[end + 2..];
+      }
+      None => {
+        out.push('{');
+        rest = &rest[1..];
+      }
+    }
+  }
+  out.push_str(rest);
+  out
+}
+
+const VIEWER_TEMPLATE: &str = r#"<!doctype html>
+<html lang="en"><head><meta charset="utf-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<title>{SUBJECT}</title>
+<style>
+  body { font-family: system-ui, sans-serif; margin: 1.5rem; }
+  .tabs { margin: 1rem 0; display: flex; flex-wrap: wrap; gap: .25rem; }
+  .tabs button.active { font-weight: bold; }
+  .toolbar { display: flex; flex-wrap: wrap; gap: .5rem; align-items: center; margin-bottom: .5rem; }
+  .toolbar button.active { font-weight: bold; }
+  .mono, pre, #session-lines td, .parts { font-family: ui-monospace, Menlo, Consolas, monospace; font-size: 12px; }
+  pre { white-space: pre-wrap; word-break: break-word; background: #f6f8fa; padding: 1rem; border-radius: 6px; }
+  #frame-wrap { background: #eef0f3; padding: .5rem; }
+  #body-frame { display: block; margin: 0 auto; width: 100%; height: 70vh; border: 1px solid #ddd; background: #fff; resize: vertical; }
+  #headers-table td { padding: .15rem .5rem; vertical-align: top; }
+  #headers-table td:first-child { font-weight: bold; white-space: nowrap; }
+  #session-lines td { padding: .1rem .5rem; vertical-align: top; white-space: pre-wrap; }
+  #session-lines .dir-server { color: #555; }
+  .compat-unsupported { color: #b00020; }
+  .compat-partial { color: #8a6d00; }
+  .att { border-top: 1px solid #ddd; padding: .75rem 0; }
+  .att img { display: block; max-width: 100%; max-height: 400px; border: 1px solid #ddd; margin: .5rem 0; }
+  .att iframe { display: block; width: 100%; height: 60vh; border: 1px solid #ddd; margin: .5rem 0; }
+</style>
+</head>
+<body>
+  <p><a href="/">← back</a></p>
+  <h2>{SUBJECT}</h2>
+  <p><strong>From:</strong> {FROM} &nbsp; <strong>To:</strong> {TO} &nbsp; <strong>Received:</strong> {WHEN}</p>
+  <nav class="tabs">
+    <button type="button" data-tab="html" class="active">HTML</button>
+    <button type="button" data-tab="text">Plain text</button>
+    <button type="button" data-tab="headers">Headers</button>
+    <button type="button" data-tab="source">Source</button>
+    <button type="button" data-tab="compat">Compatibility</button>
+    <button type="button" data-tab="parts">MIME parts</button>
+    <button type="button" data-tab="attachments">Attachments</button>
+    <button type="button" data-tab="session">Session</button>
+  </nav>
+  <section id="tab-html" class="tab">
+    <div class="toolbar">
+      <span>Width:</span>
+      <button type="button" data-width="375px">Mobile</button>
+      <button type="button" data-width="768px">Tablet</button>
+      <button type="button" data-width="100%" class="active">Desktop</button>
+      <label><input type="checkbox" id="block-remote" /> Block remote images</label>
+    </div>
+    <div id="frame-wrap">
+      <iframe id="body-frame" title="Message body" sandbox="allow-popups allow-popups-to-escape-sandbox" referrerpolicy="no-referrer"></iframe>
+    </div>
+  </section>
+  <section id="tab-text" class="tab" hidden><pre id="text-body"></pre></section>
+  <section id="tab-headers" class="tab" hidden><table id="headers-table" class="mono"></table></section>
+  <section id="tab-source" class="tab" hidden>
+    <p><a href="/messages/{ID}/raw" download="{ID}.eml">Download .eml</a></p>
+    <pre id="source"></pre>
+  </section>
+  <section id="tab-compat" class="tab" hidden><div id="compat"></div></section>
+  <section id="tab-parts" class="tab" hidden><ul id="parts-tree" class="parts"></ul></section>
+  <section id="tab-attachments" class="tab" hidden><div id="atts"></div></section>
+  <section id="tab-session" class="tab" hidden>
+    <p id="session-meta"></p>
+    <table id="session-lines"></table>
+  </section>
+  <script>
+    const ID = '{ID}';
+    const el = (tag, text, attrs) => {
+      const e = document.createElement(tag);
+      if (text !== undefined) e.textContent = text;
+      Object.assign(e, attrs || {});
+      return e;
+    };
+
+    const frame = document.getElementById('body-frame');
+    const blockRemote = document.getElementById('block-remote');
+    const showBody = () => {
+      localStorage.setItem('fauxmail.blockRemote', blockRemote.checked ? '1' : '');
+      frame.src = `/messages/${ID}/body?remote_images=${!blockRemote.checked}`;
+    };
+    blockRemote.checked = !!localStorage.getItem('fauxmail.blockRemote');
+    blockRemote.addEventListener('change', showBody);
+    showBody();
+    document.querySelectorAll('[data-width]').forEach(b => b.addEventListener('click', () => {
+      document.querySelectorAll('[data-width]').forEach(x => x.classList.toggle('active', x === b));
+      frame.style.width = b.dataset.width;
+    }));
+
+    const loaders = {
+      async text() {
+        const res = await fetch(`/messages/${ID}`);
+        const { message } = await res.json();
+        document.getElementById('text-body').textContent = message.text ?? '(no plain-text alternative)';
+      },
+      async headers() {
+        const res = await fetch(`/messages/${ID}/headers`);
+        const rows = await res.json();
+        document.getElementById('headers-table').replaceChildren(...rows.map(h => {
+          const tr = el('tr');
+          tr.append(el('td', h.name), el('td', h.value));
+          return tr;
+        }));
+      },
+      async source() {
+        const res = await fetch(`/messages/${ID}/raw`);
+        document.getElementById('source').textContent = res.ok ? await res.text() : 'No raw source stored (message was posted as JSON).';
+      },
+      async compat() {
+        const target = document.getElementById('compat');
+        const report = await (await fetch(`/messages/${ID}/html-check`)).json();
+        if (!report.has_html) { target.textContent = 'No HTML body.'; return; }
+        target.replaceChildren(...report.clients.map(c => {
+          const div = el('div');
+          div.appendChild(el('h3', `${c.label}: ${c.unsupported} unsupported, ${c.partial} partial`));
+          if (!c.issues.length) { div.appendChild(el('p', 'No known problems.')); return div; }
+          const ul = el('ul');
+          c.issues.forEach(i => {
+            const li = el('li');
+            li.append(
+              el('strong', i.support, { className: 'compat-' + i.support }),
+              el('span', ` ${i.title} (line${i.lines.length > 1 ? 's' : ''} ${i.lines.join(', ')})${i.note ? ' - ' + i.note : ''}`),
+            );
+            ul.appendChild(li);
+          });
+          div.appendChild(ul);
+          return div;
+        }));
+      },
+      async parts() {
+        const target = document.getElementById('parts-tree');
+        const res = await fetch(`/messages/${ID}/parts`);
+        if (!res.ok) { target.textContent = 'No MIME structure stored for this message.'; return; }
+        const node = p => {
+          const bits = [p.path, p.content_type];
+          if (p.charset) bits.push('charset=' + p.charset);
+          if (p.transfer_encoding) bits.push(p.transfer_encoding);
+          if (p.disposition) bits.push(p.disposition);
+          if (p.content_id) bits.push('cid:' + p.content_id);
+          if (p.filename) bits.push('"' + p.filename + '"');
+          if (!p.children.length) bits.push(p.size + ' bytes');
+          const li = el('li');
+          li.appendChild(p.children.length
+            ? el('span', bits.join(' · '))
+            : el('a', bits.join(' · '), { href: `/parts/${p.id}/download`, target: '_blank' }));
+          if (p.children.length) {
+            const ul = el('ul');
+            p.children.forEach(c => ul.appendChild(node(c)));
+            li.appendChild(ul);
+          }
+          return li;
+        };
+        target.replaceChildren(node(await res.json()));
+      },
+      async attachments() {
+        const target = document.getElementById('atts');
+        const res = await fetch(`/messages/${ID}/attachments`);
+        const atts = await res.json();
+        if (!atts.length) { target.textContent = 'None'; return; }
+        target.replaceChildren(...atts.map(a => {
+          const url = `/attachments/${a.id}/download`;
+          const div = el('div', undefined, { className: 'att' });
+          const label = `${a.filename || '(unnamed)'} · ${a.content_type} · ${a.size} bytes${a.inline ? ' · inline' : ''}`;
+          div.appendChild(el('a', label, { href: url, target: '_blank' }));
+          if (a.content_type.startsWith('image/')) {
+            div.appendChild(el('img', undefined, { src: url, alt: a.filename || '' }));
+          } else if (a.content_type === 'application/pdf') {
+            div.appendChild(el('iframe', undefined, { src: url, title: a.filename || 'PDF' }));
+          } else if (a.content_type.startsWith('text/') && a.size <= 262144) {
+            const pre = el('pre');
+            fetch(url).then(r => r.text()).then(t => { pre.textContent = t; });
+            div.appendChild(pre);
+          }
+          return div;
+        }));
+      },
+      async session() {
+        const meta = document.getElementById('session-meta');
+        const res = await fetch(`/messages/${ID}/session`);
+        if (!res.ok) { meta.textContent = 'No SMTP session recorded for this message.'; return; }
+        const s = await res.json();
+        meta.textContent = `Client ${s.client_addr} · HELO ${s.helo || '(none)'} · AUTH ${s.auth_user || '(none)'} · ${s.started_at} → ${s.ended_at || 'open'}`;
+        document.getElementById('session-lines').replaceChildren(...s.transcript.map(l => {
+          const tr = el('tr', undefined, { className: 'dir-' + l.dir });
+          tr.append(el('td', l.ts), el('td', l.dir === 'client' ? 'C:' : 'S:'), el('td', l.line));
+          return tr;
+        }));
+      },
+    };
+    const loaded = new Set();
+    document.querySelectorAll('.tabs button').forEach(b => b.addEventListener('click', () => {
+      const tab = b.dataset.tab;
+      document.querySelectorAll('.tabs button').forEach(x => x.classList.toggle('active', x === b));
+      document.querySelectorAll('.tab').forEach(t => { t.hidden = t.id !== 'tab-' + tab; });
+      if (loaders[tab] && !loaded.has(tab)) { loaded.add(tab); loaders[tab](); }
+    }));
+  </script>
+</body></html>"#;
@@ -0,0 +1,117 @@
+//! Long-poll for a message matching the list filters.
+
+use crate::{
+  analysis::codes,
+  app::AppState,
+  http::messages::to_api_emails,
+  models::{
+    analysis::codes_report::CodesReport, email::db_email::DbEmail,
+    response::wait_result::WaitResult,
+  },
+  store::{MessageFilter, MessageQuery},
+};
+use axum::{
+  Json,
+  extract::{Query, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::error;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const MAX_TIMEOUT_SECS: u64 = 120;
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const PAGE_SIZE: u32 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct WaitParams {
+  pub q: Option<String>,
+  pub from: Option<String>,
+  pub to: Option<String>,
+  /// Only consider messages received after this instant.
+  pub since: Option<DateTime<Utc>>,
+  /// Seconds to wait before giving up (default 30, max 120).
+  pub timeout: Option<u64>,
+  /// Only match messages with at least one extracted code.
+  pub has_code: Option<bool>,
+}
+
+/// Newest match, paging back through the list until `since` is passed.
+async fn find_match(
+  state: &AppState,
+  params: &WaitParams,
+  mut query: MessageQuery,
+) -> Result<Option<(DbEmail, CodesReport)>, sqlx::Error> {
+  loop {
+    let rows = state.store.list_messages(&query).await?;
+    let last_page = rows.len() < PAGE_SIZE as usize;
+    for m in rows {
+      if params.since.is_some_and(|since| m.received_at <= since) {
+        return Ok(None);
+      }
+      let found = codes::extract(&m);
+      if !params.has_code.unwrap_or(false) || !found.codes.is_empty() {
+        return Ok(Some((m, found)));
+      }
+    }
+    if last_page {
+      return Ok(None);
+    }
+    query.offset += PAGE_SIZE;
+  }
+}
+
+/// Newest message matching the filters, waiting for one to arrive if needed.
+/// 404 once the timeout passes without a match.
+pub async fn wait_for_message(
+  State(state): State<AppState>,
+  Query(params): Query<WaitParams>,
+) -> impl IntoResponse {
+  let query = MessageQuery {
+    filter: MessageFilter {
+      q: params.q.clone(),
+      from: params.from.clone(),
+      to: params.to.clone(),
+    },
+    limit: Some(PAGE_SIZE),
+    ..Default::default()
+  };
+  let timeout = Duration::from_secs(
+    params
+      .timeout
+      .unwrap_or(DEFAULT_TIMEOUT_SECS)
+      .min(MAX_TIMEOUT_SECS),
+  );
+  let deadline = Instant::now() + timeout;
+
+  loop {
+    let hit = match find_match(&state, &params, query.clone()).await {
+      Ok(hit) => hit,
+      Err(e) => {
+        error!("wait_for_message error: {e}");
+        return (StatusCode::INTERNAL_SERVER_ERROR, "db error").into_response();
+      }
+    };
+    if let Some((m, found)) = hit {
+      let message = to_api_emails(&state, vec![m]).await.remove(0);
+      return Json(WaitResult {
+        message,
+        codes: found,
+      })
+      .into_response();
+    }
+    if Instant::now() + POLL_INTERVAL > deadline {
+      return (StatusCode::NOT_FOUND, "no matching message").into_response();
+    }
+    tokio::select! {
+      _ = tokio::time::sleep(POLL_INTERVAL) => {}
+      _ = state.shutdown.cancelled() => {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response();
+      }
+    }
+  }
+}
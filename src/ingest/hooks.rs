@@ -0,0 +1,33 @@
+//! Extension points run by the ingest pipeline.
+
+use super::Source;
+use crate::{app::AppState, models::email::new_message::NewMessage};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// A step of the ingest pipeline; analysis, rules and webhooks plug in here.
+#[async_trait]
+pub trait Hook: Send + Sync {
+  /// Runs on the parsed message before anything is stored. It may amend the
+  /// message, or reject it with a reason the client gets to see.
+  async fn before_store(&self, _msg: &mut NewMessage, _source: Source) -> Result<(), String> {
+    Ok(())
+  }
+
+  /// Runs once the message is committed.
+  async fn after_store(&self, _state: &AppState, _msg: &NewMessage, _source: Source) {}
+}
+
+/// Hooks in registration order.
+#[derive(Clone, Default)]
+pub struct Hooks(Vec<Arc<dyn Hook>>);
+
+impl Hooks {
+  pub fn push(&mut self, hook: impl Hook + 'static) {
+    self.0.push(Arc::new(hook));
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn Hook>> {
+    self.0.iter()
+  }
+}
@@ -0,0 +1,244 @@
+//! Message ingestion shared by SMTP, `/send`, `/send/raw` and `/import`.
+//!
+//! Every path builds a [`NewMessage`] (raw sources are parsed exactly once),
+//! runs the registered [`Hook`]s, moves bodies to the blob store if one is
+//! configured, and stores the message with its recipients, parts and
+//! attachments in a single transaction.
+
+pub mod hooks;
+pub mod webhook;
+
+use crate::{
+  app::AppState,
+  blob,
+  http::{logs::log_db, messages::SendRequest},
+  models::email::new_message::NewMessage,
+  util::{
+    collect_addresses, collect_attachments, collect_headers, collect_mail_addresses,
+    extract_bodies, mime::collect_parts, thread_headers,
+  },
+};
+use chrono::Utc;
+use mailparse::parse_mail;
+use std::{collections::HashMap, time::Instant};
+use uuid::Uuid;
+
+pub use hooks::{Hook, Hooks};
+pub use webhook::Webhook;
+
+/// Where a message entered fauxmail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+  Smtp,
+  Json,
+  Raw,
+  Import,
+}
+
+impl Source {
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Source::Smtp => "smtp",
+      Source::Json => "json",
+      Source::Raw => "raw",
+      Source::Import => "import",
+    }
+  }
+}
+
+/// SMTP envelope and session details of a message received over SMTP.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+  pub session_id: Uuid,
+  pub helo: Option<String>,
+  pub client_addr: String,
+  pub mail_from: Option<String>,
+  pub rcpt_to: Vec<String>,
+}
+
+/// Why a message was not stored.
+#[derive(Debug)]
+pub enum IngestError {
+  Parse(mailparse::MailParseError),
+  /// A hook refused the message.
+  Rejected(String),
+  Db(sqlx::Error),
+  Blob(std::io::Error),
+}
+
+impl std::fmt::Display for IngestError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      IngestError::Parse(e) => write!(f, "parse error: {e}"),
+      IngestError::Rejected(reason) => write!(f, "rejected: {reason}"),
+      IngestError::Db(e) => write!(f, "db error: {e}"),
+      IngestError::Blob(e) => write!(f, "blob store error: {e}"),
+    }
+  }
+}
+
+/// Parse an RFC 822 message. Header addresses win; the SMTP envelope only
+/// fills in a missing From/To and supplies Bcc recipients.
+pub fn parse_raw(raw: Vec<u8>, envelope: Option<Envelope>) -> Result<NewMessage, IngestError> {
+  let parsed = parse_mail(&raw).map_err(IngestError::Parse)?;
+  let headers = collect_headers(&parsed);
+  let (text, html) = extract_bodies(&parsed);
+  let envelope_to = envelope
+    .as_ref()
+    .map(|e| e.rcpt_to.clone())
+    .unwrap_or_default();
+  let addresses = collect_mail_addresses(&parsed, &envelope_to);
+  let to: Vec<String> = if addresses.to.is_empty() {
+    envelope_to.clone()
+  } else {
+    addresses.to.iter().map(|a| a.to_string()).collect()
+  };
+  let envelope_from = envelope.as_ref().and_then(|e| e.mail_from.clone());
+  // Re-serialized so later parsing of the stored string sees quoted names.
+  let from = if addresses.from.is_empty() {
+    headers.get("from").cloned()
+  } else {
+    Some(
+      addresses
+        .from
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(", "),
+    )
+  }
+  .or(envelope_from.clone());
+  let mut attachments = Vec::new();
+  collect_attachments(&parsed, &mut attachments);
+
+  Ok(NewMessage {
+    id: Uuid::new_v4(),
+    received_at: Utc::now(),
+    from,
+    to,
+    subject: headers.get("subject").cloned(),
+    text,
+    html,
+    raw_len: raw.len() as i64,
+    session_id: envelope.as_ref().map(|e| e.session_id),
+    envelope_from,
+    envelope_to,
+    helo: envelope.as_ref().and_then(|e| e.helo.clone()),
+    client_addr: envelope.map(|e| e.client_addr),
+    thread: thread_headers(&headers),
+    addresses,
+    parts: collect_parts(&parsed),
+    attachments,
+    headers,
+    raw: Some(raw),
+    raw_sha256: None,
+  })
+}
+
+/// Build a message from a `/send` request; explicit fields win over headers
+/// of the same name.
+pub fn from_json(req: &SendRequest) -> NewMessage {
+  let mut lower: HashMap<String, String> = req
+    .headers
+    .iter()
+    .map(|(k, v)| (k.to_ascii_lowercase(), v.clone()))
+    .collect();
+  if let Some(from) = &req.from {
+    lower.insert("from".into(), from.clone());
+  }
+  lower.insert("to".into(), req.to.join(", "));
+
+  NewMessage {
+    id: Uuid::new_v4(),
+    received_at: Utc::now(),
+    from: req.from.clone(),
+    to: req.to.clone(),
+    subject: req.subject.clone(),
+    text: req.text.clone(),
+    html: req.html.clone(),
+    headers: req.headers.clone(),
+    raw_len: 0,
+    raw: None,
+    raw_sha256: None,
+    session_id: None,
+    envelope_from: None,
+    envelope_to: Vec::new(),
+    helo: None,
+    client_addr: None,
+    thread: thread_headers(&lower),
+    addresses: collect_addresses(&lower, &[]),
+    parts: Vec::new(),
+    attachments: Vec::new(),
+  }
+}
+
+/// Parse and store a raw message.
+pub async fn ingest_raw(
+  state: &AppState,
+  source: Source,
+  raw: Vec<u8>,
+  envelope: Option<Envelope>,
+) -> Result<Uuid, IngestError> {
+  let started = Instant::now();
+  let msg = parse_raw(raw, envelope)?;
+  commit(state, source, msg, started).await
+}
+
+/// Run the hooks over `msg` and store it.
+pub async fn ingest(
+  state: &AppState,
+  source: Source,
+  msg: NewMessage,
+) -> Result<Uuid, IngestError> {
+  commit(state, source, msg, Instant::now()).await
+}
+
+async fn commit(
+  state: &AppState,
+  source: Source,
+  mut msg: NewMessage,
+  started: Instant,
+) -> Result<Uuid, IngestError> {
+  for hook in state.hooks.iter() {
+    hook
+      .before_store(&mut msg, source)
+      .await
+      .map_err(IngestError::Rejected)?;
+  }
+  let created = match &state.blobs {
+    Some(blobs) => blob::offload(blobs, &mut msg)
+      .await
+      .map_err(IngestError::Blob)?,
+    None => Vec::new(),
+  };
+  if let Err(e) = state.store.insert_message(&msg).await {
+    if let Some(blobs) = &state.blobs {
+      blobs.discard(&created).await;
+    }
+    return Err(IngestError::Db(e));
+  }
+  state
+    .metrics
+    .message_ingested(source, &msg, started.elapsed());
+
+  // Imports log one summary line for the whole archive instead.
+  let via = match source {
+    Source::Smtp => Some("SMTP"),
+    Source::Json => Some("REST"),
+    Source::Raw => Some("EML"),
+    Source::Import => None,
+  };
+  if let Some(via) = via {
+    log_db(
+      state,
+      "INFO",
+      &format!("stored message via {via}: {}", msg.id),
+    )
+    .await
+    .ok();
+  }
+  for hook in state.hooks.iter() {
+    hook.after_store(state, &msg, source).await;
+  }
+  Ok(msg.id)
+}
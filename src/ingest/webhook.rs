@@ -0,0 +1,65 @@
+//! Hook that POSTs a summary of every stored message to a URL.
+
+use super::{Hook, Source};
+use crate::{
+  app::AppState,
+  models::{email::new_message::NewMessage, response::webhook_event::WebhookEvent},
+};
+use async_trait::async_trait;
+use std::time::Duration;
+use tracing::warn;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Webhook {
+  url: String,
+  client: reqwest::Client,
+}
+
+impl Webhook {
+  pub fn new(url: impl Into<String>) -> Self {
+    Webhook {
+      url: url.into(),
+      client: reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .unwrap_or_default(),
+    }
+  }
+
+  /// Configured by `FAUXMAIL_WEBHOOK_URL`.
+  pub fn from_env() -> Option<Self> {
+    std::env::var("FAUXMAIL_WEBHOOK_URL")
+      .ok()
+      .map(|v| v.trim().to_string())
+      .filter(|v| !v.is_empty())
+      .map(Webhook::new)
+  }
+}
+
+#[async_trait]
+impl Hook for Webhook {
+  /// Delivered in the background so a slow receiver never delays the client.
+  async fn after_store(&self, _state: &AppState, msg: &NewMessage, source: Source) {
+    let event = WebhookEvent {
+      event: "message.stored",
+      id: msg.id,
+      source: source.as_str(),
+      received_at: msg.received_at,
+      from: msg.from.clone(),
+      to: msg.to.clone(),
+      subject: msg.subject.clone(),
+    };
+    let request = self.client.post(&self.url).json(&event);
+    let url = self.url.clone();
+    tokio::spawn(async move {
+      match request.send().await {
+        Ok(res) if !res.status().is_success() => {
+          warn!("webhook {url} answered {}", res.status())
+        }
+        Ok(_) => {}
+        Err(e) => warn!("webhook {url} error: {e}"),
+      }
+    });
+  }
+}
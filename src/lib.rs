@@ -1,16 +1,32 @@
 //! fauxmail library entrypoint.
 //!
 //! Modules:
+//! - `analysis`: offline checks over stored messages
 //! - `app`: startup, configuration, shared state
+//! - `auth`: optional API tokens, scopes and dashboard login
+//! - `blob`: content-addressed files for attachment bodies and raw sources
 //! - `http`: Axum router and handlers
+//! - `ingest`: parsing, hooks and atomic storage of incoming messages
 //! - `smtp`: lightweight SMTP listener (local dev)
 //! - `db`: migrations and SQLite helpers
+//! - `logging`: `tracing` layer that persists events to the logs table
+//! - `metrics`: counters and histograms served at `/metrics`
 //! - `models`: typed records used across layers
+//! - `retention`: limits and background purging of old messages
+//! - `store`: storage backends behind the `MessageStore` trait
 //! - `util`: helpers for parsing and HTML escaping
 
+pub mod analysis;
 pub mod app;
+pub mod auth;
+pub mod blob;
 pub mod db;
 pub mod http;
+pub mod ingest;
+pub mod logging;
+pub mod metrics;
 pub mod models;
+pub mod retention;
 pub mod smtp;
+pub mod store;
 pub mod util;
@@ -0,0 +1,107 @@
+//! Persist `tracing` events to the logs table.
+//!
+//! [`DbLayer`] hands events to a bounded channel; [`spawn_writer`] drains it
+//! into the store once one is open. Events from sqlx and from the writer
+//! itself are skipped so storing a log line never logs again.
+
+use crate::{app::AppState, models::log::new_log_entry::NewLogEntry};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::{
+  Event, Subscriber,
+  field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+/// Events waiting to be written; more are dropped rather than blocking.
+const QUEUE: usize = 4096;
+
+/// Target of the writer's own diagnostics.
+const WRITER_TARGET: &str = "fauxmail::logging::writer";
+
+pub struct DbLayer {
+  tx: mpsc::Sender<NewLogEntry>,
+}
+
+/// A layer plus the receiving end to pass to [`spawn_writer`].
+pub fn layer() -> (DbLayer, mpsc::Receiver<NewLogEntry>) {
+  let (tx, rx) = mpsc::channel(QUEUE);
+  (DbLayer { tx }, rx)
+}
+
+impl<S: Subscriber> Layer<S> for DbLayer {
+  fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    let meta = event.metadata();
+    let target = meta.target();
+    if target == "sqlx" || target.starts_with("sqlx::") || target == WRITER_TARGET {
+      return;
+    }
+    let mut fields = FieldVisitor::default();
+    event.record(&mut fields);
+    let entry = NewLogEntry {
+      ts: Utc::now(),
+      level: meta.level().to_string(),
+      target: Some(target.to_string()),
+      message: fields.message.unwrap_or_default(),
+      fields_json: (!fields.fields.is_empty()).then(|| Value::Object(fields.fields).to_string()),
+    };
+    let _ = self.tx.try_send(entry);
+  }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+  message: Option<String>,
+  fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+  fn put(&mut self, field: &Field, value: Value) {
+    if field.name() == "message" {
+      self.message = Some(match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+      });
+    } else {
+      self.fields.insert(field.name().to_string(), value);
+    }
+  }
+}
+
+impl Visit for FieldVisitor {
+  fn record_str(&mut self, field: &Field, value: &str) {
+    self.put(field, Value::from(value));
+  }
+
+  fn record_i64(&mut self, field: &Field, value: i64) {
+    self.put(field, Value::from(value));
+  }
+
+  fn record_u64(&mut self, field: &Field, value: u64) {
+    self.put(field, Value::from(value));
+  }
+
+  fn record_f64(&mut self, field: &Field, value: f64) {
+    self.put(field, Value::from(value));
+  }
+
+  fn record_bool(&mut self, field: &Field, value: bool) {
+    self.put(field, Value::from(value));
+  }
+
+  fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+    self.put(field, Value::from(format!("{value:?}")));
+  }
+}
+
+/// Write queued events to the store for the life of the process.
+pub fn spawn_writer(state: AppState, mut rx: mpsc::Receiver<NewLogEntry>) {
+  tokio::spawn(async move {
+    while let Some(entry) = rx.recv().await {
+      if let Err(e) = state.store.append_log(&entry).await {
+        tracing::warn!(target: WRITER_TARGET, "log write error: {e}");
+      }
+    }
+  });
+}
@@ -0,0 +1,279 @@
+//! In-process counters rendered in the Prometheus text format at `/metrics`.
+
+use crate::{ingest::Source, models::email::new_message::NewMessage, store::StoreStats};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt::Write,
+  sync::{
+    Mutex,
+    atomic::{AtomicU64, Ordering},
+  },
+  time::Duration,
+};
+
+/// Upper bounds (seconds) of the ingest latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+  0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Distinct recipient domains labelled on their own; any further domains are
+/// counted under `other` so a run over random domains can't grow the series
+/// without bound.
+pub const MAX_DOMAIN_LABELS: usize = 50;
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+  smtp_connections: AtomicU64,
+  smtp_auth_failures: AtomicU64,
+  attachment_bytes: AtomicU64,
+  inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+  received: BTreeMap<&'static str, u64>,
+  received_by_domain: BTreeMap<String, u64>,
+  received_by_other_domain: u64,
+  smtp_replies: BTreeMap<String, u64>,
+  ingest_latency: BTreeMap<&'static str, Histogram>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+  /// Per-bucket (non-cumulative) counts; the last slot is `+Inf`.
+  buckets: [u64; LATENCY_BUCKETS.len() + 1],
+  sum: f64,
+  count: u64,
+}
+
+impl Histogram {
+  fn observe(&mut self, secs: f64) {
+    let slot = LATENCY_BUCKETS
+      .iter()
+      .position(|le| secs <= *le)
+      .unwrap_or(LATENCY_BUCKETS.len());
+    self.buckets[slot] += 1;
+    self.sum += secs;
+    self.count += 1;
+  }
+}
+
+impl Metrics {
+  fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+    self.inner.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  pub fn smtp_connection(&self) {
+    self.smtp_connections.fetch_add(1, Ordering::Relaxed);
+  }
+
+  /// Count the final line of an SMTP reply; `535` also counts as an AUTH failure.
+  pub fn smtp_reply(&self, line: &str) {
+    if line.as_bytes().get(3) == Some(&b'-') {
+      return;
+    }
+    let code = line.get(..3).unwrap_or(line);
+    if code == "535" {
+      self.smtp_auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+    *self
+      .lock()
+      .smtp_replies
+      .entry(code.to_string())
+      .or_default() += 1;
+  }
+
+  /// Record a stored message: its path, recipient domains, attachment bytes
+  /// and how long ingestion took.
+  pub fn message_ingested(&self, source: Source, msg: &NewMessage, elapsed: Duration) {
+    let attachment_bytes: u64 = msg.attachments.iter().map(|a| a.data.len() as u64).sum();
+    self
+      .attachment_bytes
+      .fetch_add(attachment_bytes, Ordering::Relaxed);
+    let domains: BTreeSet<String> = ["to", "cc", "bcc"]
+      .into_iter()
+      .flat_map(|kind| msg.addresses.get(kind).into_iter().flatten())
+      .filter_map(|a| a.address.rsplit_once('@'))
+      .map(|(_, domain)| domain.to_ascii_lowercase())
+      .collect();
+
+    let mut inner = self.lock();
+    *inner.received.entry(source.as_str()).or_default() += 1;
+    let mut overflow = false;
+    for domain in domains {
+      if let Some(n) = inner.received_by_domain.get_mut(&domain) {
+        *n += 1;
+      } else if inner.received_by_domain.len() < MAX_DOMAIN_LABELS {
+        inner.received_by_domain.insert(domain, 1);
+      } else {
+        overflow = true;
+      }
+    }
+    if overflow {
+      inner.received_by_other_domain += 1;
+    }
+    inner
+      .ingest_latency
+      .entry(source.as_str())
+      .or_default()
+      .observe(elapsed.as_secs_f64());
+  }
+
+  /// Everything in the Prometheus text exposition format.
+  pub fn render(&self, stats: Option<&StoreStats>) -> String {
+    let mut out = String::new();
+    let inner = self.lock();
+
+    header(
+      &mut out,
+      "fauxmail_messages_received_total",
+      "counter",
+      "Messages stored, by ingest path.",
+    );
+    for (source, n) in &inner.received {
+      let _ = writeln!(
+        out,
+        "fauxmail_messages_received_total{{source=\"{source}\"}} {n}"
+      );
+    }
+    header(
+      &mut out,
+      "fauxmail_messages_received_by_domain_total",
+      "counter",
+      "Messages stored, by To/Cc/Bcc recipient domain (capped; the rest as other).",
+    );
+    for (domain, n) in &inner.received_by_domain {
+      let _ = writeln!(
+        out,
+        "fauxmail_messages_received_by_domain_total{{domain=\"{}\"}} {n}",
+        escape(domain)
+      );
+    }
+    if inner.received_by_other_domain > 0 {
+      let _ = writeln!(
+        out,
+        "fauxmail_messages_received_by_domain_total{{domain=\"other\"}} {}",
+        inner.received_by_other_domain
+      );
+    }
+
+    header(
+      &mut out,
+      "fauxmail_ingest_duration_seconds",
+      "histogram",
+      "Time from receipt to commit of a message.",
+    );
+    for (source, h) in &inner.ingest_latency {
+      let mut cumulative = 0;
+      for (le, n) in LATENCY_BUCKETS.iter().zip(&h.buckets) {
+        cumulative += n;
+        let _ = writeln!(
+          out,
+          "fauxmail_ingest_duration_seconds_bucket{{source=\"{source}\",le=\"{le}\"}} {cumulative}"
+        );
+      }
+      let _ = writeln!(
+        out,
+        "fauxmail_ingest_duration_seconds_bucket{{source=\"{source}\",le=\"+Inf\"}} {}",
+        h.count
+      );
+      let _ = writeln!(
+        out,
+        "fauxmail_ingest_duration_seconds_sum{{source=\"{source}\"}} {}",
+        h.sum
+      );
+      let _ = writeln!(
+        out,
+        "fauxmail_ingest_duration_seconds_count{{source=\"{source}\"}} {}",
+        h.count
+      );
+    }
+
+    header(
+      &mut out,
+      "fauxmail_smtp_connections_total",
+      "counter",
+      "SMTP connections accepted.",
+    );
+    let _ = writeln!(
+      out,
+      "fauxmail_smtp_connections_total {}",
+      self.smtp_connections.load(Ordering::Relaxed)
+    );
+    header(
+      &mut out,
+      "fauxmail_smtp_auth_failures_total",
+      "counter",
+      "Rejected SMTP AUTH attempts.",
+    );
+    let _ = writeln!(
+      out,
+      "fauxmail_smtp_auth_failures_total {}",
+      self.smtp_auth_failures.load(Ordering::Relaxed)
+    );
+    header(
+      &mut out,
+      "fauxmail_smtp_replies_total",
+      "counter",
+      "SMTP replies sent, by reply code.",
+    );
+    for (code, n) in &inner.smtp_replies {
+      let _ = writeln!(
+        out,
+        "fauxmail_smtp_replies_total{{code=\"{}\"}} {n}",
+        escape(code)
+      );
+    }
+
+    header(
+      &mut out,
+      "fauxmail_attachment_bytes_received_total",
+      "counter",
+      "Attachment bytes in stored messages.",
+    );
+    let _ = writeln!(
+      out,
+      "fauxmail_attachment_bytes_received_total {}",
+      self.attachment_bytes.load(Ordering::Relaxed)
+    );
+
+    if let Some(stats) = stats {
+      header(
+        &mut out,
+        "fauxmail_messages",
+        "gauge",
+        "Messages currently stored.",
+      );
+      let _ = writeln!(out, "fauxmail_messages {}", stats.messages);
+      header(
+        &mut out,
+        "fauxmail_attachment_bytes",
+        "gauge",
+        "Attachment bytes currently stored.",
+      );
+      let _ = writeln!(out, "fauxmail_attachment_bytes {}", stats.attachment_bytes);
+      if let Some(db_bytes) = stats.db_bytes {
+        header(
+          &mut out,
+          "fauxmail_database_size_bytes",
+          "gauge",
+          "Size of the database.",
+        );
+        let _ = writeln!(out, "fauxmail_database_size_bytes {db_bytes}");
+      }
+    }
+    out
+  }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+  let _ = writeln!(out, "# HELP {name} {help}");
+  let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Escape a label value.
+fn escape(v: &str) -> String {
+  v.replace('\\', "\\\\")
+    .replace('"', "\\\"")
+    .replace('\n', "\\n")
+}
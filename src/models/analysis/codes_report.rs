@@ -0,0 +1,36 @@
+//! One-time passcodes and magic links found in a message.
+
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeSource {
+  Subject,
+  Text,
+  Html,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FoundCode {
+  pub code: String,
+  pub source: CodeSource,
+  /// Index of the pattern that matched; lower is more specific.
+  pub pattern: usize,
+}
+
+/// A link that looks like a sign-in, confirmation or reset link.
+#[derive(Debug, Serialize)]
+pub struct MagicLink {
+  pub url: String,
+  /// Value of a token-like query parameter, when there is one.
+  pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CodesReport {
+  pub message_id: Uuid,
+  /// Most likely code first.
+  pub codes: Vec<FoundCode>,
+  pub magic_links: Vec<MagicLink>,
+}
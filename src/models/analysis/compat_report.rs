@@ -0,0 +1,61 @@
+//! HTML/CSS compatibility report for one message.
+
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Email client families the compatibility database covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Client {
+  Outlook,
+  Gmail,
+  AppleMail,
+}
+
+impl Client {
+  pub const ALL: [Client; 3] = [Client::Outlook, Client::Gmail, Client::AppleMail];
+
+  pub fn label(self) -> &'static str {
+    match self {
+      Client::Outlook => "Outlook (Windows desktop)",
+      Client::Gmail => "Gmail (web and apps)",
+      Client::AppleMail => "Apple Mail (macOS and iOS)",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Support {
+  Supported,
+  Partial,
+  Unsupported,
+}
+
+/// A feature used by the message that a client does not fully support.
+#[derive(Debug, Serialize)]
+pub struct CompatIssue {
+  pub feature: &'static str,
+  pub title: &'static str,
+  pub support: Support,
+  pub note: &'static str,
+  /// 1-based line numbers in the HTML body where the feature appears.
+  pub lines: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientReport {
+  pub client: Client,
+  pub label: &'static str,
+  pub unsupported: usize,
+  pub partial: usize,
+  pub issues: Vec<CompatIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompatReport {
+  pub message_id: Uuid,
+  /// Whether the message had an HTML body to check.
+  pub has_html: bool,
+  pub clients: Vec<ClientReport>,
+}
@@ -0,0 +1,31 @@
+//! Spam-score and deliverability findings for one message.
+
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  Info,
+  Warning,
+  Error,
+}
+
+/// One rule that matched; `score` adds to the message total.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+  pub rule: &'static str,
+  pub severity: Severity,
+  pub score: f32,
+  pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeliverabilityReport {
+  pub message_id: Uuid,
+  pub score: f32,
+  /// Score at or above which the message is considered likely spam.
+  pub threshold: f32,
+  pub likely_spam: bool,
+  pub findings: Vec<Finding>,
+}
@@ -0,0 +1,32 @@
+//! A URL found in a message, with its optional reachability check.
+
+use serde::Serialize;
+
+/// Where in the message a link was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkSource {
+  Html,
+  Text,
+  ListUnsubscribe,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageLink {
+  pub url: String,
+  pub sources: Vec<LinkSource>,
+  /// Anchor text when the link came from an `<a href>`.
+  pub text: Option<String>,
+  /// Destination carried in the query string of a tracking redirect.
+  pub redirect_target: Option<String>,
+  pub check: Option<LinkCheck>,
+}
+
+/// Result of requesting a link; redirects are reported, not followed.
+#[derive(Debug, Serialize)]
+pub struct LinkCheck {
+  pub method: &'static str,
+  pub status: Option<u16>,
+  pub location: Option<String>,
+  pub error: Option<String>,
+}
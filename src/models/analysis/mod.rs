@@ -0,0 +1,6 @@
+//! Reports produced by the offline message analyzers.
+
+pub mod codes_report;
+pub mod compat_report;
+pub mod deliverability_report;
+pub mod message_link;
@@ -4,11 +4,14 @@ use serde::Serialize;
 use sqlx::FromRow;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow)]
 pub struct AttachmentMeta {
   pub id: Uuid,
   pub message_id: Uuid,
   pub filename: Option<String>,
   pub content_type: String,
   pub size: i64,
+  pub content_id: Option<String>,
+  /// Referenced from the HTML body via `cid:` rather than a regular attachment.
+  pub inline: bool,
 }
@@ -3,10 +3,12 @@
 use sqlx::FromRow;
 use uuid::Uuid;
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct AttachmentRow {
   pub id: Uuid,
   pub filename: Option<String>,
   pub content_type: String,
+  /// Empty when the body lives in the blob store.
   pub content: Vec<u8>,
+  pub sha256: Option<String>,
 }
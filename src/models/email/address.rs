@@ -0,0 +1,63 @@
+//! Parsed mailbox addresses.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One mailbox: routing address plus optional display name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Address {
+  pub name: Option<String>,
+  pub address: String,
+}
+
+impl fmt::Display for Address {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.name.as_deref() {
+      Some(name) if name.contains(|c: char| "()<>[]:;@\\,.\"".contains(c)) => {
+        write!(f, "\"{}\" <{}>", name.replace('"', "\\\""), self.address)
+      }
+      Some(name) => write!(f, "{name} <{}>", self.address),
+      None => write!(f, "{}", self.address),
+    }
+  }
+}
+
+/// Structured addresses of a message, grouped by header.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Addresses {
+  pub from: Vec<Address>,
+  pub sender: Vec<Address>,
+  pub reply_to: Vec<Address>,
+  pub to: Vec<Address>,
+  pub cc: Vec<Address>,
+  pub bcc: Vec<Address>,
+}
+
+impl Addresses {
+  /// Storage kinds, in the order they are persisted.
+  pub const KINDS: [&'static str; 6] = ["from", "sender", "reply_to", "to", "cc", "bcc"];
+
+  pub fn get(&self, kind: &str) -> Option<&Vec<Address>> {
+    match kind {
+      "from" => Some(&self.from),
+      "sender" => Some(&self.sender),
+      "reply_to" => Some(&self.reply_to),
+      "to" => Some(&self.to),
+      "cc" => Some(&self.cc),
+      "bcc" => Some(&self.bcc),
+      _ => None,
+    }
+  }
+
+  pub fn get_mut(&mut self, kind: &str) -> Option<&mut Vec<Address>> {
+    match kind {
+      "from" => Some(&mut self.from),
+      "sender" => Some(&mut self.sender),
+      "reply_to" => Some(&mut self.reply_to),
+      "to" => Some(&mut self.to),
+      "cc" => Some(&mut self.cc),
+      "bcc" => Some(&mut self.bcc),
+      _ => None,
+    }
+  }
+}
@@ -1,6 +1,7 @@
 //! API representation of an email.
 
-use super::db_email::DbEmail;
+use super::{address::Addresses, db_email::DbEmail};
+use crate::util::parse_addresses;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 use std::collections::HashMap;
@@ -17,6 +18,20 @@ pub struct ApiEmail {
   pub html: Option<String>,
   pub headers: HashMap<String, String>,
   pub raw_len: i64,
+  /// SMTP envelope sender (`MAIL FROM`); absent for messages posted over HTTP.
+  pub envelope_from: Option<String>,
+  /// SMTP envelope recipients (`RCPT TO`), including Bcc recipients.
+  pub envelope_to: Vec<String>,
+  pub helo: Option<String>,
+  pub client_addr: Option<String>,
+  /// Parsed `{name, address}` lists for from/sender/reply_to/to/cc/bcc.
+  pub addresses: Addresses,
+  /// `Message-ID` without angle brackets.
+  pub message_id: Option<String>,
+  pub in_reply_to: Option<String>,
+  pub references: Vec<String>,
+  /// Conversation this message belongs to; see `GET /threads/:id`.
+  pub thread_id: Uuid,
 }
 
 impl From<DbEmail> for ApiEmail {
@@ -27,6 +42,26 @@ impl From<DbEmail> for ApiEmail {
       .as_deref()
       .and_then(|s| serde_json::from_str(s).ok())
       .unwrap_or_default();
+    let envelope_to: Vec<String> = d
+      .envelope_to
+      .as_deref()
+      .and_then(|s| serde_json::from_str(s).ok())
+      .unwrap_or_default();
+    let references: Vec<String> = d
+      .references_json
+      .as_deref()
+      .and_then(|s| serde_json::from_str(s).ok())
+      .unwrap_or_default();
+    // Stored recipient rows replace this when present; older rows only have strings.
+    let addresses = Addresses {
+      from: d
+        .from_addr
+        .as_deref()
+        .map(parse_addresses)
+        .unwrap_or_default(),
+      to: to.iter().flat_map(|t| parse_addresses(t)).collect(),
+      ..Addresses::default()
+    };
     ApiEmail {
       id: d.id,
       received_at: d.received_at,
@@ -37,6 +72,16 @@ impl From<DbEmail> for ApiEmail {
       html: d.html_body,
       headers,
       raw_len: d.raw_len,
+      envelope_from: d.envelope_from,
+      envelope_to,
+      helo: d.helo,
+      client_addr: d.client_addr,
+      addresses,
+      message_id: d.message_id_header,
+      in_reply_to: d.in_reply_to,
+      references,
+      // Messages stored before threading existed are their own thread.
+      thread_id: d.thread_id.unwrap_or(d.id),
     }
   }
 }
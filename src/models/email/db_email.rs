@@ -4,7 +4,7 @@ use chrono::{DateTime, Utc};
 use sqlx::FromRow;
 use uuid::Uuid;
 
-#[derive(Debug, FromRow)]
+#[derive(Debug, Clone, FromRow)]
 pub struct DbEmail {
   pub id: Uuid,
   pub received_at: DateTime<Utc>,
@@ -15,4 +15,17 @@ pub struct DbEmail {
   pub html_body: Option<String>,
   pub headers_json: Option<String>,
   pub raw_len: i64,
+  pub envelope_from: Option<String>,
+  pub envelope_to: Option<String>,
+  pub helo: Option<String>,
+  pub client_addr: Option<String>,
+  pub message_id_header: Option<String>,
+  pub in_reply_to: Option<String>,
+  pub references_json: Option<String>,
+  pub thread_id: Option<Uuid>,
+}
+
+impl DbEmail {
+  /// Column list matching the fields above, for `SELECT` statements.
+  pub const COLUMNS: &'static str = "id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len, envelope_from, envelope_to, helo, client_addr, message_id_header, in_reply_to, references_json, thread_id";
 }
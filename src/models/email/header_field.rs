@@ -0,0 +1,9 @@
+//! One message header in its original order.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct HeaderField {
+  pub name: String,
+  pub value: String,
+}
@@ -1,4 +1,8 @@
 //! Email-related models.
 
+pub mod address;
 pub mod api_email;
 pub mod db_email;
+pub mod header_field;
+pub mod new_message;
+pub mod recipient_row;
@@ -0,0 +1,34 @@
+//! A parsed message ready to be stored.
+
+use super::address::Addresses;
+use crate::util::{NewAttachment, ThreadHeaders, mime::MimePart};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct NewMessage {
+  pub id: Uuid,
+  pub received_at: DateTime<Utc>,
+  pub from: Option<String>,
+  pub to: Vec<String>,
+  pub subject: Option<String>,
+  pub text: Option<String>,
+  pub html: Option<String>,
+  pub headers: HashMap<String, String>,
+  pub raw_len: i64,
+  /// Original bytes for SMTP and EML submissions; `None` for JSON messages.
+  pub raw: Option<Vec<u8>>,
+  /// Blob holding `raw` once offloaded; the database then keeps no copy.
+  pub raw_sha256: Option<String>,
+  pub session_id: Option<Uuid>,
+  pub envelope_from: Option<String>,
+  /// Empty for messages that did not arrive over SMTP.
+  pub envelope_to: Vec<String>,
+  pub helo: Option<String>,
+  pub client_addr: Option<String>,
+  pub thread: ThreadHeaders,
+  pub addresses: Addresses,
+  pub parts: Vec<MimePart>,
+  pub attachments: Vec<NewAttachment>,
+}
@@ -0,0 +1,12 @@
+//! Database row for one parsed address of a message.
+
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct RecipientRow {
+  pub message_id: Uuid,
+  pub kind: String,
+  pub name: Option<String>,
+  pub address: String,
+}
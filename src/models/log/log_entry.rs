@@ -1,13 +1,28 @@
-//! Log entry stored in SQLite and exposed via API.
+//! Log entry stored in the database and exposed via API.
 
 use chrono::{DateTime, Utc};
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use sqlx::FromRow;
 
-#[derive(Debug, Serialize, FromRow)]
+#[derive(Debug, Clone, Serialize, FromRow)]
 pub struct LogEntry {
   pub id: i64,
   pub ts: DateTime<Utc>,
   pub level: String,
+  /// `tracing` target (module path); `None` for entries written directly.
+  pub target: Option<String>,
   pub message: String,
+  /// Structured event fields as a JSON object.
+  #[serde(rename = "fields", serialize_with = "raw_json")]
+  pub fields_json: Option<String>,
+}
+
+impl LogEntry {
+  pub const COLUMNS: &'static str = "id, ts, level, target, message, fields_json";
+}
+
+fn raw_json<S: Serializer>(v: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+  v.as_deref()
+    .and_then(|j| serde_json::from_str::<serde_json::Value>(j).ok())
+    .serialize(s)
 }
@@ -1,3 +1,4 @@
-//! Log entry model.
+//! Log entry models.
 
 pub mod log_entry;
+pub mod new_log_entry;
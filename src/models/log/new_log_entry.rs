@@ -0,0 +1,25 @@
+//! Log entry about to be stored.
+
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone)]
+pub struct NewLogEntry {
+  pub ts: DateTime<Utc>,
+  pub level: String,
+  pub target: Option<String>,
+  pub message: String,
+  pub fields_json: Option<String>,
+}
+
+impl NewLogEntry {
+  /// A plain entry without target or fields, stamped now.
+  pub fn new(level: &str, message: &str) -> Self {
+    NewLogEntry {
+      ts: Utc::now(),
+      level: level.to_string(),
+      target: None,
+      message: message.to_string(),
+      fields_json: None,
+    }
+  }
+}
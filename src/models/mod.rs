@@ -1,6 +1,11 @@
 //! Data models shared across layers.
 
+pub mod analysis;
 pub mod attachment;
 pub mod email;
 pub mod log;
+pub mod part;
 pub mod response;
+pub mod session;
+pub mod thread;
+pub mod token;
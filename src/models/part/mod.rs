@@ -0,0 +1,5 @@
+//! MIME part models.
+
+pub mod part_meta;
+pub mod part_node;
+pub mod part_row;
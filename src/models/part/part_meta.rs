@@ -0,0 +1,20 @@
+//! Stored MIME part metadata.
+
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PartMeta {
+  pub id: Uuid,
+  pub message_id: Uuid,
+  pub path: String,
+  pub parent_path: Option<String>,
+  pub content_type: String,
+  pub charset: Option<String>,
+  pub transfer_encoding: Option<String>,
+  pub content_id: Option<String>,
+  pub disposition: Option<String>,
+  pub filename: Option<String>,
+  pub size: i64,
+}
@@ -0,0 +1,39 @@
+//! MIME tree node returned by the parts API.
+
+use super::part_meta::PartMeta;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct PartNode {
+  #[serde(flatten)]
+  pub part: PartMeta,
+  pub children: Vec<PartNode>,
+}
+
+impl PartNode {
+  /// Rebuild the tree from parts stored in document order.
+  pub fn build(parts: Vec<PartMeta>) -> Option<PartNode> {
+    let mut iter = parts.into_iter();
+    let mut root = PartNode::leaf(iter.next()?);
+    for part in iter {
+      root.insert(part);
+    }
+    Some(root)
+  }
+
+  fn leaf(part: PartMeta) -> PartNode {
+    PartNode {
+      part,
+      children: Vec::new(),
+    }
+  }
+
+  /// In document order a part's parent is always on the rightmost branch.
+  fn insert(&mut self, part: PartMeta) {
+    if part.parent_path.as_deref() == Some(self.part.path.as_str()) {
+      self.children.push(PartNode::leaf(part));
+    } else if let Some(last) = self.children.last_mut() {
+      last.insert(part);
+    }
+  }
+}
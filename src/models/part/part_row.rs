@@ -0,0 +1,15 @@
+//! MIME part row for downloads.
+
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct PartRow {
+  pub id: Uuid,
+  pub filename: Option<String>,
+  pub content_type: String,
+  pub charset: Option<String>,
+  /// Empty when the body lives in the blob store.
+  pub content: Option<Vec<u8>>,
+  pub sha256: Option<String>,
+}
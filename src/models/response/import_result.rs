@@ -0,0 +1,13 @@
+//! Response type for an archive import.
+
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct ImportResult {
+  pub imported: usize,
+  pub failed: usize,
+  pub ids: Vec<Uuid>,
+  /// One line per message that could not be stored.
+  pub errors: Vec<String>,
+}
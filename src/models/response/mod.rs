@@ -1,3 +1,7 @@
 //! Response wrapper types.
 
+pub mod import_result;
 pub mod message_with_attachments;
+pub mod server_info;
+pub mod wait_result;
+pub mod webhook_event;
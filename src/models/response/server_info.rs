@@ -0,0 +1,34 @@
+//! Response types for `/info` and `/readyz`.
+
+use serde::Serialize;
+use std::net::SocketAddr;
+
+#[derive(Debug, Serialize)]
+pub struct InfoResponse {
+  pub version: &'static str,
+  pub http_addr: Option<SocketAddr>,
+  pub smtp_addr: Option<SocketAddr>,
+  /// Database URL with any password masked.
+  pub database: String,
+  /// `None` when the database cannot be queried.
+  pub messages: Option<u64>,
+  pub features: Features,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Features {
+  pub smtp_auth: bool,
+  pub blob_store: bool,
+  pub blob_raw_sources: bool,
+  pub retention: bool,
+  pub ingest_hooks: usize,
+  /// API tokens or dashboard login are required.
+  pub api_auth: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+  pub ready: bool,
+  pub database: bool,
+  pub smtp: bool,
+}
@@ -0,0 +1,10 @@
+//! Response type for a message that satisfied a wait.
+
+use crate::models::{analysis::codes_report::CodesReport, email::api_email::ApiEmail};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct WaitResult {
+  pub message: ApiEmail,
+  pub codes: CodesReport,
+}
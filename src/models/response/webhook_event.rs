@@ -0,0 +1,17 @@
+//! Body of the POST sent by the ingest webhook.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct WebhookEvent {
+  pub event: &'static str,
+  pub id: Uuid,
+  /// `smtp`, `json`, `raw` or `import`.
+  pub source: &'static str,
+  pub received_at: DateTime<Utc>,
+  pub from: Option<String>,
+  pub to: Vec<String>,
+  pub subject: Option<String>,
+}
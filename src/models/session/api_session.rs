@@ -0,0 +1,33 @@
+//! API representation of an SMTP session and its transcript.
+
+use super::{db_session::DbSession, transcript_line::TranscriptLine};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct ApiSession {
+  pub id: Uuid,
+  pub started_at: DateTime<Utc>,
+  pub ended_at: Option<DateTime<Utc>>,
+  pub client_addr: String,
+  pub helo: Option<String>,
+  pub auth_user: Option<String>,
+  pub transcript: Vec<TranscriptLine>,
+}
+
+impl From<DbSession> for ApiSession {
+  fn from(d: DbSession) -> Self {
+    let transcript: Vec<TranscriptLine> =
+      serde_json::from_str(&d.transcript_json).unwrap_or_default();
+    ApiSession {
+      id: d.id,
+      started_at: d.started_at,
+      ended_at: d.ended_at,
+      client_addr: d.client_addr,
+      helo: d.helo,
+      auth_user: d.auth_user,
+      transcript,
+    }
+  }
+}
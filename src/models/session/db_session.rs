@@ -0,0 +1,16 @@
+//! Database row for an SMTP session.
+
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DbSession {
+  pub id: Uuid,
+  pub started_at: DateTime<Utc>,
+  pub ended_at: Option<DateTime<Utc>>,
+  pub client_addr: String,
+  pub helo: Option<String>,
+  pub auth_user: Option<String>,
+  pub transcript_json: String,
+}
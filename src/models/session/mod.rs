@@ -0,0 +1,5 @@
+//! SMTP session models.
+
+pub mod api_session;
+pub mod db_session;
+pub mod transcript_line;
@@ -0,0 +1,19 @@
+//! One line of an SMTP dialogue.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Which side of the connection sent a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+  Client,
+  Server,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptLine {
+  pub ts: DateTime<Utc>,
+  pub dir: Direction,
+  pub line: String,
+}
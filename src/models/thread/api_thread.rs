@@ -0,0 +1,32 @@
+//! API summary of a conversation.
+
+use super::db_thread::DbThread;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct ApiThread {
+  pub id: Uuid,
+  /// Subject of the first message.
+  pub subject: Option<String>,
+  pub message_count: i64,
+  pub first_at: DateTime<Utc>,
+  pub last_at: DateTime<Utc>,
+  pub participants: Vec<String>,
+}
+
+impl From<DbThread> for ApiThread {
+  fn from(d: DbThread) -> Self {
+    let participants: Vec<Option<String>> =
+      serde_json::from_str(&d.participants_json).unwrap_or_default();
+    ApiThread {
+      id: d.id,
+      subject: d.subject,
+      message_count: d.message_count,
+      first_at: d.first_at,
+      last_at: d.last_at,
+      participants: participants.into_iter().flatten().collect(),
+    }
+  }
+}
@@ -0,0 +1,16 @@
+//! Aggregated database row for one conversation.
+
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, FromRow)]
+pub struct DbThread {
+  pub id: Uuid,
+  pub subject: Option<String>,
+  pub message_count: i64,
+  pub first_at: DateTime<Utc>,
+  pub last_at: DateTime<Utc>,
+  /// JSON array of distinct `From` values.
+  pub participants_json: String,
+}
@@ -0,0 +1,5 @@
+//! Conversation models.
+
+pub mod api_thread;
+pub mod db_thread;
+pub mod thread_detail;
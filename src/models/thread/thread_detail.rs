@@ -0,0 +1,15 @@
+//! A conversation with all of its messages.
+
+use crate::models::email::api_email::ApiEmail;
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct ThreadDetail {
+  pub id: Uuid,
+  pub subject: Option<String>,
+  /// Oldest first.
+  pub messages: Vec<ApiEmail>,
+  /// Message-IDs referenced by the thread that were never captured.
+  pub missing_references: Vec<String>,
+}
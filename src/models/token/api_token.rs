@@ -0,0 +1,25 @@
+//! API representation of a managed token, without its secret.
+
+use super::db_token::DbToken;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+  pub id: Uuid,
+  pub name: String,
+  pub scopes: Vec<String>,
+  pub created_at: DateTime<Utc>,
+}
+
+impl From<DbToken> for ApiToken {
+  fn from(d: DbToken) -> Self {
+    ApiToken {
+      id: d.id,
+      name: d.name,
+      scopes: d.scopes.split_whitespace().map(str::to_string).collect(),
+      created_at: d.created_at,
+    }
+  }
+}
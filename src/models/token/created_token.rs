@@ -0,0 +1,11 @@
+//! Response to `POST /tokens`: the only time the secret is shown.
+
+use super::api_token::ApiToken;
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+pub struct CreatedToken {
+  #[serde(flatten)]
+  pub info: ApiToken,
+  pub token: String,
+}
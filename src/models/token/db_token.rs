@@ -0,0 +1,19 @@
+//! Database row for a managed API token; only a hash of the secret is kept.
+
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DbToken {
+  pub id: Uuid,
+  pub name: String,
+  pub token_sha256: String,
+  /// Space-separated scope names.
+  pub scopes: String,
+  pub created_at: DateTime<Utc>,
+}
+
+impl DbToken {
+  pub const COLUMNS: &'static str = "id, name, token_sha256, scopes, created_at";
+}
@@ -0,0 +1,5 @@
+//! API token models.
+
+pub mod api_token;
+pub mod created_token;
+pub mod db_token;
@@ -0,0 +1,175 @@
+//! Retention policy and background purging.
+//!
+//! Limits come from the environment:
+//! - `FAUXMAIL_RETENTION_MAX_MESSAGES`: keep at most this many messages
+//! - `FAUXMAIL_RETENTION_MAX_AGE`: drop messages older than this (`90s`, `30m`, `12h`, `7d`)
+//! - `FAUXMAIL_RETENTION_MAX_BYTES`: cap total message size (`500M`, `2G`, or bytes)
+//! - `FAUXMAIL_RETENTION_SCOPE`: `global` (default) or `mailbox`, which applies
+//!   the count and size limits to each To/Cc/Bcc address separately
+//! - `FAUXMAIL_RETENTION_INTERVAL`: seconds between runs (default 60)
+//!
+//! The oldest messages are evicted first.
+
+use crate::{app::AppState, store::RetentionRow};
+use chrono::Utc;
+use std::{
+  collections::{BTreeMap, HashSet},
+  time::Duration,
+};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+  pub max_messages: Option<u64>,
+  pub max_age: Option<Duration>,
+  pub max_bytes: Option<u64>,
+  /// Apply count and size limits per recipient address instead of globally.
+  pub per_mailbox: bool,
+  pub interval: Duration,
+}
+
+impl RetentionPolicy {
+  /// Policy from `FAUXMAIL_RETENTION_*`; `None` when no limit is set.
+  pub fn from_env() -> Option<Self> {
+    let var = |name: &str| {
+      std::env::var(name)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+    };
+    let parsed = |name: &str, parse: fn(&str) -> Option<u64>| {
+      let value = var(name)?;
+      let n = parse(&value);
+      if n.is_none() {
+        warn!("ignoring invalid {name}={value}");
+      }
+      n
+    };
+    let policy = RetentionPolicy {
+      max_messages: parsed("FAUXMAIL_RETENTION_MAX_MESSAGES", |v| v.parse().ok()),
+      max_age: parsed("FAUXMAIL_RETENTION_MAX_AGE", parse_duration_secs).map(Duration::from_secs),
+      max_bytes: parsed("FAUXMAIL_RETENTION_MAX_BYTES", parse_bytes),
+      per_mailbox: var("FAUXMAIL_RETENTION_SCOPE")
+        .is_some_and(|s| s.eq_ignore_ascii_case("mailbox")),
+      interval: Duration::from_secs(
+        parsed("FAUXMAIL_RETENTION_INTERVAL", |v| v.parse().ok())
+          .unwrap_or(60)
+          .max(1),
+      ),
+    };
+    (policy.max_messages.is_some() || policy.max_age.is_some() || policy.max_bytes.is_some())
+      .then_some(policy)
+  }
+}
+
+/// `90`, `90s`, `30m`, `12h`, `7d`.
+fn parse_duration_secs(v: &str) -> Option<u64> {
+  let (num, unit) = v.split_at(v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len()));
+  let n: u64 = num.parse().ok()?;
+  let mult = match unit.trim().to_ascii_lowercase().as_str() {
+    "" | "s" => 1,
+    "m" => 60,
+    "h" => 3600,
+    "d" => 86400,
+    _ => return None,
+  };
+  n.checked_mul(mult)
+}
+
+/// `1048576`, `512K`, `500M`, `2G` (binary units; a trailing `B` is allowed).
+fn parse_bytes(v: &str) -> Option<u64> {
+  let (num, unit) = v.split_at(v.find(|c: char| !c.is_ascii_digit()).unwrap_or(v.len()));
+  let n: u64 = num.parse().ok()?;
+  let unit = unit.trim().to_ascii_lowercase();
+  let mult = match unit.trim_end_matches('b').trim_end_matches('i') {
+    "" => 1,
+    "k" => 1 << 10,
+    "m" => 1 << 20,
+    "g" => 1 << 30,
+    _ => return None,
+  };
+  n.checked_mul(mult)
+}
+
+/// Run [`purge`] every `policy.interval` for the life of the process.
+pub fn spawn(state: AppState, policy: RetentionPolicy) {
+  info!(
+    "retention: max_messages={:?} max_age={:?} max_bytes={:?} scope={}",
+    policy.max_messages,
+    policy.max_age,
+    policy.max_bytes,
+    if policy.per_mailbox {
+      "mailbox"
+    } else {
+      "global"
+    }
+  );
+  tokio::spawn(async move {
+    let mut tick = tokio::time::interval(policy.interval);
+    loop {
+      tick.tick().await;
+      if let Err(e) = purge(&state, &policy).await {
+        error!("retention purge error: {e}");
+      }
+    }
+  });
+}
+
+/// Evict messages beyond the policy's limits; returns how many were removed.
+pub async fn purge(state: &AppState, policy: &RetentionPolicy) -> Result<u64, sqlx::Error> {
+  let mut rows = state.store.retention_rows().await?;
+  rows.sort_by(|a, b| b.received_at.cmp(&a.received_at));
+  let mut doomed: Vec<Uuid> = Vec::new();
+  let mut expired: HashSet<Uuid> = HashSet::new();
+
+  if let Some(max_age) = policy.max_age {
+    let cutoff = Utc::now() - chrono::Duration::from_std(max_age).unwrap_or(chrono::Duration::MAX);
+    expired.extend(rows.iter().filter(|r| r.received_at < cutoff).map(|r| r.id));
+  }
+
+  if policy.max_messages.is_some() || policy.max_bytes.is_some() {
+    let groups: Vec<Vec<&RetentionRow>> = if policy.per_mailbox {
+      let mut by_mailbox: BTreeMap<&str, Vec<&RetentionRow>> = BTreeMap::new();
+      // Messages without To/Cc/Bcc rows share one group so they still age out.
+      let mut unaddressed = Vec::new();
+      for r in &rows {
+        if r.mailboxes.is_empty() {
+          unaddressed.push(r);
+        }
+        for mailbox in &r.mailboxes {
+          by_mailbox.entry(mailbox).or_default().push(r);
+        }
+      }
+      by_mailbox.into_values().chain([unaddressed]).collect()
+    } else {
+      vec![rows.iter().collect()]
+    };
+    for newest_first in groups {
+      let mut bytes = 0u64;
+      // Messages already expired by age do not count against the other limits.
+      let live = newest_first
+        .into_iter()
+        .filter(|r| !expired.contains(&r.id));
+      for (kept, r) in live.enumerate() {
+        bytes += r.size;
+        let over_count = policy.max_messages.is_some_and(|max| kept as u64 >= max);
+        let over_bytes = policy.max_bytes.is_some_and(|max| bytes > max);
+        if over_count || over_bytes {
+          doomed.push(r.id);
+        }
+      }
+    }
+  }
+
+  doomed.extend(expired);
+  doomed.sort_unstable();
+  doomed.dedup();
+  if doomed.is_empty() {
+    return Ok(0);
+  }
+  let deleted = state.store.delete_messages(&doomed).await?;
+  info!("retention purged {deleted} messages");
+  crate::blob::prune_unreferenced(state).await;
+  Ok(deleted)
+}
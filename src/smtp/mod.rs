@@ -1,102 +1,216 @@
 //! Minimal SMTP listener for local development.
 //!
 //! Supports HELO/EHLO, optional AUTH LOGIN/PLAIN, MAIL FROM, RCPT TO, DATA, QUIT.
+//! Each connection is recorded as a session transcript linked to its messages.
+
+pub mod transcript;
 
 use crate::{
   app::AppState,
-  http::logs::log_db,
-  util::{collect_attachments, collect_headers, extract_bodies},
+  ingest::{self, Envelope, IngestError, Source},
+  metrics::Metrics,
 };
 use base64::Engine;
 use base64::engine::general_purpose::STANDARD as B64;
-use chrono::Utc;
-use mailparse::parse_mail;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{
   io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-  net::{TcpListener, TcpStream},
+  net::{
+    TcpListener, TcpStream,
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+  },
+  task::JoinSet,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
-use uuid::Uuid;
+use transcript::{REDACTED, Transcript};
+
+/// How long open sessions get to wind down after shutdown begins.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
 
-pub async fn start_smtp(state: AppState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Bind the listener on `FAUXMAIL_SMTP_ADDR` (default `127.0.0.1:1025`).
+pub async fn bind_from_env() -> Result<TcpListener, Box<dyn std::error::Error + Send + Sync>> {
   let addr = std::env::var("FAUXMAIL_SMTP_ADDR").unwrap_or_else(|_| "127.0.0.1:1025".to_string());
-  let listener = TcpListener::bind(&addr).await?;
+  let listener = TcpListener::bind(&addr)
+    .await
+    .map_err(|e| format!("cannot bind SMTP listener on {addr}: {e}"))?;
   info!("smtp listener: {}", addr);
+  Ok(listener)
+}
 
+/// Accept SMTP connections on an already bound listener until
+/// `state.shutdown` is cancelled, then wait for open sessions to close.
+pub async fn serve_smtp(
+  state: AppState,
+  listener: TcpListener,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  let _ = state.server.smtp_addr.set(listener.local_addr()?);
+  let mut sessions = JoinSet::new();
   loop {
-    let (stream, peer) = listener.accept().await?;
-    let state = state.clone();
-    tokio::spawn(async move {
-      if let Err(e) = handle_client(state, stream).await {
-        warn!("smtp connection error from {}: {}", peer, e);
+    tokio::select! {
+      accepted = listener.accept() => match accepted {
+        Ok((stream, peer)) => {
+          let state = state.clone();
+          sessions.spawn(async move {
+            if let Err(e) = handle_client(state, stream, peer).await {
+              warn!("smtp connection error from {}: {}", peer, e);
+            }
+          });
+        }
+        Err(e) => {
+          // Usually transient (e.g. out of file descriptors); back off.
+          warn!("smtp accept error: {e}");
+          tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+      },
+      Some(_) = sessions.join_next(), if !sessions.is_empty() => {}
+      _ = state.shutdown.cancelled() => break,
+    }
+  }
+  drop(listener);
+  let drained = tokio::time::timeout(SHUTDOWN_GRACE, async {
+    while sessions.join_next().await.is_some() {}
+  })
+  .await;
+  if drained.is_err() {
+    warn!("aborting {} smtp sessions still open", sessions.len());
+  }
+  Ok(())
+}
+
+/// One client connection: socket halves plus the transcript being recorded.
+struct Conn {
+  reader: BufReader<OwnedReadHalf>,
+  writer: OwnedWriteHalf,
+  transcript: Transcript,
+  metrics: Arc<Metrics>,
+  shutdown: CancellationToken,
+  /// Shutdown began and the client was sent a `421`.
+  closing: bool,
+}
+
+impl Conn {
+  /// Send a reply line (without CRLF) and record it.
+  async fn reply(&mut self, line: &str) -> std::io::Result<()> {
+    self.transcript.server(line);
+    self.metrics.smtp_reply(line);
+    self.writer.write_all(line.as_bytes()).await?;
+    self.writer.write_all(b"\r\n").await?;
+    self.writer.flush().await
+  }
+
+  /// Read one line, trimmed of its line ending. Returns `None` on EOF or
+  /// once shutdown began.
+  async fn read_line(&mut self) -> std::io::Result<Option<String>> {
+    let line = self.read_raw_line().await?;
+    Ok(line.map(|l| l.trim_end_matches(['\r', '\n']).to_string()))
+  }
+
+  /// Read one line with its line ending. On shutdown the client is sent a
+  /// `421` and this and every later read return `None`.
+  async fn read_raw_line(&mut self) -> std::io::Result<Option<String>> {
+    if self.closing {
+      return Ok(None);
+    }
+    let mut buf = String::new();
+    let n = tokio::select! {
+      n = self.reader.read_line(&mut buf) => n?,
+      _ = self.shutdown.cancelled() => {
+        self.closing = true;
+        self.reply("421 fauxmail shutting down").await?;
+        return Ok(None);
       }
-    });
+    };
+    Ok((n > 0).then_some(buf))
   }
 }
 
 async fn handle_client(
   state: AppState,
   stream: TcpStream,
+  peer: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+  let (read_half, writer) = stream.into_split();
+  let mut conn = Conn {
+    reader: BufReader::new(read_half),
+    writer,
+    transcript: Transcript::new(peer),
+    metrics: state.metrics.clone(),
+    shutdown: state.shutdown.clone(),
+    closing: false,
+  };
+  state.metrics.smtp_connection();
+  if let Err(e) = conn.transcript.insert(&state).await {
+    error!("smtp session insert error: {e}");
+  }
+  let result = converse(&state, &mut conn).await;
+  if let Err(e) = conn.transcript.save(&state, true).await {
+    error!("smtp session save error: {e}");
+  }
+  result
+}
+
+async fn converse(
+  state: &AppState,
+  conn: &mut Conn,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
   let user = std::env::var("FAUXMAIL_SMTP_USER").ok();
   let pass = std::env::var("FAUXMAIL_SMTP_PASS").ok();
   let require_auth = user.is_some() && pass.is_some();
 
-  let (read_half, mut writer) = stream.into_split();
-  let mut reader = BufReader::new(read_half);
-  writer.write_all(b"220 fauxmail dev smtp\r\n").await?;
-  writer.flush().await?;
+  conn.reply("220 fauxmail dev smtp").await?;
 
   let mut authed = !require_auth;
   let mut mail_from: Option<String> = None;
   let mut rcpts: Vec<String> = Vec::new();
-  let mut buf = String::new();
 
-  loop {
-    buf.clear();
-    let n = reader.read_line(&mut buf).await?;
-    if n == 0 {
-      break;
-    }
-    let line = buf.trim_end_matches(['\r', '\n']);
+  while let Some(line) = conn.read_line().await? {
+    let line = line.as_str();
     debug!("smtp <= {}", line);
     let upper = line.to_uppercase();
 
+    if upper.starts_with("AUTH PLAIN ") {
+      conn
+        .transcript
+        .client(&format!("{} {REDACTED}", &line[..10]));
+    } else {
+      conn.transcript.client(line);
+    }
+
     if upper.starts_with("EHLO") || upper.starts_with("HELO") {
-      writer.write_all(b"250-fauxmail\r\n").await?;
+      let name = line[4..].trim();
+      conn.transcript.helo = (!name.is_empty()).then(|| name.to_string());
+      conn.reply("250-fauxmail").await?;
       if require_auth {
-        writer.write_all(b"250-AUTH PLAIN LOGIN\r\n").await?;
+        conn.reply("250-AUTH PLAIN LOGIN").await?;
       }
-      writer.write_all(b"250 OK\r\n").await?;
+      conn.reply("250 OK").await?;
     } else if upper.starts_with("AUTH ") {
       if !require_auth {
-        writer.write_all(b"503 AUTH not required\r\n").await?;
+        conn.reply("503 AUTH not required").await?;
         continue;
       }
       if upper.starts_with("AUTH LOGIN") {
-        writer.write_all(b"334 VXNlcm5hbWU6\r\n").await?; // 'Username:'
-        let mut u = String::new();
-        reader.read_line(&mut u).await?;
-        let u = u.trim_end_matches(['\r', '\n']);
-        let Ok(decoded_user) = String::from_utf8(B64.decode(u)?) else {
-          writer.write_all(b"535 auth failed\r\n").await?;
+        conn.reply("334 VXNlcm5hbWU6").await?; // 'Username:'
+        let u = conn.read_line().await?.unwrap_or_default();
+        conn.transcript.client(&u);
+        let Ok(decoded_user) = String::from_utf8(B64.decode(&u)?) else {
+          conn.reply("535 auth failed").await?;
           continue;
         };
-        writer.write_all(b"334 UGFzc3dvcmQ6\r\n").await?; // 'Password:'
-        let mut p = String::new();
-        reader.read_line(&mut p).await?;
-        let p = p.trim_end_matches(['\r', '\n']);
-        let Ok(decoded_pass) = String::from_utf8(B64.decode(p)?) else {
-          writer.write_all(b"535 auth failed\r\n").await?;
+        conn.transcript.auth_user = Some(decoded_user.clone());
+        conn.reply("334 UGFzc3dvcmQ6").await?; // 'Password:'
+        let p = conn.read_line().await?.unwrap_or_default();
+        conn.transcript.client(REDACTED);
+        let Ok(decoded_pass) = String::from_utf8(B64.decode(&p)?) else {
+          conn.reply("535 auth failed").await?;
           continue;
         };
         if decoded_user == user.clone().unwrap() && decoded_pass == pass.clone().unwrap() {
           authed = true;
-          writer
-            .write_all(b"235 Authentication successful\r\n")
-            .await?;
+          conn.reply("235 Authentication successful").await?;
         } else {
-          writer.write_all(b"535 Authentication failed\r\n").await?;
+          conn.reply("535 Authentication failed").await?;
         }
       } else if upper.starts_with("AUTH PLAIN") {
         let parts: Vec<&str> = line.split_whitespace().collect();
@@ -107,149 +221,96 @@ async fn handle_client(
         let _ = iter.next();
         let u = String::from_utf8(iter.next().unwrap_or_default().to_vec()).unwrap_or_default();
         let p = String::from_utf8(iter.next().unwrap_or_default().to_vec()).unwrap_or_default();
+        conn.transcript.auth_user = Some(u.clone());
         if Some(u) == user.clone() && Some(p) == pass.clone() {
           authed = true;
-          writer
-            .write_all(b"235 Authentication successful\r\n")
-            .await?;
+          conn.reply("235 Authentication successful").await?;
         } else {
-          writer.write_all(b"535 Authentication failed\r\n").await?;
+          conn.reply("535 Authentication failed").await?;
         }
       } else {
-        writer
-          .write_all(b"504 Unrecognized authentication type\r\n")
-          .await?;
+        conn.reply("504 Unrecognized authentication type").await?;
       }
     } else if upper.starts_with("MAIL FROM:") {
       if require_auth && !authed {
-        writer.write_all(b"530 Authentication required\r\n").await?;
+        conn.reply("530 Authentication required").await?;
         continue;
       }
       mail_from = Some(line[10..].trim().trim_matches(['<', '>']).to_string());
       rcpts.clear();
-      writer.write_all(b"250 OK\r\n").await?;
+      conn.reply("250 OK").await?;
     } else if upper.starts_with("RCPT TO:") {
       if require_auth && !authed {
-        writer.write_all(b"530 Authentication required\r\n").await?;
+        conn.reply("530 Authentication required").await?;
         continue;
       }
       rcpts.push(line[8..].trim().trim_matches(['<', '>']).to_string());
-      writer.write_all(b"250 Accepted\r\n").await?;
+      conn.reply("250 Accepted").await?;
     } else if upper == "DATA" {
       if require_auth && !authed {
-        writer.write_all(b"530 Authentication required\r\n").await?;
+        conn.reply("530 Authentication required").await?;
         continue;
       }
-      writer
-        .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
-        .await?;
+      conn.reply("354 End data with <CR><LF>.<CR><LF>").await?;
       let mut data = Vec::new();
       // Read until line with single '.'
       loop {
-        let mut line = String::new();
-        let n = reader.read_line(&mut line).await?;
-        if n == 0 {
+        let Some(line) = conn.read_raw_line().await? else {
+          if conn.closing {
+            // Interrupted by shutdown: the partial message is dropped.
+            return Ok(());
+          }
           break;
-        }
+        };
         if line == ".\r\n" || line == ".\n" {
           break;
         }
         data.extend_from_slice(line.as_bytes());
       }
+      conn
+        .transcript
+        .client(&format!("[{} bytes of message data]", data.len()));
+      conn.transcript.client(".");
 
-      // Store message
-      let id = Uuid::new_v4();
-      match store_raw_message(&state, id, mail_from.clone(), rcpts.clone(), data).await {
-        Ok(_) => {
-          let _ = log_db(&state, "INFO", &format!("stored message via SMTP: {id}")).await;
-          writer
-            .write_all(format!("250 OK id={id}\r\n").as_bytes())
+      let envelope = Envelope {
+        session_id: conn.transcript.id,
+        helo: conn.transcript.helo.clone(),
+        client_addr: conn.transcript.client_addr.clone(),
+        mail_from: mail_from.clone(),
+        rcpt_to: rcpts.clone(),
+      };
+      match ingest::ingest_raw(state, Source::Smtp, data, Some(envelope)).await {
+        Ok(id) => {
+          conn.reply(&format!("250 OK id={id}")).await?;
+        }
+        Err(IngestError::Rejected(reason)) => {
+          conn
+            .reply(&format!("550 Message rejected: {reason}"))
             .await?;
         }
         Err(e) => {
           error!("smtp store error: {e}");
-          writer
-            .write_all(b"451 Requested action aborted: local error\r\n")
+          conn
+            .reply("451 Requested action aborted: local error")
             .await?;
         }
       }
+      // Keep the stored transcript current for messages viewed mid-session.
+      if let Err(e) = conn.transcript.save(state, false).await {
+        error!("smtp session save error: {e}");
+      }
     } else if upper == "RSET" {
       mail_from = None;
       rcpts.clear();
-      writer.write_all(b"250 OK\r\n").await?;
+      conn.reply("250 OK").await?;
     } else if upper == "NOOP" {
-      writer.write_all(b"250 OK\r\n").await?;
+      conn.reply("250 OK").await?;
     } else if upper == "QUIT" {
-      writer.write_all(b"221 Bye\r\n").await?;
+      conn.reply("221 Bye").await?;
       break;
     } else {
-      writer.write_all(b"502 Command not implemented\r\n").await?;
-    }
-  }
-  Ok(())
-}
-
-async fn store_raw_message(
-  state: &AppState,
-  id: Uuid,
-  from: Option<String>,
-  to: Vec<String>,
-  raw: Vec<u8>,
-) -> Result<(), sqlx::Error> {
-  let parsed = parse_mail(&raw).map_err(|e| {
-    error!("smtp parse error: {e}");
-    sqlx::Error::Protocol("parse error".into())
-  })?;
-  let (text, html) = extract_bodies(&parsed);
-  let mut headers = collect_headers(&parsed);
-  if !headers.contains_key("from") {
-    if let Some(f) = from.clone() {
-      headers.insert("from".into(), f);
+      conn.reply("502 Command not implemented").await?;
     }
   }
-  if !headers.contains_key("to") && !to.is_empty() {
-    headers.insert("to".into(), to.join(", "));
-  }
-  let subject = headers.get("subject").cloned();
-
-  // Insert message
-  let to_json = serde_json::to_string(&to).unwrap_or_else(|_| "[]".to_string());
-  let headers_json = if headers.is_empty() {
-    None
-  } else {
-    Some(serde_json::to_string(&headers).unwrap_or_else(|_| "{}".to_string()))
-  };
-  sqlx::query(
-        "INSERT INTO messages (id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
-    )
-    .bind(id)
-    .bind(Utc::now())
-    .bind(from)
-    .bind(to_json)
-    .bind(subject)
-    .bind(text)
-    .bind(html)
-    .bind(headers_json)
-    .bind(raw.len() as i64)
-    .execute(&state.db)
-    .await?;
-
-  // Attachments
-  let mut atts = Vec::new();
-  collect_attachments(&parsed, &mut atts);
-  for (filename, content_type, data) in atts {
-    let att_id = Uuid::new_v4();
-    sqlx::query(
-            "INSERT INTO attachments (id, message_id, filename, content_type, size, content) VALUES (?, ?, ?, ?, ?, ?)",
-        )
-        .bind(att_id)
-        .bind(id)
-        .bind(filename)
-        .bind(content_type)
-        .bind(data.len() as i64)
-        .bind(data)
-        .execute(&state.db)
-        .await?;
-  }
   Ok(())
 }
@@ -0,0 +1,87 @@
+//! Per-connection SMTP transcript recording.
+//!
+//! Every command and reply is kept with a timestamp. Credentials never reach
+//! the transcript: AUTH payloads are replaced with `<redacted>` and only the
+//! decoded user name is kept on the session.
+
+use crate::{
+  app::AppState,
+  models::session::{
+    db_session::DbSession,
+    transcript_line::{Direction, TranscriptLine},
+  },
+};
+use chrono::{DateTime, Utc};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+pub const REDACTED: &str = "<redacted>";
+
+/// In-memory transcript of one SMTP connection.
+#[derive(Debug)]
+pub struct Transcript {
+  pub id: Uuid,
+  pub started_at: DateTime<Utc>,
+  pub client_addr: String,
+  pub helo: Option<String>,
+  pub auth_user: Option<String>,
+  lines: Vec<TranscriptLine>,
+}
+
+impl Transcript {
+  pub fn new(peer: SocketAddr) -> Self {
+    Transcript {
+      id: Uuid::new_v4(),
+      started_at: Utc::now(),
+      client_addr: peer.to_string(),
+      helo: None,
+      auth_user: None,
+      lines: Vec::new(),
+    }
+  }
+
+  /// Record a line received from the client.
+  pub fn client(&mut self, line: &str) {
+    self.push(Direction::Client, line);
+  }
+
+  /// Record a reply sent to the client.
+  pub fn server(&mut self, line: &str) {
+    self.push(Direction::Server, line);
+  }
+
+  pub fn lines(&self) -> &[TranscriptLine] {
+    &self.lines
+  }
+
+  fn push(&mut self, dir: Direction, line: &str) {
+    self.lines.push(TranscriptLine {
+      ts: Utc::now(),
+      dir,
+      line: line.to_string(),
+    });
+  }
+
+  /// Stored form of the session; `ended` stamps the close time.
+  fn record(&self, ended: bool) -> DbSession {
+    DbSession {
+      id: self.id,
+      started_at: self.started_at,
+      ended_at: ended.then(Utc::now),
+      client_addr: self.client_addr.clone(),
+      helo: self.helo.clone(),
+      auth_user: self.auth_user.clone(),
+      transcript_json: serde_json::to_string(&self.lines).unwrap_or_else(|_| "[]".to_string()),
+    }
+  }
+
+  /// Create the session row; called once when the connection opens.
+  pub async fn insert(&self, state: &AppState) -> Result<(), sqlx::Error> {
+    state.store.insert_session(&self.record(false)).await
+  }
+
+  /// Persist the current transcript; `ended` stamps the close time.
+  pub async fn save(&self, state: &AppState, ended: bool) -> Result<(), sqlx::Error> {
+    state.store.update_session(&self.record(ended)).await
+  }
+}
@@ -0,0 +1,626 @@
+//! In-process [`MessageStore`]; nothing survives a restart.
+
+use super::{
+  LogQuery, MessageFilter, MessageQuery, MessageStore, RetentionRow, SortKey, StoreStats,
+  contains_ci,
+};
+use crate::{
+  blob::StoredBody,
+  models::{
+    attachment::{attachment_meta::AttachmentMeta, attachment_row::AttachmentRow},
+    email::{
+      address::Addresses, db_email::DbEmail, new_message::NewMessage, recipient_row::RecipientRow,
+    },
+    log::{log_entry::LogEntry, new_log_entry::NewLogEntry},
+    part::{part_meta::PartMeta, part_row::PartRow},
+    session::db_session::DbSession,
+    thread::api_thread::ApiThread,
+    token::db_token::DbToken,
+  },
+};
+use async_trait::async_trait;
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  sync::Mutex,
+};
+use uuid::Uuid;
+
+struct StoredMessage {
+  row: DbEmail,
+  raw: Option<StoredBody>,
+  session_id: Option<Uuid>,
+  recipients: Vec<RecipientRow>,
+  references: Vec<String>,
+}
+
+struct StoredPart {
+  meta: PartMeta,
+  headers: Vec<(String, String)>,
+  content: Option<Vec<u8>>,
+  sha256: Option<String>,
+}
+
+struct StoredAttachment {
+  meta: AttachmentMeta,
+  content: Vec<u8>,
+  sha256: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+  /// In arrival order.
+  messages: Vec<StoredMessage>,
+  parts: Vec<StoredPart>,
+  attachments: Vec<StoredAttachment>,
+  sessions: Vec<DbSession>,
+  logs: Vec<LogEntry>,
+  next_log_id: i64,
+  tokens: Vec<DbToken>,
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+  inner: Mutex<Inner>,
+}
+
+impl MemoryStore {
+  fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+    self.inner.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+fn thread_of(m: &StoredMessage) -> Uuid {
+  m.row.thread_id.unwrap_or(m.row.id)
+}
+
+/// Same semantics as the SQLite filter: case-insensitive substrings, with
+/// address filters also checking stored recipient rows.
+fn matches(m: &StoredMessage, f: &MessageFilter) -> bool {
+  fn text(v: &Option<String>) -> &str {
+    v.as_deref().unwrap_or("")
+  }
+  if let Some(q) = f.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+    let r = &m.row;
+    if ![
+      text(&r.from_addr),
+      text(&r.subject),
+      text(&r.text_body),
+      &r.to_recipients,
+    ]
+    .iter()
+    .any(|field| contains_ci(field, q))
+    {
+      return false;
+    }
+  }
+  let address_filters = [
+    (
+      f.from.as_deref(),
+      text(&m.row.from_addr),
+      &["from", "sender"][..],
+    ),
+    (
+      f.to.as_deref(),
+      m.row.to_recipients.as_str(),
+      &["to", "cc", "bcc"][..],
+    ),
+  ];
+  for (value, column, kinds) in address_filters {
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+      continue;
+    };
+    let in_column = contains_ci(column, value);
+    let in_rows = m.recipients.iter().any(|r| {
+      kinds.contains(&r.kind.as_str())
+        && (contains_ci(&r.address, value) || contains_ci(text(&r.name), value))
+    });
+    if !in_column && !in_rows {
+      return false;
+    }
+  }
+  true
+}
+
+fn page<T>(items: Vec<T>, query: &MessageQuery) -> Vec<T> {
+  let items = items.into_iter().skip(query.offset as usize);
+  match query.limit {
+    Some(limit) => items.take(limit as usize).collect(),
+    None => items.collect(),
+  }
+}
+
+impl Inner {
+  fn message(&self, id: Uuid) -> Option<&StoredMessage> {
+    self.messages.iter().find(|m| m.row.id == id)
+  }
+
+  /// Mirror of the SQLite thread assignment; see `SqliteStore`.
+  fn assign_thread(&mut self, id: Uuid, message: &NewMessage) {
+    let thread = &message.thread;
+    let mut related: Vec<&String> = thread.references.iter().collect();
+    related.extend(&thread.in_reply_to);
+    related.extend(&thread.message_id);
+    let mut by_age: Vec<&StoredMessage> = self.messages.iter().filter(|m| m.row.id != id).collect();
+    by_age.sort_by_key(|m| m.row.received_at);
+
+    let mut threads: Vec<Uuid> = by_age
+      .iter()
+      .filter(|m| {
+        m.row
+          .message_id_header
+          .as_ref()
+          .is_some_and(|mid| related.contains(&mid))
+      })
+      .map(|m| thread_of(m))
+      .collect();
+    if let Some(own) = &thread.message_id {
+      threads.extend(
+        by_age
+          .iter()
+          .filter(|m| m.row.in_reply_to.as_ref() == Some(own) || m.references.contains(own))
+          .map(|m| thread_of(m)),
+      );
+    }
+
+    let thread_id = threads.first().copied().unwrap_or(id);
+    for m in &mut self.messages {
+      if m.row.id == id || threads.contains(&thread_of(m)) {
+        m.row.thread_id = Some(thread_id);
+      }
+    }
+  }
+}
+
+#[async_trait]
+impl MessageStore for MemoryStore {
+  async fn insert_message(&self, msg: &NewMessage) -> Result<(), sqlx::Error> {
+    let references_json = (!msg.thread.references.is_empty())
+      .then(|| serde_json::to_string(&msg.thread.references).unwrap_or_else(|_| "[]".to_string()));
+    let row = DbEmail {
+      id: msg.id,
+      received_at: msg.received_at,
+      from_addr: msg.from.clone(),
+      to_recipients: serde_json::to_string(&msg.to).unwrap_or_else(|_| "[]".to_string()),
+      subject: msg.subject.clone(),
+      text_body: msg.text.clone(),
+      html_body: msg.html.clone(),
+      headers_json: (!msg.headers.is_empty())
+        .then(|| serde_json::to_string(&msg.headers).unwrap_or_else(|_| "{}".to_string())),
+      raw_len: msg.raw_len,
+      envelope_from: msg.envelope_from.clone(),
+      envelope_to: (!msg.envelope_to.is_empty())
+        .then(|| serde_json::to_string(&msg.envelope_to).unwrap_or_else(|_| "[]".to_string())),
+      helo: msg.helo.clone(),
+      client_addr: msg.client_addr.clone(),
+      message_id_header: msg.thread.message_id.clone(),
+      in_reply_to: msg.thread.in_reply_to.clone(),
+      references_json,
+      thread_id: None,
+    };
+    let recipients = Addresses::KINDS
+      .iter()
+      .flat_map(|kind| {
+        msg
+          .addresses
+          .get(kind)
+          .into_iter()
+          .flatten()
+          .map(|a| RecipientRow {
+            message_id: msg.id,
+            kind: kind.to_string(),
+            name: a.name.clone(),
+            address: a.address.clone(),
+          })
+      })
+      .collect();
+
+    let mut inner = self.lock();
+    inner.messages.push(StoredMessage {
+      row,
+      raw: match &msg.raw_sha256 {
+        Some(sha) => Some(StoredBody::Blob(sha.clone())),
+        None => msg.raw.clone().map(StoredBody::Bytes),
+      },
+      session_id: msg.session_id,
+      recipients,
+      references: msg.thread.references.clone(),
+    });
+    inner.assign_thread(msg.id, msg);
+    for p in &msg.parts {
+      inner.parts.push(StoredPart {
+        meta: PartMeta {
+          id: Uuid::new_v4(),
+          message_id: msg.id,
+          path: p.path.clone(),
+          parent_path: p.parent_path.clone(),
+          content_type: p.content_type.clone(),
+          charset: p.charset.clone(),
+          transfer_encoding: p.transfer_encoding.clone(),
+          content_id: p.content_id.clone(),
+          disposition: p.disposition.clone(),
+          filename: p.filename.clone(),
+          size: p.body.as_ref().map_or(0, |b| b.len() as i64),
+        },
+        headers: p.headers.clone(),
+        content: match p.sha256 {
+          Some(_) => p.body.as_ref().map(|_| Vec::new()),
+          None => p.body.clone(),
+        },
+        sha256: p.sha256.clone(),
+      });
+    }
+    for a in &msg.attachments {
+      inner.attachments.push(StoredAttachment {
+        meta: AttachmentMeta {
+          id: Uuid::new_v4(),
+          message_id: msg.id,
+          filename: a.filename.clone(),
+          content_type: a.content_type.clone(),
+          size: a.data.len() as i64,
+          content_id: a.content_id.clone(),
+          inline: a.inline,
+        },
+        content: match a.sha256 {
+          Some(_) => Vec::new(),
+          None => a.data.clone(),
+        },
+        sha256: a.sha256.clone(),
+      });
+    }
+    Ok(())
+  }
+
+  async fn list_messages(&self, query: &MessageQuery) -> Result<Vec<DbEmail>, sqlx::Error> {
+    let inner = self.lock();
+    let mut rows: Vec<&DbEmail> = inner
+      .messages
+      .iter()
+      .filter(|m| matches(m, &query.filter))
+      .map(|m| &m.row)
+      .collect();
+    match query.sort {
+      SortKey::ReceivedAt => rows.sort_by_key(|r| r.received_at),
+      SortKey::Subject => rows.sort_by(|a, b| a.subject.cmp(&b.subject)),
+      SortKey::From => rows.sort_by(|a, b| a.from_addr.cmp(&b.from_addr)),
+      SortKey::To => rows.sort_by(|a, b| a.to_recipients.cmp(&b.to_recipients)),
+    }
+    if !query.ascending {
+      rows.reverse();
+    }
+    Ok(page(rows.into_iter().cloned().collect(), query))
+  }
+
+  async fn get_message(&self, id: Uuid) -> Result<Option<DbEmail>, sqlx::Error> {
+    Ok(self.lock().message(id).map(|m| m.row.clone()))
+  }
+
+  async fn recipients(&self, ids: &[Uuid]) -> Result<Vec<RecipientRow>, sqlx::Error> {
+    let inner = self.lock();
+    Ok(
+      ids
+        .iter()
+        .filter_map(|id| inner.message(*id))
+        .flat_map(|m| m.recipients.iter().cloned())
+        .collect(),
+    )
+  }
+
+  async fn raw_source(&self, id: Uuid) -> Result<Option<Option<StoredBody>>, sqlx::Error> {
+    Ok(self.lock().message(id).map(|m| m.raw.clone()))
+  }
+
+  async fn message_headers(&self, id: Uuid) -> Result<Option<Vec<(String, String)>>, sqlx::Error> {
+    let inner = self.lock();
+    let Some(m) = inner.message(id) else {
+      return Ok(None);
+    };
+    let root = inner
+      .parts
+      .iter()
+      .find(|p| p.meta.message_id == id && p.meta.path == "1");
+    Ok(Some(match root {
+      Some(p) => p.headers.clone(),
+      None => {
+        let map: BTreeMap<String, String> = m
+          .row
+          .headers_json
+          .as_deref()
+          .and_then(|j| serde_json::from_str(j).ok())
+          .unwrap_or_default();
+        map.into_iter().collect()
+      }
+    }))
+  }
+
+  async fn delete_messages(&self, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+    let ids: HashSet<&Uuid> = ids.iter().collect();
+    let mut inner = self.lock();
+    let before = inner.messages.len();
+    let sessions: HashSet<Uuid> = inner
+      .messages
+      .iter()
+      .filter(|m| ids.contains(&m.row.id))
+      .filter_map(|m| m.session_id)
+      .collect();
+    inner.messages.retain(|m| !ids.contains(&m.row.id));
+    let deleted = (before - inner.messages.len()) as u64;
+    inner.parts.retain(|p| !ids.contains(&p.meta.message_id));
+    inner
+      .attachments
+      .retain(|a| !ids.contains(&a.meta.message_id));
+    let live: HashSet<Uuid> = inner.messages.iter().filter_map(|m| m.session_id).collect();
+    inner
+      .sessions
+      .retain(|s| !sessions.contains(&s.id) || s.ended_at.is_none() || live.contains(&s.id));
+    Ok(deleted)
+  }
+
+  async fn clear_messages(&self) -> Result<(), sqlx::Error> {
+    let mut inner = self.lock();
+    let sessions: HashSet<Uuid> = inner.messages.iter().filter_map(|m| m.session_id).collect();
+    inner
+      .sessions
+      .retain(|s| !sessions.contains(&s.id) || s.ended_at.is_none());
+    inner.messages.clear();
+    inner.parts.clear();
+    inner.attachments.clear();
+    Ok(())
+  }
+
+  async fn ping(&self) -> Result<(), sqlx::Error> {
+    Ok(())
+  }
+
+  async fn stats(&self) -> Result<StoreStats, sqlx::Error> {
+    let inner = self.lock();
+    Ok(StoreStats {
+      messages: inner.messages.len() as u64,
+      attachment_bytes: inner.attachments.iter().map(|a| a.meta.size as u64).sum(),
+      db_bytes: None,
+    })
+  }
+
+  async fn retention_rows(&self) -> Result<Vec<RetentionRow>, sqlx::Error> {
+    let inner = self.lock();
+    Ok(
+      inner
+        .messages
+        .iter()
+        .map(|m| {
+          let r = &m.row;
+          let size = if r.raw_len > 0 {
+            r.raw_len as u64
+          } else {
+            (r.text_body.as_deref().unwrap_or("").chars().count()
+              + r.html_body.as_deref().unwrap_or("").chars().count()) as u64
+          };
+          let mut mailboxes: Vec<String> = m
+            .recipients
+            .iter()
+            .filter(|r| matches!(r.kind.as_str(), "to" | "cc" | "bcc"))
+            .map(|r| r.address.to_lowercase())
+            .collect();
+          mailboxes.sort();
+          mailboxes.dedup();
+          RetentionRow {
+            id: r.id,
+            received_at: r.received_at,
+            size,
+            mailboxes,
+          }
+        })
+        .collect(),
+    )
+  }
+
+  async fn list_attachments(&self, message_id: Uuid) -> Result<Vec<AttachmentMeta>, sqlx::Error> {
+    let mut list: Vec<AttachmentMeta> = self
+      .lock()
+      .attachments
+      .iter()
+      .filter(|a| a.meta.message_id == message_id)
+      .map(|a| a.meta.clone())
+      .collect();
+    list.sort_by_key(|a| a.id);
+    Ok(list)
+  }
+
+  async fn get_attachment(&self, id: Uuid) -> Result<Option<AttachmentRow>, sqlx::Error> {
+    Ok(
+      self
+        .lock()
+        .attachments
+        .iter()
+        .find(|a| a.meta.id == id)
+        .map(|a| AttachmentRow {
+          id,
+          filename: a.meta.filename.clone(),
+          content_type: a.meta.content_type.clone(),
+          content: a.content.clone(),
+          sha256: a.sha256.clone(),
+        }),
+    )
+  }
+
+  async fn list_parts(&self, message_id: Uuid) -> Result<Vec<PartMeta>, sqlx::Error> {
+    Ok(
+      self
+        .lock()
+        .parts
+        .iter()
+        .filter(|p| p.meta.message_id == message_id)
+        .map(|p| p.meta.clone())
+        .collect(),
+    )
+  }
+
+  async fn get_part(&self, id: Uuid) -> Result<Option<PartRow>, sqlx::Error> {
+    Ok(
+      self
+        .lock()
+        .parts
+        .iter()
+        .find(|p| p.meta.id == id)
+        .map(|p| PartRow {
+          id,
+          filename: p.meta.filename.clone(),
+          content_type: p.meta.content_type.clone(),
+          charset: p.meta.charset.clone(),
+          content: p.content.clone(),
+          sha256: p.sha256.clone(),
+        }),
+    )
+  }
+
+  async fn blob_refs(&self) -> Result<HashSet<String>, sqlx::Error> {
+    let inner = self.lock();
+    let attachments = inner.attachments.iter().filter_map(|a| a.sha256.clone());
+    let raw = inner.messages.iter().filter_map(|m| match &m.raw {
+      Some(StoredBody::Blob(sha)) => Some(sha.clone()),
+      _ => None,
+    });
+    Ok(attachments.chain(raw).collect())
+  }
+
+  async fn list_threads(&self, query: &MessageQuery) -> Result<Vec<ApiThread>, sqlx::Error> {
+    let inner = self.lock();
+    let wanted: HashSet<Uuid> = inner
+      .messages
+      .iter()
+      .filter(|m| matches(m, &query.filter))
+      .map(thread_of)
+      .collect();
+    let mut groups: HashMap<Uuid, Vec<&DbEmail>> = HashMap::new();
+    for m in inner
+      .messages
+      .iter()
+      .filter(|m| wanted.contains(&thread_of(m)))
+    {
+      groups.entry(thread_of(m)).or_default().push(&m.row);
+    }
+    let mut threads: Vec<ApiThread> = groups
+      .into_iter()
+      .map(|(id, mut members)| {
+        members.sort_by_key(|m| m.received_at);
+        let mut participants: Vec<String> = Vec::new();
+        for from in members.iter().filter_map(|m| m.from_addr.clone()) {
+          if !participants.contains(&from) {
+            participants.push(from);
+          }
+        }
+        ApiThread {
+          id,
+          subject: members[0].subject.clone(),
+          message_count: members.len() as i64,
+          first_at: members[0].received_at,
+          last_at: members[members.len() - 1].received_at,
+          participants,
+        }
+      })
+      .collect();
+    threads.sort_by(|a, b| b.last_at.cmp(&a.last_at));
+    Ok(page(threads, query))
+  }
+
+  async fn thread_messages(&self, thread_ids: &[Uuid]) -> Result<Vec<DbEmail>, sqlx::Error> {
+    let inner = self.lock();
+    let mut rows: Vec<DbEmail> = inner
+      .messages
+      .iter()
+      .filter(|m| thread_ids.contains(&thread_of(m)))
+      .map(|m| m.row.clone())
+      .collect();
+    rows.sort_by_key(|r| r.received_at);
+    Ok(rows)
+  }
+
+  async fn insert_session(&self, session: &DbSession) -> Result<(), sqlx::Error> {
+    self.lock().sessions.push(session.clone());
+    Ok(())
+  }
+
+  async fn update_session(&self, session: &DbSession) -> Result<(), sqlx::Error> {
+    let mut inner = self.lock();
+    if let Some(s) = inner.sessions.iter_mut().find(|s| s.id == session.id) {
+      s.ended_at = session.ended_at;
+      s.helo = session.helo.clone();
+      s.auth_user = session.auth_user.clone();
+      s.transcript_json = session.transcript_json.clone();
+    }
+    Ok(())
+  }
+
+  async fn message_session(&self, message_id: Uuid) -> Result<Option<DbSession>, sqlx::Error> {
+    let inner = self.lock();
+    let Some(session_id) = inner.message(message_id).and_then(|m| m.session_id) else {
+      return Ok(None);
+    };
+    Ok(inner.sessions.iter().find(|s| s.id == session_id).cloned())
+  }
+
+  async fn append_log(&self, entry: &NewLogEntry) -> Result<(), sqlx::Error> {
+    let mut inner = self.lock();
+    inner.next_log_id += 1;
+    let id = inner.next_log_id;
+    inner.logs.push(LogEntry {
+      id,
+      ts: entry.ts,
+      level: entry.level.clone(),
+      target: entry.target.clone(),
+      message: entry.message.clone(),
+      fields_json: entry.fields_json.clone(),
+    });
+    Ok(())
+  }
+
+  async fn list_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, sqlx::Error> {
+    let inner = self.lock();
+    let q = query.q.as_deref().map(str::trim).unwrap_or("");
+    let matching = inner.logs.iter().filter(|l| {
+      (query.levels.is_empty() || query.levels.contains(&l.level))
+        && query.since_id.is_none_or(|id| l.id > id)
+        && query.since.is_none_or(|ts| l.ts >= ts)
+        && query.until.is_none_or(|ts| l.ts < ts)
+        && (contains_ci(&l.message, q)
+          || contains_ci(l.target.as_deref().unwrap_or(""), q)
+          || contains_ci(l.fields_json.as_deref().unwrap_or(""), q))
+    });
+    let (limit, offset) = (query.limit as usize, query.offset as usize);
+    let mut logs: Vec<LogEntry> = if query.since_id.is_some() {
+      matching.skip(offset).take(limit).cloned().collect()
+    } else {
+      matching.rev().skip(offset).take(limit).cloned().collect()
+    };
+    if query.since_id.is_none() {
+      logs.reverse();
+    }
+    Ok(logs)
+  }
+
+  async fn insert_token(&self, token: &DbToken) -> Result<(), sqlx::Error> {
+    self.lock().tokens.push(token.clone());
+    Ok(())
+  }
+
+  async fn list_tokens(&self) -> Result<Vec<DbToken>, sqlx::Error> {
+    Ok(self.lock().tokens.clone())
+  }
+
+  async fn delete_token(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut inner = self.lock();
+    let before = inner.tokens.len();
+    inner.tokens.retain(|t| t.id != id);
+    Ok(inner.tokens.len() < before)
+  }
+
+  async fn token_by_hash(&self, sha256: &str) -> Result<Option<DbToken>, sqlx::Error> {
+    let inner = self.lock();
+    Ok(
+      inner
+        .tokens
+        .iter()
+        .find(|t| t.token_sha256 == sha256)
+        .cloned(),
+    )
+  }
+}
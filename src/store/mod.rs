@@ -0,0 +1,170 @@
+//! Storage backends.
+//!
+//! Handlers and the SMTP listener only talk to [`MessageStore`]. `sqlite`
+//! persists to a SQLite database; `postgres` to a PostgreSQL database that
+//! several replicas can share; `memory` keeps everything in process for
+//! tests and throwaway CI runs.
+
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use crate::{
+  blob::StoredBody,
+  models::{
+    attachment::{attachment_meta::AttachmentMeta, attachment_row::AttachmentRow},
+    email::{db_email::DbEmail, new_message::NewMessage, recipient_row::RecipientRow},
+    log::{log_entry::LogEntry, new_log_entry::NewLogEntry},
+    part::{part_meta::PartMeta, part_row::PartRow},
+    session::db_session::DbSession,
+    thread::api_thread::ApiThread,
+    token::db_token::DbToken,
+  },
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgPoolOptions, sqlite::SqlitePoolOptions};
+use std::{collections::HashSet, sync::Arc};
+use uuid::Uuid;
+
+/// Message filters shared by listing, search, threads, waiting and export.
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+  /// Substring of sender, subject, text body or recipients.
+  pub q: Option<String>,
+  /// Substring of a sender name or address.
+  pub from: Option<String>,
+  /// Substring of any To/Cc/Bcc name or address.
+  pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+  #[default]
+  ReceivedAt,
+  Subject,
+  From,
+  To,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageQuery {
+  pub filter: MessageFilter,
+  pub sort: SortKey,
+  pub ascending: bool,
+  /// `None` returns every match.
+  pub limit: Option<u32>,
+  pub offset: u32,
+}
+
+/// Which log entries to list. Without `since_id` the first page holds the
+/// newest entries; with it, entries after that id are paged forward. Each
+/// page is returned oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+  /// Levels to include (`INFO`, `WARN`, ...); empty includes all.
+  pub levels: Vec<String>,
+  pub since_id: Option<i64>,
+  pub since: Option<DateTime<Utc>>,
+  pub until: Option<DateTime<Utc>>,
+  /// Substring of the message, target or fields.
+  pub q: Option<String>,
+  pub limit: u32,
+  pub offset: u32,
+}
+
+/// Totals reported by `/metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct StoreStats {
+  pub messages: u64,
+  /// Sum of stored attachment sizes, including those kept in the blob store.
+  pub attachment_bytes: u64,
+  /// On-disk database size, where the backend can tell.
+  pub db_bytes: Option<u64>,
+}
+
+/// What retention needs to know about a message.
+#[derive(Debug, Clone)]
+pub struct RetentionRow {
+  pub id: Uuid,
+  pub received_at: DateTime<Utc>,
+  /// Raw size, or body length for messages posted as JSON.
+  pub size: u64,
+  /// Lowercased To/Cc/Bcc addresses.
+  pub mailboxes: Vec<String>,
+}
+
+#[async_trait]
+pub trait MessageStore: Send + Sync {
+  /// Store a message with its recipients, MIME parts and attachments, and
+  /// put it into a thread.
+  async fn insert_message(&self, msg: &NewMessage) -> Result<(), sqlx::Error>;
+  async fn list_messages(&self, query: &MessageQuery) -> Result<Vec<DbEmail>, sqlx::Error>;
+  async fn get_message(&self, id: Uuid) -> Result<Option<DbEmail>, sqlx::Error>;
+  /// Stored address rows for the given messages, in header order.
+  async fn recipients(&self, ids: &[Uuid]) -> Result<Vec<RecipientRow>, sqlx::Error>;
+  /// `None` when the message does not exist; `Some(None)` when it has no source.
+  async fn raw_source(&self, id: Uuid) -> Result<Option<Option<StoredBody>>, sqlx::Error>;
+  /// Top-level headers in original order, or the stored header map sorted
+  /// by name for messages without a MIME tree.
+  async fn message_headers(&self, id: Uuid) -> Result<Option<Vec<(String, String)>>, sqlx::Error>;
+  /// Delete messages with everything stored for them; returns how many went.
+  async fn delete_messages(&self, ids: &[Uuid]) -> Result<u64, sqlx::Error>;
+  async fn clear_messages(&self) -> Result<(), sqlx::Error>;
+  async fn retention_rows(&self) -> Result<Vec<RetentionRow>, sqlx::Error>;
+  async fn stats(&self) -> Result<StoreStats, sqlx::Error>;
+  /// Cheap round trip to check the database is reachable.
+  async fn ping(&self) -> Result<(), sqlx::Error>;
+
+  async fn list_attachments(&self, message_id: Uuid) -> Result<Vec<AttachmentMeta>, sqlx::Error>;
+  async fn get_attachment(&self, id: Uuid) -> Result<Option<AttachmentRow>, sqlx::Error>;
+  async fn list_parts(&self, message_id: Uuid) -> Result<Vec<PartMeta>, sqlx::Error>;
+  async fn get_part(&self, id: Uuid) -> Result<Option<PartRow>, sqlx::Error>;
+  /// Every blob hash still referenced by a stored message.
+  async fn blob_refs(&self) -> Result<HashSet<String>, sqlx::Error>;
+
+  /// Threads with at least one matching message, most recently active first.
+  async fn list_threads(&self, query: &MessageQuery) -> Result<Vec<ApiThread>, sqlx::Error>;
+  /// Messages of the given threads, oldest first.
+  async fn thread_messages(&self, thread_ids: &[Uuid]) -> Result<Vec<DbEmail>, sqlx::Error>;
+
+  async fn insert_session(&self, session: &DbSession) -> Result<(), sqlx::Error>;
+  async fn update_session(&self, session: &DbSession) -> Result<(), sqlx::Error>;
+  /// The SMTP session a message arrived in.
+  async fn message_session(&self, message_id: Uuid) -> Result<Option<DbSession>, sqlx::Error>;
+
+  async fn append_log(&self, entry: &NewLogEntry) -> Result<(), sqlx::Error>;
+  async fn list_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, sqlx::Error>;
+
+  async fn insert_token(&self, token: &DbToken) -> Result<(), sqlx::Error>;
+  /// Managed API tokens, oldest first.
+  async fn list_tokens(&self) -> Result<Vec<DbToken>, sqlx::Error>;
+  /// Whether a token with this id existed.
+  async fn delete_token(&self, id: Uuid) -> Result<bool, sqlx::Error>;
+  async fn token_by_hash(&self, sha256: &str) -> Result<Option<DbToken>, sqlx::Error>;
+}
+
+/// Open the store named by a `FAUXMAIL_DATABASE` URL: `memory://`,
+/// `postgres://` (or `postgresql://`), or a `sqlite:` URL.
+pub async fn open(url: &str) -> Result<Arc<dyn MessageStore>, sqlx::Error> {
+  if url == "memory" || url.starts_with("memory:") {
+    return Ok(Arc::new(memory::MemoryStore::default()));
+  }
+  if url.starts_with("postgres:") || url.starts_with("postgresql:") {
+    let pool = PgPoolOptions::new().max_connections(5).connect(url).await?;
+    crate::db::postgres::run_migrations(&pool).await?;
+    return Ok(Arc::new(postgres::PostgresStore::new(pool)));
+  }
+  let url = crate::db::ensure_sqlite_path(url);
+  let pool = SqlitePoolOptions::new()
+    .max_connections(5)
+    .connect(&url)
+    .await?;
+  crate::db::run_migrations(&pool).await?;
+  Ok(Arc::new(sqlite::SqliteStore::new(pool)))
+}
+
+/// Case-insensitive substring match, like SQLite's `LIKE '%needle%'`.
+pub(crate) fn contains_ci(haystack: &str, needle: &str) -> bool {
+  needle.is_empty() || crate::util::find_ascii_ci(haystack, needle).is_some()
+}
@@ -0,0 +1,599 @@
+//! PostgreSQL-backed [`MessageStore`], for replicas sharing one database.
+//!
+//! Queries mirror `sqlite` so both backends answer the API identically:
+//! `ILIKE` stands in for SQLite's case-insensitive `LIKE`, and text sorts
+//! use byte order with NULLs first, as SQLite does.
+
+use super::{
+  LogQuery, MessageFilter, MessageQuery, MessageStore, RetentionRow, SortKey, StoreStats,
+};
+use crate::{
+  blob::StoredBody,
+  models::{
+    attachment::{attachment_meta::AttachmentMeta, attachment_row::AttachmentRow},
+    email::{
+      address::Addresses, db_email::DbEmail, new_message::NewMessage, recipient_row::RecipientRow,
+    },
+    log::{log_entry::LogEntry, new_log_entry::NewLogEntry},
+    part::{part_meta::PartMeta, part_row::PartRow},
+    session::db_session::DbSession,
+    thread::{api_thread::ApiThread, db_thread::DbThread},
+    token::db_token::DbToken,
+  },
+  util::ThreadHeaders,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool, Postgres, QueryBuilder};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+pub struct PostgresStore {
+  pool: PgPool,
+}
+
+impl PostgresStore {
+  /// Wrap a pool whose schema is already migrated.
+  pub fn new(pool: PgPool) -> Self {
+    PostgresStore { pool }
+  }
+
+  pub fn pool(&self) -> &PgPool {
+    &self.pool
+  }
+}
+
+/// Same rules as the SQLite `assign_thread`.
+async fn assign_thread(
+  conn: &mut PgConnection,
+  id: Uuid,
+  thread: &ThreadHeaders,
+) -> Result<(), sqlx::Error> {
+  let mut related: Vec<String> = thread.references.clone();
+  related.extend(thread.in_reply_to.clone());
+  related.extend(thread.message_id.clone());
+  let mut threads: Vec<Uuid> = Vec::new();
+  if !related.is_empty() {
+    threads.extend(
+      sqlx::query_scalar::<_, Uuid>(
+        "SELECT coalesce(thread_id, id) FROM messages WHERE id != $1 AND message_id_header = ANY($2) ORDER BY received_at",
+      )
+      .bind(id)
+      .bind(&related)
+      .fetch_all(&mut *conn)
+      .await?,
+    );
+  }
+  if let Some(own) = &thread.message_id {
+    let quoted = serde_json::to_string(own).unwrap_or_default();
+    let children: Vec<Uuid> = sqlx::query_scalar(
+      "SELECT coalesce(thread_id, id) FROM messages WHERE id != $1 AND (in_reply_to = $2 OR strpos(coalesce(references_json, ''), $3) > 0) ORDER BY received_at",
+    )
+    .bind(id)
+    .bind(own)
+    .bind(quoted)
+    .fetch_all(&mut *conn)
+    .await?;
+    threads.extend(children);
+  }
+
+  let thread_id = threads.first().copied().unwrap_or(id);
+  sqlx::query("UPDATE messages SET thread_id = $1 WHERE id = $2")
+    .bind(thread_id)
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+  let others: Vec<Uuid> = threads.into_iter().filter(|t| *t != thread_id).collect();
+  if !others.is_empty() {
+    sqlx::query("UPDATE messages SET thread_id = $1 WHERE coalesce(thread_id, id) = ANY($2)")
+      .bind(thread_id)
+      .bind(&others)
+      .execute(&mut *conn)
+      .await?;
+  }
+  Ok(())
+}
+
+/// `References` as stored: a JSON array, or NULL when there are none.
+fn references_json(thread: &ThreadHeaders) -> Option<String> {
+  (!thread.references.is_empty())
+    .then(|| serde_json::to_string(&thread.references).unwrap_or_else(|_| "[]".to_string()))
+}
+
+/// Build the `WHERE` clause (with leading space) and its bind values, all
+/// sharing one `$1` pattern per filter.
+fn filter_sql(f: &MessageFilter) -> (String, Vec<String>) {
+  let mut conds = Vec::new();
+  let mut binds = Vec::new();
+  if let Some(q) = f.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+    binds.push(format!("%{q}%"));
+    let n = binds.len();
+    conds.push(format!("(coalesce(from_addr,'') ILIKE ${n} OR coalesce(subject,'') ILIKE ${n} OR coalesce(text_body,'') ILIKE ${n} OR to_recipients ILIKE ${n})"));
+  }
+  let address_filters = [
+    (f.from.as_deref(), "from_addr", "'from', 'sender'"),
+    (f.to.as_deref(), "to_recipients", "'to', 'cc', 'bcc'"),
+  ];
+  for (value, column, kinds) in address_filters {
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+      continue;
+    };
+    binds.push(format!("%{value}%"));
+    let n = binds.len();
+    conds.push(format!(
+      "(coalesce({column},'') ILIKE ${n} OR EXISTS (SELECT 1 FROM recipients r WHERE r.message_id = messages.id AND r.kind IN ({kinds}) AND (r.address ILIKE ${n} OR coalesce(r.name,'') ILIKE ${n})))"
+    ));
+  }
+  if conds.is_empty() {
+    (String::new(), binds)
+  } else {
+    (format!(" WHERE {}", conds.join(" AND ")), binds)
+  }
+}
+
+/// `LIMIT`/`OFFSET` clause (with leading space) for a query.
+fn paging_sql(q: &MessageQuery) -> String {
+  match q.limit {
+    Some(limit) => format!(" LIMIT {limit} OFFSET {}", q.offset),
+    None if q.offset > 0 => format!(" OFFSET {}", q.offset),
+    None => String::new(),
+  }
+}
+
+#[async_trait]
+impl MessageStore for PostgresStore {
+  async fn insert_message(&self, msg: &NewMessage) -> Result<(), sqlx::Error> {
+    let to_json = serde_json::to_string(&msg.to).unwrap_or_else(|_| "[]".to_string());
+    let headers_json = (!msg.headers.is_empty())
+      .then(|| serde_json::to_string(&msg.headers).unwrap_or_else(|_| "{}".to_string()));
+    let envelope_to_json = (!msg.envelope_to.is_empty())
+      .then(|| serde_json::to_string(&msg.envelope_to).unwrap_or_else(|_| "[]".to_string()));
+    let mut tx = self.pool.begin().await?;
+    sqlx::query(
+      "INSERT INTO messages (id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len, session_id, envelope_from, envelope_to, helo, client_addr, raw_source, raw_sha256, message_id_header, in_reply_to, references_json) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)",
+    )
+    .bind(msg.id)
+    .bind(msg.received_at)
+    .bind(&msg.from)
+    .bind(to_json)
+    .bind(&msg.subject)
+    .bind(&msg.text)
+    .bind(&msg.html)
+    .bind(headers_json)
+    .bind(msg.raw_len)
+    .bind(msg.session_id)
+    .bind(&msg.envelope_from)
+    .bind(envelope_to_json)
+    .bind(&msg.helo)
+    .bind(&msg.client_addr)
+    .bind(msg.raw.as_ref().filter(|_| msg.raw_sha256.is_none()))
+    .bind(&msg.raw_sha256)
+    .bind(&msg.thread.message_id)
+    .bind(&msg.thread.in_reply_to)
+    .bind(references_json(&msg.thread))
+    .execute(&mut *tx)
+    .await?;
+    assign_thread(&mut tx, msg.id, &msg.thread).await?;
+
+    for kind in Addresses::KINDS {
+      for (position, a) in msg.addresses.get(kind).into_iter().flatten().enumerate() {
+        sqlx::query(
+          "INSERT INTO recipients (message_id, kind, position, name, address) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(msg.id)
+        .bind(kind)
+        .bind(position as i64)
+        .bind(&a.name)
+        .bind(&a.address)
+        .execute(&mut *tx)
+        .await?;
+      }
+    }
+
+    for (seq, p) in msg.parts.iter().enumerate() {
+      let headers_json = serde_json::to_string(&p.headers).unwrap_or_else(|_| "[]".to_string());
+      sqlx::query(
+        "INSERT INTO parts (id, message_id, seq, path, parent_path, content_type, charset, transfer_encoding, content_id, disposition, filename, headers_json, size, content, sha256) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+      )
+      .bind(Uuid::new_v4())
+      .bind(msg.id)
+      .bind(seq as i64)
+      .bind(&p.path)
+      .bind(&p.parent_path)
+      .bind(&p.content_type)
+      .bind(&p.charset)
+      .bind(&p.transfer_encoding)
+      .bind(&p.content_id)
+      .bind(&p.disposition)
+      .bind(&p.filename)
+      .bind(headers_json)
+      .bind(p.body.as_ref().map_or(0, |b| b.len() as i64))
+      .bind(p.body.as_ref().map(|b| if p.sha256.is_some() { &[][..] } else { b.as_slice() }))
+      .bind(&p.sha256)
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    for a in &msg.attachments {
+      sqlx::query(
+        "INSERT INTO attachments (id, message_id, filename, content_type, size, content, content_id, inline, sha256) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+      )
+      .bind(Uuid::new_v4())
+      .bind(msg.id)
+      .bind(&a.filename)
+      .bind(&a.content_type)
+      .bind(a.data.len() as i64)
+      .bind(if a.sha256.is_some() { &[][..] } else { a.data.as_slice() })
+      .bind(&a.content_id)
+      .bind(a.inline)
+      .bind(&a.sha256)
+      .execute(&mut *tx)
+      .await?;
+    }
+    tx.commit().await
+  }
+
+  async fn list_messages(&self, query: &MessageQuery) -> Result<Vec<DbEmail>, sqlx::Error> {
+    let (where_sql, binds) = filter_sql(&query.filter);
+    let order_by = match query.sort {
+      SortKey::ReceivedAt => "received_at",
+      SortKey::Subject => "subject COLLATE \"C\"",
+      SortKey::From => "from_addr COLLATE \"C\"",
+      SortKey::To => "to_recipients COLLATE \"C\"",
+    };
+    let dir = if query.ascending {
+      "ASC NULLS FIRST"
+    } else {
+      "DESC NULLS LAST"
+    };
+    let sql = format!(
+      "SELECT {} FROM messages{where_sql} ORDER BY {order_by} {dir}{}",
+      DbEmail::COLUMNS,
+      paging_sql(query)
+    );
+    let mut q = sqlx::query_as::<_, DbEmail>(&sql);
+    for b in &binds {
+      q = q.bind(b);
+    }
+    q.fetch_all(&self.pool).await
+  }
+
+  async fn get_message(&self, id: Uuid) -> Result<Option<DbEmail>, sqlx::Error> {
+    sqlx::query_as::<_, DbEmail>(&format!(
+      "SELECT {} FROM messages WHERE id = $1",
+      DbEmail::COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn recipients(&self, ids: &[Uuid]) -> Result<Vec<RecipientRow>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT message_id, kind, name, address FROM recipients WHERE message_id = ANY($1) ORDER BY message_id, kind, position",
+    )
+    .bind(ids)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn raw_source(&self, id: Uuid) -> Result<Option<Option<StoredBody>>, sqlx::Error> {
+    let row: Option<(Option<Vec<u8>>, Option<String>)> =
+      sqlx::query_as("SELECT raw_source, raw_sha256 FROM messages WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+    Ok(row.map(|(raw, sha)| match sha {
+      Some(sha) => Some(StoredBody::Blob(sha)),
+      None => raw.map(StoredBody::Bytes),
+    }))
+  }
+
+  async fn message_headers(&self, id: Uuid) -> Result<Option<Vec<(String, String)>>, sqlx::Error> {
+    let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+      "SELECT m.headers_json, p.headers_json FROM messages m LEFT JOIN parts p ON p.message_id = m.id AND p.path = '1' WHERE m.id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.map(|(map_json, root_json)| match root_json {
+      Some(json) => serde_json::from_str(&json).unwrap_or_default(),
+      None => {
+        let map: BTreeMap<String, String> = map_json
+          .and_then(|j| serde_json::from_str(&j).ok())
+          .unwrap_or_default();
+        map.into_iter().collect()
+      }
+    }))
+  }
+
+  async fn delete_messages(&self, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+    crate::db::postgres::delete_messages(&self.pool, ids).await
+  }
+
+  async fn clear_messages(&self) -> Result<(), sqlx::Error> {
+    let mut tx = self.pool.begin().await?;
+    for sql in [
+      "DELETE FROM smtp_sessions WHERE ended_at IS NOT NULL AND id IN (SELECT session_id FROM messages)",
+      "DELETE FROM recipients",
+      "DELETE FROM parts",
+      "DELETE FROM messages",
+    ] {
+      sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await
+  }
+
+  async fn ping(&self) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn stats(&self) -> Result<StoreStats, sqlx::Error> {
+    let (messages, attachment_bytes, db_bytes): (i64, i64, i64) = sqlx::query_as(
+      "SELECT (SELECT COUNT(*) FROM messages), (SELECT coalesce(SUM(size), 0)::BIGINT FROM attachments), pg_database_size(current_database())",
+    )
+    .fetch_one(&self.pool)
+    .await?;
+    Ok(StoreStats {
+      messages: messages as u64,
+      attachment_bytes: attachment_bytes as u64,
+      db_bytes: Some(db_bytes as u64),
+    })
+  }
+
+  async fn retention_rows(&self) -> Result<Vec<RetentionRow>, sqlx::Error> {
+    // Messages posted as JSON have no raw size; count their bodies instead.
+    let rows: Vec<(Uuid, DateTime<Utc>, i64)> = sqlx::query_as(
+      "SELECT id, received_at, CASE WHEN raw_len > 0 THEN raw_len ELSE length(coalesce(text_body, '')) + length(coalesce(html_body, '')) END FROM messages",
+    )
+    .fetch_all(&self.pool)
+    .await?;
+    let mailboxes: Vec<(Uuid, String)> = sqlx::query_as(
+      "SELECT DISTINCT message_id, lower(address) FROM recipients WHERE kind IN ('to', 'cc', 'bcc')",
+    )
+    .fetch_all(&self.pool)
+    .await?;
+    let mut by_message: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (id, mailbox) in mailboxes {
+      by_message.entry(id).or_default().push(mailbox);
+    }
+    Ok(
+      rows
+        .into_iter()
+        .map(|(id, received_at, size)| RetentionRow {
+          id,
+          received_at,
+          size: size.max(0) as u64,
+          mailboxes: by_message.remove(&id).unwrap_or_default(),
+        })
+        .collect(),
+    )
+  }
+
+  async fn list_attachments(&self, message_id: Uuid) -> Result<Vec<AttachmentMeta>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, message_id, filename, content_type, size, content_id, inline FROM attachments WHERE message_id = $1 ORDER BY id",
+    )
+    .bind(message_id)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn get_attachment(&self, id: Uuid) -> Result<Option<AttachmentRow>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, filename, content_type, content, sha256 FROM attachments WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn list_parts(&self, message_id: Uuid) -> Result<Vec<PartMeta>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, message_id, path, parent_path, content_type, charset, transfer_encoding, content_id, disposition, filename, size FROM parts WHERE message_id = $1 ORDER BY seq",
+    )
+    .bind(message_id)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn get_part(&self, id: Uuid) -> Result<Option<PartRow>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, filename, content_type, charset, content, sha256 FROM parts WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn blob_refs(&self) -> Result<HashSet<String>, sqlx::Error> {
+    sqlx::query_scalar(
+      "SELECT sha256 FROM attachments WHERE sha256 IS NOT NULL UNION SELECT raw_sha256 FROM messages WHERE raw_sha256 IS NOT NULL",
+    )
+    .fetch_all(&self.pool)
+    .await
+    .map(|refs: Vec<String>| refs.into_iter().collect())
+  }
+
+  async fn list_threads(&self, query: &MessageQuery) -> Result<Vec<ApiThread>, sqlx::Error> {
+    let (where_sql, binds) = filter_sql(&query.filter);
+    let filter = if where_sql.is_empty() {
+      String::new()
+    } else {
+      format!(
+        " WHERE coalesce(thread_id, id) IN (SELECT coalesce(thread_id, id) FROM messages{where_sql})"
+      )
+    };
+    let sql = format!(
+      "SELECT t.id, \
+         (SELECT subject FROM messages f WHERE coalesce(f.thread_id, f.id) = t.id ORDER BY f.received_at LIMIT 1) AS subject, \
+         t.message_count, t.first_at, t.last_at, t.participants_json \
+       FROM (SELECT coalesce(thread_id, id) AS id, COUNT(*) AS message_count, \
+           MIN(received_at) AS first_at, MAX(received_at) AS last_at, \
+           coalesce(json_agg(DISTINCT from_addr)::text, '[]') AS participants_json \
+         FROM messages{filter} GROUP BY coalesce(thread_id, id)) t \
+       ORDER BY t.last_at DESC{}",
+      paging_sql(query)
+    );
+    let mut q = sqlx::query_as::<_, DbThread>(&sql);
+    for b in &binds {
+      q = q.bind(b);
+    }
+    let rows = q.fetch_all(&self.pool).await?;
+    Ok(rows.into_iter().map(ApiThread::from).collect())
+  }
+
+  async fn thread_messages(&self, thread_ids: &[Uuid]) -> Result<Vec<DbEmail>, sqlx::Error> {
+    sqlx::query_as::<_, DbEmail>(&format!(
+      "SELECT {} FROM messages WHERE coalesce(thread_id, id) = ANY($1) ORDER BY received_at",
+      DbEmail::COLUMNS
+    ))
+    .bind(thread_ids)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn insert_session(&self, s: &DbSession) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO smtp_sessions (id, started_at, ended_at, client_addr, helo, auth_user, transcript_json) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(s.id)
+    .bind(s.started_at)
+    .bind(s.ended_at)
+    .bind(&s.client_addr)
+    .bind(&s.helo)
+    .bind(&s.auth_user)
+    .bind(&s.transcript_json)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn update_session(&self, s: &DbSession) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "UPDATE smtp_sessions SET ended_at = $1, helo = $2, auth_user = $3, transcript_json = $4 WHERE id = $5",
+    )
+    .bind(s.ended_at)
+    .bind(&s.helo)
+    .bind(&s.auth_user)
+    .bind(&s.transcript_json)
+    .bind(s.id)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn message_session(&self, message_id: Uuid) -> Result<Option<DbSession>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT s.id, s.started_at, s.ended_at, s.client_addr, s.helo, s.auth_user, s.transcript_json FROM smtp_sessions s JOIN messages m ON m.session_id = s.id WHERE m.id = $1",
+    )
+    .bind(message_id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn append_log(&self, entry: &NewLogEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO logs (ts, level, target, message, fields_json) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(entry.ts)
+    .bind(&entry.level)
+    .bind(&entry.target)
+    .bind(&entry.message)
+    .bind(&entry.fields_json)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn list_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, sqlx::Error> {
+    let mut b = QueryBuilder::<Postgres>::new(format!(
+      "SELECT {} FROM logs WHERE 1 = 1",
+      LogEntry::COLUMNS
+    ));
+    if !query.levels.is_empty() {
+      b.push(" AND level IN (");
+      let mut levels = b.separated(", ");
+      for level in &query.levels {
+        levels.push_bind(level);
+      }
+      levels.push_unseparated(")");
+    }
+    if let Some(id) = query.since_id {
+      b.push(" AND id > ").push_bind(id);
+    }
+    if let Some(ts) = query.since {
+      b.push(" AND ts >= ").push_bind(ts);
+    }
+    if let Some(ts) = query.until {
+      b.push(" AND ts < ").push_bind(ts);
+    }
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+      let pattern = format!("%{q}%");
+      b.push(" AND (message ILIKE ")
+        .push_bind(pattern.clone())
+        .push(" OR coalesce(target, '') ILIKE ")
+        .push_bind(pattern.clone())
+        .push(" OR coalesce(fields_json, '') ILIKE ")
+        .push_bind(pattern)
+        .push(")");
+    }
+    let forward = query.since_id.is_some();
+    b.push(if forward {
+      " ORDER BY id ASC"
+    } else {
+      " ORDER BY id DESC"
+    });
+    b.push(" LIMIT ")
+      .push_bind(query.limit as i64)
+      .push(" OFFSET ")
+      .push_bind(query.offset as i64);
+    let mut logs: Vec<LogEntry> = b.build_query_as().fetch_all(&self.pool).await?;
+    if !forward {
+      logs.reverse();
+    }
+    Ok(logs)
+  }
+
+  async fn insert_token(&self, t: &DbToken) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO api_tokens (id, name, token_sha256, scopes, created_at) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(t.id)
+    .bind(&t.name)
+    .bind(&t.token_sha256)
+    .bind(&t.scopes)
+    .bind(t.created_at)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn list_tokens(&self) -> Result<Vec<DbToken>, sqlx::Error> {
+    let sql = format!(
+      "SELECT {} FROM api_tokens ORDER BY created_at",
+      DbToken::COLUMNS
+    );
+    sqlx::query_as(&sql).fetch_all(&self.pool).await
+  }
+
+  async fn delete_token(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM api_tokens WHERE id = $1")
+      .bind(id)
+      .execute(&self.pool)
+      .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  async fn token_by_hash(&self, sha256: &str) -> Result<Option<DbToken>, sqlx::Error> {
+    let sql = format!(
+      "SELECT {} FROM api_tokens WHERE token_sha256 = $1",
+      DbToken::COLUMNS
+    );
+    sqlx::query_as(&sql)
+      .bind(sha256)
+      .fetch_optional(&self.pool)
+      .await
+  }
+}
@@ -0,0 +1,605 @@
+//! SQLite-backed [`MessageStore`].
+
+use super::{
+  LogQuery, MessageFilter, MessageQuery, MessageStore, RetentionRow, SortKey, StoreStats,
+};
+use crate::{
+  blob::StoredBody,
+  models::{
+    attachment::{attachment_meta::AttachmentMeta, attachment_row::AttachmentRow},
+    email::{
+      address::Addresses, db_email::DbEmail, new_message::NewMessage, recipient_row::RecipientRow,
+    },
+    log::{log_entry::LogEntry, new_log_entry::NewLogEntry},
+    part::{part_meta::PartMeta, part_row::PartRow},
+    session::db_session::DbSession,
+    thread::{api_thread::ApiThread, db_thread::DbThread},
+    token::db_token::DbToken,
+  },
+  util::ThreadHeaders,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+pub struct SqliteStore {
+  pool: SqlitePool,
+}
+
+impl SqliteStore {
+  /// Wrap a pool whose schema is already migrated.
+  pub fn new(pool: SqlitePool) -> Self {
+    SqliteStore { pool }
+  }
+
+  pub fn pool(&self) -> &SqlitePool {
+    &self.pool
+  }
+}
+
+/// Put a freshly stored message into a conversation.
+///
+/// It joins the thread of any stored message it replies to, references,
+/// or shares a Message-ID with. Replies that arrived before it are pulled
+/// into the same thread, merging conversations split by arrival order.
+async fn assign_thread(
+  conn: &mut SqliteConnection,
+  id: Uuid,
+  thread: &ThreadHeaders,
+) -> Result<(), sqlx::Error> {
+  let mut related: Vec<&String> = thread.references.iter().collect();
+  related.extend(&thread.in_reply_to);
+  related.extend(&thread.message_id);
+  let mut threads: Vec<Uuid> = Vec::new();
+  if !related.is_empty() {
+    let placeholders = vec!["?"; related.len()].join(", ");
+    let sql = format!(
+      "SELECT coalesce(thread_id, id) FROM messages WHERE id != ? AND message_id_header IN ({placeholders}) ORDER BY received_at"
+    );
+    let mut query = sqlx::query_scalar::<_, Uuid>(&sql).bind(id);
+    for r in &related {
+      query = query.bind(r);
+    }
+    threads.extend(query.fetch_all(&mut *conn).await?);
+  }
+  if let Some(own) = &thread.message_id {
+    let quoted = serde_json::to_string(own).unwrap_or_default();
+    let children: Vec<Uuid> = sqlx::query_scalar(
+      "SELECT coalesce(thread_id, id) FROM messages WHERE id != ? AND (in_reply_to = ? OR instr(coalesce(references_json, ''), ?) > 0) ORDER BY received_at",
+    )
+    .bind(id)
+    .bind(own)
+    .bind(quoted)
+    .fetch_all(&mut *conn)
+    .await?;
+    threads.extend(children);
+  }
+
+  let thread_id = threads.first().copied().unwrap_or(id);
+  sqlx::query("UPDATE messages SET thread_id = ? WHERE id = ?")
+    .bind(thread_id)
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+  for other in threads.iter().filter(|t| **t != thread_id) {
+    sqlx::query("UPDATE messages SET thread_id = ? WHERE coalesce(thread_id, id) = ?")
+      .bind(thread_id)
+      .bind(other)
+      .execute(&mut *conn)
+      .await?;
+  }
+  Ok(())
+}
+
+/// `References` as stored: a JSON array, or NULL when there are none.
+fn references_json(thread: &ThreadHeaders) -> Option<String> {
+  (!thread.references.is_empty())
+    .then(|| serde_json::to_string(&thread.references).unwrap_or_else(|_| "[]".to_string()))
+}
+
+/// Build the `WHERE` clause (with leading space) and its bind values.
+fn filter_sql(f: &MessageFilter) -> (String, Vec<String>) {
+  let mut conds = Vec::new();
+  let mut binds = Vec::new();
+  if let Some(q) = f.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+    conds.push("(coalesce(from_addr,'') LIKE ? OR coalesce(subject,'') LIKE ? OR coalesce(text_body,'') LIKE ? OR to_recipients LIKE ?)".to_string());
+    binds.extend(std::iter::repeat_n(format!("%{q}%"), 4));
+  }
+  let address_filters = [
+    (f.from.as_deref(), "from_addr", "'from', 'sender'"),
+    (f.to.as_deref(), "to_recipients", "'to', 'cc', 'bcc'"),
+  ];
+  for (value, column, kinds) in address_filters {
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+      continue;
+    };
+    conds.push(format!(
+      "(coalesce({column},'') LIKE ? OR EXISTS (SELECT 1 FROM recipients r WHERE r.message_id = messages.id AND r.kind IN ({kinds}) AND (r.address LIKE ? OR coalesce(r.name,'') LIKE ?)))"
+    ));
+    binds.extend(std::iter::repeat_n(format!("%{value}%"), 3));
+  }
+  if conds.is_empty() {
+    (String::new(), binds)
+  } else {
+    (format!(" WHERE {}", conds.join(" AND ")), binds)
+  }
+}
+
+/// `LIMIT`/`OFFSET` clause (with leading space) for a query.
+fn paging_sql(q: &MessageQuery) -> String {
+  match q.limit {
+    Some(limit) => format!(" LIMIT {limit} OFFSET {}", q.offset),
+    None if q.offset > 0 => format!(" LIMIT -1 OFFSET {}", q.offset),
+    None => String::new(),
+  }
+}
+
+#[async_trait]
+impl MessageStore for SqliteStore {
+  async fn insert_message(&self, msg: &NewMessage) -> Result<(), sqlx::Error> {
+    let to_json = serde_json::to_string(&msg.to).unwrap_or_else(|_| "[]".to_string());
+    let headers_json = (!msg.headers.is_empty())
+      .then(|| serde_json::to_string(&msg.headers).unwrap_or_else(|_| "{}".to_string()));
+    let envelope_to_json = (!msg.envelope_to.is_empty())
+      .then(|| serde_json::to_string(&msg.envelope_to).unwrap_or_else(|_| "[]".to_string()));
+    let mut tx = self.pool.begin().await?;
+    sqlx::query(
+      "INSERT INTO messages (id, received_at, from_addr, to_recipients, subject, text_body, html_body, headers_json, raw_len, session_id, envelope_from, envelope_to, helo, client_addr, raw_source, raw_sha256, message_id_header, in_reply_to, references_json) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(msg.id)
+    .bind(msg.received_at)
+    .bind(&msg.from)
+    .bind(to_json)
+    .bind(&msg.subject)
+    .bind(&msg.text)
+    .bind(&msg.html)
+    .bind(headers_json)
+    .bind(msg.raw_len)
+    .bind(msg.session_id)
+    .bind(&msg.envelope_from)
+    .bind(envelope_to_json)
+    .bind(&msg.helo)
+    .bind(&msg.client_addr)
+    .bind(msg.raw.as_ref().filter(|_| msg.raw_sha256.is_none()))
+    .bind(&msg.raw_sha256)
+    .bind(&msg.thread.message_id)
+    .bind(&msg.thread.in_reply_to)
+    .bind(references_json(&msg.thread))
+    .execute(&mut *tx)
+    .await?;
+    assign_thread(&mut tx, msg.id, &msg.thread).await?;
+
+    for kind in Addresses::KINDS {
+      for (position, a) in msg.addresses.get(kind).into_iter().flatten().enumerate() {
+        sqlx::query(
+          "INSERT INTO recipients (message_id, kind, position, name, address) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(msg.id)
+        .bind(kind)
+        .bind(position as i64)
+        .bind(&a.name)
+        .bind(&a.address)
+        .execute(&mut *tx)
+        .await?;
+      }
+    }
+
+    for (seq, p) in msg.parts.iter().enumerate() {
+      let headers_json = serde_json::to_string(&p.headers).unwrap_or_else(|_| "[]".to_string());
+      sqlx::query(
+        "INSERT INTO parts (id, message_id, seq, path, parent_path, content_type, charset, transfer_encoding, content_id, disposition, filename, headers_json, size, content, sha256) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+      )
+      .bind(Uuid::new_v4())
+      .bind(msg.id)
+      .bind(seq as i64)
+      .bind(&p.path)
+      .bind(&p.parent_path)
+      .bind(&p.content_type)
+      .bind(&p.charset)
+      .bind(&p.transfer_encoding)
+      .bind(&p.content_id)
+      .bind(&p.disposition)
+      .bind(&p.filename)
+      .bind(headers_json)
+      .bind(p.body.as_ref().map_or(0, |b| b.len() as i64))
+      .bind(p.body.as_ref().map(|b| if p.sha256.is_some() { &[][..] } else { b.as_slice() }))
+      .bind(&p.sha256)
+      .execute(&mut *tx)
+      .await?;
+    }
+
+    for a in &msg.attachments {
+      sqlx::query(
+        "INSERT INTO attachments (id, message_id, filename, content_type, size, content, content_id, inline, sha256) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+      )
+      .bind(Uuid::new_v4())
+      .bind(msg.id)
+      .bind(&a.filename)
+      .bind(&a.content_type)
+      .bind(a.data.len() as i64)
+      .bind(if a.sha256.is_some() { &[][..] } else { a.data.as_slice() })
+      .bind(&a.content_id)
+      .bind(a.inline)
+      .bind(&a.sha256)
+      .execute(&mut *tx)
+      .await?;
+    }
+    tx.commit().await
+  }
+
+  async fn list_messages(&self, query: &MessageQuery) -> Result<Vec<DbEmail>, sqlx::Error> {
+    let (where_sql, binds) = filter_sql(&query.filter);
+    let order_by = match query.sort {
+      SortKey::ReceivedAt => "received_at",
+      SortKey::Subject => "subject",
+      SortKey::From => "from_addr",
+      SortKey::To => "to_recipients",
+    };
+    let dir = if query.ascending { "ASC" } else { "DESC" };
+    let sql = format!(
+      "SELECT {} FROM messages{where_sql} ORDER BY {order_by} {dir}{}",
+      DbEmail::COLUMNS,
+      paging_sql(query)
+    );
+    let mut q = sqlx::query_as::<_, DbEmail>(&sql);
+    for b in &binds {
+      q = q.bind(b);
+    }
+    q.fetch_all(&self.pool).await
+  }
+
+  async fn get_message(&self, id: Uuid) -> Result<Option<DbEmail>, sqlx::Error> {
+    sqlx::query_as::<_, DbEmail>(&format!(
+      "SELECT {} FROM messages WHERE id = ?",
+      DbEmail::COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn recipients(&self, ids: &[Uuid]) -> Result<Vec<RecipientRow>, sqlx::Error> {
+    if ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let sql = format!(
+      "SELECT message_id, kind, name, address FROM recipients WHERE message_id IN ({placeholders}) ORDER BY message_id, kind, position"
+    );
+    let mut query = sqlx::query_as::<_, RecipientRow>(&sql);
+    for id in ids {
+      query = query.bind(id);
+    }
+    query.fetch_all(&self.pool).await
+  }
+
+  async fn raw_source(&self, id: Uuid) -> Result<Option<Option<StoredBody>>, sqlx::Error> {
+    let row: Option<(Option<Vec<u8>>, Option<String>)> =
+      sqlx::query_as("SELECT raw_source, raw_sha256 FROM messages WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+    Ok(row.map(|(raw, sha)| match sha {
+      Some(sha) => Some(StoredBody::Blob(sha)),
+      None => raw.map(StoredBody::Bytes),
+    }))
+  }
+
+  async fn message_headers(&self, id: Uuid) -> Result<Option<Vec<(String, String)>>, sqlx::Error> {
+    let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+      "SELECT m.headers_json, p.headers_json FROM messages m LEFT JOIN parts p ON p.message_id = m.id AND p.path = '1' WHERE m.id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await?;
+    Ok(row.map(|(map_json, root_json)| match root_json {
+      Some(json) => serde_json::from_str(&json).unwrap_or_default(),
+      None => {
+        let map: BTreeMap<String, String> = map_json
+          .and_then(|j| serde_json::from_str(&j).ok())
+          .unwrap_or_default();
+        map.into_iter().collect()
+      }
+    }))
+  }
+
+  async fn delete_messages(&self, ids: &[Uuid]) -> Result<u64, sqlx::Error> {
+    crate::db::delete_messages(&self.pool, ids).await
+  }
+
+  async fn clear_messages(&self) -> Result<(), sqlx::Error> {
+    let mut tx = self.pool.begin().await?;
+    for sql in [
+      "DELETE FROM smtp_sessions WHERE ended_at IS NOT NULL AND id IN (SELECT session_id FROM messages)",
+      "DELETE FROM recipients",
+      "DELETE FROM parts",
+      "DELETE FROM messages",
+    ] {
+      sqlx::query(sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await
+  }
+
+  async fn ping(&self) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(&self.pool).await?;
+    Ok(())
+  }
+
+  async fn stats(&self) -> Result<StoreStats, sqlx::Error> {
+    let (messages, attachment_bytes): (i64, i64) = sqlx::query_as(
+      "SELECT (SELECT COUNT(*) FROM messages), (SELECT coalesce(SUM(size), 0) FROM attachments)",
+    )
+    .fetch_one(&self.pool)
+    .await?;
+    let db_bytes: i64 = sqlx::query_scalar(
+      "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+    )
+    .fetch_one(&self.pool)
+    .await?;
+    Ok(StoreStats {
+      messages: messages as u64,
+      attachment_bytes: attachment_bytes as u64,
+      db_bytes: Some(db_bytes as u64),
+    })
+  }
+
+  async fn retention_rows(&self) -> Result<Vec<RetentionRow>, sqlx::Error> {
+    // Messages posted as JSON have no raw size; count their bodies instead.
+    let rows: Vec<(Uuid, DateTime<Utc>, i64)> = sqlx::query_as(
+      "SELECT id, received_at, CASE WHEN raw_len > 0 THEN raw_len ELSE length(coalesce(text_body, '')) + length(coalesce(html_body, '')) END FROM messages",
+    )
+    .fetch_all(&self.pool)
+    .await?;
+    let mailboxes: Vec<(Uuid, String)> = sqlx::query_as(
+      "SELECT DISTINCT message_id, lower(address) FROM recipients WHERE kind IN ('to', 'cc', 'bcc')",
+    )
+    .fetch_all(&self.pool)
+    .await?;
+    let mut by_message: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for (id, mailbox) in mailboxes {
+      by_message.entry(id).or_default().push(mailbox);
+    }
+    Ok(
+      rows
+        .into_iter()
+        .map(|(id, received_at, size)| RetentionRow {
+          id,
+          received_at,
+          size: size.max(0) as u64,
+          mailboxes: by_message.remove(&id).unwrap_or_default(),
+        })
+        .collect(),
+    )
+  }
+
+  async fn list_attachments(&self, message_id: Uuid) -> Result<Vec<AttachmentMeta>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, message_id, filename, content_type, size, content_id, inline FROM attachments WHERE message_id = ? ORDER BY id",
+    )
+    .bind(message_id)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn get_attachment(&self, id: Uuid) -> Result<Option<AttachmentRow>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, filename, content_type, content, sha256 FROM attachments WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn list_parts(&self, message_id: Uuid) -> Result<Vec<PartMeta>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, message_id, path, parent_path, content_type, charset, transfer_encoding, content_id, disposition, filename, size FROM parts WHERE message_id = ? ORDER BY seq",
+    )
+    .bind(message_id)
+    .fetch_all(&self.pool)
+    .await
+  }
+
+  async fn get_part(&self, id: Uuid) -> Result<Option<PartRow>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT id, filename, content_type, charset, content, sha256 FROM parts WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn blob_refs(&self) -> Result<HashSet<String>, sqlx::Error> {
+    sqlx::query_scalar(
+      "SELECT sha256 FROM attachments WHERE sha256 IS NOT NULL UNION SELECT raw_sha256 FROM messages WHERE raw_sha256 IS NOT NULL",
+    )
+    .fetch_all(&self.pool)
+    .await
+    .map(|refs: Vec<String>| refs.into_iter().collect())
+  }
+
+  async fn list_threads(&self, query: &MessageQuery) -> Result<Vec<ApiThread>, sqlx::Error> {
+    let (where_sql, binds) = filter_sql(&query.filter);
+    let filter = if where_sql.is_empty() {
+      String::new()
+    } else {
+      format!(
+        " WHERE coalesce(thread_id, id) IN (SELECT coalesce(thread_id, id) FROM messages{where_sql})"
+      )
+    };
+    let sql = format!(
+      "SELECT coalesce(thread_id, id) AS id, \
+         (SELECT subject FROM messages f WHERE coalesce(f.thread_id, f.id) = coalesce(messages.thread_id, messages.id) ORDER BY f.received_at LIMIT 1) AS subject, \
+         COUNT(*) AS message_count, MIN(received_at) AS first_at, MAX(received_at) AS last_at, \
+         json_group_array(DISTINCT from_addr) AS participants_json \
+       FROM messages{filter} GROUP BY coalesce(thread_id, id) ORDER BY last_at DESC{}",
+      paging_sql(query)
+    );
+    let mut q = sqlx::query_as::<_, DbThread>(&sql);
+    for b in &binds {
+      q = q.bind(b);
+    }
+    let rows = q.fetch_all(&self.pool).await?;
+    Ok(rows.into_iter().map(ApiThread::from).collect())
+  }
+
+  async fn thread_messages(&self, thread_ids: &[Uuid]) -> Result<Vec<DbEmail>, sqlx::Error> {
+    if thread_ids.is_empty() {
+      return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; thread_ids.len()].join(", ");
+    let sql = format!(
+      "SELECT {} FROM messages WHERE coalesce(thread_id, id) IN ({placeholders}) ORDER BY received_at",
+      DbEmail::COLUMNS
+    );
+    let mut query = sqlx::query_as::<_, DbEmail>(&sql);
+    for id in thread_ids {
+      query = query.bind(id);
+    }
+    query.fetch_all(&self.pool).await
+  }
+
+  async fn insert_session(&self, s: &DbSession) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO smtp_sessions (id, started_at, ended_at, client_addr, helo, auth_user, transcript_json) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(s.id)
+    .bind(s.started_at)
+    .bind(s.ended_at)
+    .bind(&s.client_addr)
+    .bind(&s.helo)
+    .bind(&s.auth_user)
+    .bind(&s.transcript_json)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn update_session(&self, s: &DbSession) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "UPDATE smtp_sessions SET ended_at = ?, helo = ?, auth_user = ?, transcript_json = ? WHERE id = ?",
+    )
+    .bind(s.ended_at)
+    .bind(&s.helo)
+    .bind(&s.auth_user)
+    .bind(&s.transcript_json)
+    .bind(s.id)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn message_session(&self, message_id: Uuid) -> Result<Option<DbSession>, sqlx::Error> {
+    sqlx::query_as(
+      "SELECT s.id, s.started_at, s.ended_at, s.client_addr, s.helo, s.auth_user, s.transcript_json FROM smtp_sessions s JOIN messages m ON m.session_id = s.id WHERE m.id = ?",
+    )
+    .bind(message_id)
+    .fetch_optional(&self.pool)
+    .await
+  }
+
+  async fn append_log(&self, entry: &NewLogEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO logs (ts, level, target, message, fields_json) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(entry.ts)
+    .bind(&entry.level)
+    .bind(&entry.target)
+    .bind(&entry.message)
+    .bind(&entry.fields_json)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn list_logs(&self, query: &LogQuery) -> Result<Vec<LogEntry>, sqlx::Error> {
+    let mut b = QueryBuilder::<Sqlite>::new(format!(
+      "SELECT {} FROM logs WHERE 1 = 1",
+      LogEntry::COLUMNS
+    ));
+    if !query.levels.is_empty() {
+      b.push(" AND level IN (");
+      let mut levels = b.separated(", ");
+      for level in &query.levels {
+        levels.push_bind(level);
+      }
+      levels.push_unseparated(")");
+    }
+    if let Some(id) = query.since_id {
+      b.push(" AND id > ").push_bind(id);
+    }
+    if let Some(ts) = query.since {
+      b.push(" AND ts >= ").push_bind(ts);
+    }
+    if let Some(ts) = query.until {
+      b.push(" AND ts < ").push_bind(ts);
+    }
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+      let pattern = format!("%{q}%");
+      b.push(" AND (message LIKE ")
+        .push_bind(pattern.clone())
+        .push(" OR coalesce(target, '') LIKE ")
+        .push_bind(pattern.clone())
+        .push(" OR coalesce(fields_json, '') LIKE ")
+        .push_bind(pattern)
+        .push(")");
+    }
+    let forward = query.since_id.is_some();
+    b.push(if forward {
+      " ORDER BY id ASC"
+    } else {
+      " ORDER BY id DESC"
+    });
+    b.push(" LIMIT ")
+      .push_bind(query.limit as i64)
+      .push(" OFFSET ")
+      .push_bind(query.offset as i64);
+    let mut logs: Vec<LogEntry> = b.build_query_as().fetch_all(&self.pool).await?;
+    if !forward {
+      logs.reverse();
+    }
+    Ok(logs)
+  }
+
+  async fn insert_token(&self, t: &DbToken) -> Result<(), sqlx::Error> {
+    sqlx::query(
+      "INSERT INTO api_tokens (id, name, token_sha256, scopes, created_at) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(t.id)
+    .bind(&t.name)
+    .bind(&t.token_sha256)
+    .bind(&t.scopes)
+    .bind(t.created_at)
+    .execute(&self.pool)
+    .await?;
+    Ok(())
+  }
+
+  async fn list_tokens(&self) -> Result<Vec<DbToken>, sqlx::Error> {
+    let sql = format!(
+      "SELECT {} FROM api_tokens ORDER BY created_at",
+      DbToken::COLUMNS
+    );
+    sqlx::query_as(&sql).fetch_all(&self.pool).await
+  }
+
+  async fn delete_token(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM api_tokens WHERE id = ?")
+      .bind(id)
+      .execute(&self.pool)
+      .await?;
+    Ok(res.rows_affected() > 0)
+  }
+
+  async fn token_by_hash(&self, sha256: &str) -> Result<Option<DbToken>, sqlx::Error> {
+    let sql = format!(
+      "SELECT {} FROM api_tokens WHERE token_sha256 = ?",
+      DbToken::COLUMNS
+    );
+    sqlx::query_as(&sql)
+      .bind(sha256)
+      .fetch_optional(&self.pool)
+      .await
+  }
+}
@@ -0,0 +1,139 @@
+//! mbox and zip archive reading and writing.
+//!
+//! mbox follows the mboxrd convention: a message starts at a `From ` line
+//! at the start of the file or after a blank line, and body lines matching
+//! `>*From ` get one extra `>` on export which import removes again.
+
+use chrono::{DateTime, Utc};
+use std::io::{Cursor, Read};
+
+/// True when the data looks like a zip archive.
+pub fn is_zip(data: &[u8]) -> bool {
+  data.starts_with(b"PK\x03\x04")
+}
+
+/// True when the data looks like an mbox file.
+pub fn is_mbox(data: &[u8]) -> bool {
+  data.starts_with(b"From ")
+}
+
+/// Split an mbox file into raw messages.
+pub fn split_mbox(data: &[u8]) -> Vec<Vec<u8>> {
+  let mut out: Vec<Vec<u8>> = Vec::new();
+  let mut current: Option<Vec<u8>> = None;
+  let mut prev_blank = true;
+  for line in data.split_inclusive(|b| *b == b'\n') {
+    if prev_blank && line.starts_with(b"From ") {
+      out.extend(current.take().map(trim_separator));
+      current = Some(Vec::new());
+      prev_blank = false;
+      continue;
+    }
+    prev_blank = matches!(line, b"\n" | b"\r\n");
+    if let Some(msg) = current.as_mut() {
+      let quoted = line.iter().take_while(|b| **b == b'>').count();
+      if quoted > 0 && line[quoted..].starts_with(b"From ") {
+        msg.extend_from_slice(&line[1..]);
+      } else {
+        msg.extend_from_slice(line);
+      }
+    }
+  }
+  out.extend(current.map(trim_separator));
+  out.retain(|m| !m.is_empty());
+  out
+}
+
+/// Drop the blank line that separates a message from the next `From ` line.
+fn trim_separator(mut msg: Vec<u8>) -> Vec<u8> {
+  if msg.ends_with(b"\r\n\r\n") {
+    msg.truncate(msg.len() - 2);
+  } else if msg.ends_with(b"\n\n") {
+    msg.truncate(msg.len() - 1);
+  }
+  msg
+}
+
+/// Append one message to an mbox file.
+pub fn write_mbox_entry(out: &mut Vec<u8>, sender: &str, date: DateTime<Utc>, raw: &[u8]) {
+  let sender = sender.split_whitespace().next().unwrap_or("MAILER-DAEMON");
+  out.extend_from_slice(
+    format!("From {sender} {}\n", date.format("%a %b %e %H:%M:%S %Y")).as_bytes(),
+  );
+  for line in raw.split_inclusive(|b| *b == b'\n') {
+    let quoted = line.iter().take_while(|b| **b == b'>').count();
+    if line[quoted..].starts_with(b"From ") {
+      out.push(b'>');
+    }
+    out.extend_from_slice(line);
+  }
+  if !raw.ends_with(b"\n") {
+    out.push(b'\n');
+  }
+  out.push(b'\n');
+}
+
+/// Why a zip archive could not be unpacked.
+#[derive(Debug)]
+pub enum UnpackError {
+  Zip(zip::result::ZipError),
+  /// The messages expand past the given budget.
+  TooLarge,
+}
+
+impl std::fmt::Display for UnpackError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UnpackError::Zip(e) => write!(f, "{e}"),
+      UnpackError::TooLarge => write!(f, "archive expands past the import limit"),
+    }
+  }
+}
+
+impl From<zip::result::ZipError> for UnpackError {
+  fn from(e: zip::result::ZipError) -> Self {
+    UnpackError::Zip(e)
+  }
+}
+
+impl From<std::io::Error> for UnpackError {
+  fn from(e: std::io::Error) -> Self {
+    UnpackError::Zip(e.into())
+  }
+}
+
+/// Messages in a zip archive: `*.eml` files anywhere, and every file in a
+/// Maildir `cur/` or `new/` directory. At most `max_bytes` are decompressed
+/// in total; the sizes in the archive's headers are not trusted.
+pub fn unpack_zip(data: &[u8], max_bytes: u64) -> Result<Vec<Vec<u8>>, UnpackError> {
+  let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+  let mut remaining = max_bytes;
+  let mut out = Vec::new();
+  for i in 0..archive.len() {
+    let mut file = archive.by_index(i)?;
+    if !file.is_file() {
+      continue;
+    }
+    let name = file.name().replace('\\', "/");
+    let parts: Vec<&str> = name.split('/').collect();
+    let Some((file_name, dirs)) = parts.split_last() else {
+      continue;
+    };
+    if file_name.starts_with('.') || dirs.contains(&"__MACOSX") {
+      continue;
+    }
+    let is_eml = file_name.to_ascii_lowercase().ends_with(".eml");
+    let in_maildir = matches!(dirs.last(), Some(&"cur") | Some(&"new"));
+    if is_eml || in_maildir {
+      let mut buf = Vec::new();
+      (&mut file)
+        .take(remaining.saturating_add(1))
+        .read_to_end(&mut buf)?;
+      remaining = remaining
+        .checked_sub(buf.len() as u64)
+        .ok_or(UnpackError::TooLarge)?;
+      out.push(buf);
+    }
+  }
+  Ok(out)
+}
@@ -0,0 +1,104 @@
+//! MIME tree flattening for storage.
+//!
+//! Parts are numbered like IMAP body sections: the root is `1`, its children
+//! `1.1`, `1.2`, and so on. Embedded `message/rfc822` parts are parsed and
+//! their structure continues below them, up to [`MAX_EMBED_DEPTH`] levels.
+
+use mailparse::{DispositionType, MailHeaderMap, ParsedMail, parse_mail};
+
+/// Embedded messages nested deeper than this are stored as opaque leaves.
+pub const MAX_EMBED_DEPTH: usize = 16;
+
+/// One node of a message's MIME tree, ready to be stored.
+#[derive(Debug, Clone)]
+pub struct MimePart {
+  pub path: String,
+  pub parent_path: Option<String>,
+  pub content_type: String,
+  pub charset: Option<String>,
+  pub transfer_encoding: Option<String>,
+  pub content_id: Option<String>,
+  pub disposition: Option<String>,
+  pub filename: Option<String>,
+  pub headers: Vec<(String, String)>,
+  /// Decoded body for leaves and embedded messages; `None` for multipart containers.
+  pub body: Option<Vec<u8>>,
+  /// Blob holding `body` once offloaded; the database then keeps no copy.
+  pub sha256: Option<String>,
+}
+
+/// Flatten the MIME tree in document order.
+pub fn collect_parts(parsed: &ParsedMail<'_>) -> Vec<MimePart> {
+  let mut out = Vec::new();
+  walk(parsed, "1".to_string(), None, 0, &mut out);
+  out
+}
+
+fn walk(
+  part: &ParsedMail<'_>,
+  path: String,
+  parent_path: Option<String>,
+  depth: usize,
+  out: &mut Vec<MimePart>,
+) {
+  let content_type = part.ctype.mimetype.clone();
+  let is_multipart = !part.subparts.is_empty() || content_type.starts_with("multipart/");
+  let body = if is_multipart {
+    None
+  } else {
+    Some(part.get_body_raw().unwrap_or_default())
+  };
+  // Parsed in place from the stored body; pushed after this part below.
+  let mut embedded = Vec::new();
+  if content_type == "message/rfc822" && depth < MAX_EMBED_DEPTH {
+    if let Some(Ok(inner)) = body.as_deref().map(parse_mail) {
+      walk(
+        &inner,
+        format!("{path}.1"),
+        Some(path.clone()),
+        depth + 1,
+        &mut embedded,
+      );
+    }
+  }
+
+  out.push(MimePart {
+    path: path.clone(),
+    parent_path,
+    content_type,
+    charset: part.ctype.params.get("charset").cloned(),
+    transfer_encoding: part
+      .headers
+      .get_first_value("Content-Transfer-Encoding")
+      .map(|v| v.trim().to_ascii_lowercase()),
+    content_id: super::part_content_id(part),
+    disposition: part
+      .headers
+      .get_first_value("Content-Disposition")
+      .map(|_| match part.get_content_disposition().disposition {
+        DispositionType::Inline => "inline".to_string(),
+        DispositionType::Attachment => "attachment".to_string(),
+        DispositionType::FormData => "form-data".to_string(),
+        DispositionType::Extension(other) => other,
+      }),
+    filename: super::part_filename(part),
+    headers: part
+      .headers
+      .iter()
+      .map(|h| (h.get_key(), h.get_value()))
+      .collect(),
+    body,
+    sha256: None,
+  });
+
+  for (i, sub) in part.subparts.iter().enumerate() {
+    walk(
+      sub,
+      format!("{path}.{}", i + 1),
+      Some(path.clone()),
+      depth,
+      out,
+    );
+  }
+  out.append(&mut embedded);
+}
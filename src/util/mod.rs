@@ -1,23 +1,41 @@
 //! Utility functions: tracing, HTML escape, mail parsing.
 
-use mailparse::{MailHeaderMap, ParsedMail};
-use tracing_subscriber::{EnvFilter, fmt};
+use crate::{
+  logging,
+  models::{
+    email::address::{Address, Addresses},
+    log::new_log_entry::NewLogEntry,
+  },
+};
+use mailparse::{MailAddr, MailAddrList, MailHeaderMap, ParsedMail, addrparse, addrparse_header};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
-/// Initialize pretty CLI logging.
-pub fn init_tracing() {
+pub mod archive;
+pub mod mime;
+pub mod params;
+
+/// Initialize pretty CLI logging plus the logs-table layer; events queue on
+/// the returned receiver until [`logging::spawn_writer`] drains it.
+pub fn init_tracing() -> mpsc::Receiver<NewLogEntry> {
   let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-  fmt()
-    .with_env_filter(filter)
-    .with_target(false)
-    .pretty()
+  let (db_layer, rx) = logging::layer();
+  tracing_subscriber::registry()
+    .with(filter)
+    .with(fmt::layer().with_target(false).pretty())
+    .with(db_layer)
     .init();
+  rx
 }
 
-/// Minimal HTML escaping for text display.
+/// HTML escaping for text and attribute values.
 pub fn html_escape(s: &str) -> String {
   s.replace('&', "&amp;")
     .replace('<', "&lt;")
     .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
 }
 
 /// Collect headers into a lowercase HashMap.
@@ -29,6 +47,150 @@ pub fn collect_headers(parsed: &ParsedMail<'_>) -> std::collections::HashMap<Str
   map
 }
 
+/// Parse an RFC 5322 address list; group members are flattened.
+///
+/// Values that do not parse fall back to a plain comma split.
+pub fn parse_addresses(value: &str) -> Vec<Address> {
+  match addrparse(value) {
+    Ok(list) => flatten_addresses(list),
+    Err(_) => split_addresses(value),
+  }
+}
+
+/// Like [`parse_addresses`], but on the raw header so RFC 2047 display
+/// names are decoded after parsing: an encoded comma stays in the name.
+pub fn parse_address_header(header: &mailparse::MailHeader<'_>) -> Vec<Address> {
+  match addrparse_header(header) {
+    Ok(list) => flatten_addresses(list),
+    Err(_) => split_addresses(&header.get_value()),
+  }
+}
+
+fn flatten_addresses(list: MailAddrList) -> Vec<Address> {
+  list
+    .iter()
+    .flat_map(|a| match a {
+      MailAddr::Single(s) => vec![s.clone()],
+      MailAddr::Group(g) => g.addrs.clone(),
+    })
+    .map(|s| Address {
+      name: s.display_name.filter(|n| !n.trim().is_empty()),
+      address: s.addr,
+    })
+    .collect()
+}
+
+fn split_addresses(value: &str) -> Vec<Address> {
+  value
+    .split(',')
+    .map(|p| p.trim())
+    .filter(|p| !p.is_empty())
+    .map(|p| Address {
+      name: None,
+      address: p.trim_matches(['<', '>']).to_string(),
+    })
+    .collect()
+}
+
+/// Structured addresses from lowercase headers.
+///
+/// Without a `Bcc` header, envelope recipients that appear in neither `To`
+/// nor `Cc` are reported as Bcc.
+pub fn collect_addresses(headers: &HashMap<String, String>, envelope_to: &[String]) -> Addresses {
+  addresses_with(
+    |k| {
+      headers
+        .get(k)
+        .map(|v| parse_addresses(v))
+        .unwrap_or_default()
+    },
+    envelope_to,
+  )
+}
+
+/// [`collect_addresses`] for a parsed message, reading the raw headers.
+pub fn collect_mail_addresses(parsed: &ParsedMail<'_>, envelope_to: &[String]) -> Addresses {
+  addresses_with(
+    |k| {
+      // The last occurrence wins, as in `collect_headers`.
+      parsed
+        .headers
+        .get_all_headers(k)
+        .last()
+        .map(|h| parse_address_header(h))
+        .unwrap_or_default()
+    },
+    envelope_to,
+  )
+}
+
+fn addresses_with(get: impl Fn(&str) -> Vec<Address>, envelope_to: &[String]) -> Addresses {
+  let mut out = Addresses {
+    from: get("from"),
+    sender: get("sender"),
+    reply_to: get("reply-to"),
+    to: get("to"),
+    cc: get("cc"),
+    bcc: get("bcc"),
+  };
+  if out.bcc.is_empty() {
+    out.bcc = envelope_to
+      .iter()
+      .filter(|rcpt| {
+        !out
+          .to
+          .iter()
+          .chain(&out.cc)
+          .any(|a| a.address.eq_ignore_ascii_case(rcpt))
+      })
+      .map(|rcpt| Address {
+        name: None,
+        address: rcpt.clone(),
+      })
+      .collect();
+  }
+  out
+}
+
+/// Threading headers of a message, with angle brackets removed.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadHeaders {
+  pub message_id: Option<String>,
+  pub in_reply_to: Option<String>,
+  pub references: Vec<String>,
+}
+
+/// Read `Message-ID`, `In-Reply-To` and `References` from lowercase headers.
+pub fn thread_headers(headers: &HashMap<String, String>) -> ThreadHeaders {
+  let ids = |k: &str| headers.get(k).map(|v| parse_msg_ids(v)).unwrap_or_default();
+  ThreadHeaders {
+    message_id: ids("message-id").into_iter().next(),
+    in_reply_to: ids("in-reply-to").into_iter().next(),
+    references: ids("references"),
+  }
+}
+
+/// `<id>` tokens in a header value; a bare value is taken whole.
+fn parse_msg_ids(value: &str) -> Vec<String> {
+  let mut out = Vec::new();
+  let mut rest = value;
+  while let Some(start) = rest.find('<') {
+    let Some(len) = rest[start..].find('>') else {
+      break;
+    };
+    let id = rest[start + 1..start + len].trim();
+    if !id.is_empty() && !out.iter().any(|o| o == id) {
+      out.push(id.to_string());
+    }
+    rest = &rest[start + len + 1..];
+  }
+  let bare = value.trim();
+  if out.is_empty() && !bare.is_empty() && !bare.contains(char::is_whitespace) {
+    out.push(bare.to_string());
+  }
+  out
+}
+
 /// Extract first text and HTML bodies from a MIME tree.
 pub fn extract_bodies(parsed: &ParsedMail<'_>) -> (Option<String>, Option<String>) {
   if parsed.subparts.is_empty() {
@@ -54,41 +216,65 @@ pub fn extract_bodies(parsed: &ParsedMail<'_>) -> (Option<String>, Option<String
   }
 }
 
+/// File name of a MIME part from `Content-Disposition` or the `Content-Type` name.
+///
+/// Parameters are decoded per RFC 2231 and RFC 2047 (see `params`).
+pub fn part_filename(parsed: &ParsedMail<'_>) -> Option<String> {
+  let param = |header: &str, name: &str| {
+    params::raw_header(parsed, header)
+      .map(|v| params::parse_header_params(&v))
+      .and_then(|p| p.params.get(name).cloned())
+      .filter(|v| !v.trim().is_empty())
+  };
+  param("Content-Disposition", "filename").or_else(|| param("Content-Type", "name"))
+}
+
+/// `Content-ID` of a MIME part without its angle brackets.
+pub fn part_content_id(parsed: &ParsedMail<'_>) -> Option<String> {
+  parsed
+    .headers
+    .get_first_value("Content-ID")
+    .map(|v| v.trim().trim_matches(['<', '>']).to_string())
+    .filter(|v| !v.is_empty())
+}
+
+/// Attachment candidate extracted from a MIME leaf.
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+  pub filename: Option<String>,
+  pub content_type: String,
+  /// `Content-ID` without angle brackets, referenced as `cid:` from HTML.
+  pub content_id: Option<String>,
+  /// Parts with a Content-ID that are not explicitly attachments render inline.
+  pub inline: bool,
+  pub data: Vec<u8>,
+  /// Blob holding `data` once offloaded; the database then keeps no copy.
+  pub sha256: Option<String>,
+}
+
 /// Traverse MIME parts and collect attachment candidates.
-pub fn collect_attachments(
-  parsed: &ParsedMail<'_>,
-  out: &mut Vec<(Option<String>, String, Vec<u8>)>,
-) {
+pub fn collect_attachments(parsed: &ParsedMail<'_>, out: &mut Vec<NewAttachment>) {
   if parsed.subparts.is_empty() {
     let ctype = parsed.ctype.mimetype.clone();
     let disp = parsed
       .headers
       .get_first_value("Content-Disposition")
       .unwrap_or_default();
-    let mut filename: Option<String> = None;
-    if let Some(pos) = disp.to_lowercase().find("filename=") {
-      let part = &disp[pos + 9..];
-      let cleaned = part
-        .trim()
-        .trim_matches(['"', '\''])
-        .split(';')
-        .next()
-        .unwrap_or("");
-      if !cleaned.is_empty() {
-        filename = Some(cleaned.to_string());
-      }
-    }
-    for (k, v) in &parsed.ctype.params {
-      if k.eq_ignore_ascii_case("name") {
-        filename.get_or_insert(v.clone());
-      }
-    }
+    let filename = part_filename(parsed);
+    let content_id = part_content_id(parsed);
+    let is_attachment = disp.to_ascii_lowercase().contains("attachment");
     let is_text = ctype == "text/plain" || ctype == "text/html";
-    let looks_attachment =
-      disp.to_ascii_lowercase().contains("attachment") || filename.is_some() || (!is_text);
+    let looks_attachment = is_attachment || filename.is_some() || (!is_text);
     if looks_attachment {
       let data = parsed.get_body_raw().unwrap_or_default();
-      out.push((filename, parsed.ctype.mimetype.clone(), data));
+      out.push(NewAttachment {
+        filename,
+        content_type: ctype,
+        inline: content_id.is_some() && !is_attachment,
+        content_id,
+        data,
+        sha256: None,
+      });
     }
   } else {
     for part in &parsed.subparts {
@@ -96,3 +282,63 @@ pub fn collect_attachments(
     }
   }
 }
+
+/// Point `cid:` references at attachment URLs; `urls` maps Content-ID to URL.
+///
+/// References are matched as written and, failing that, percent-decoded
+/// (RFC 2392 allows either form).
+pub fn rewrite_cid_urls(html: &str, urls: &HashMap<String, String>) -> String {
+  let mut out = String::with_capacity(html.len());
+  let mut rest = html;
+  while let Some(pos) = find_ascii_ci(rest, "cid:") {
+    out.push_str(&rest[..pos]);
+    let after = &rest[pos + 4..];
+    let end = after
+      .find(|c: char| c == '"' || c == '\'' || c == ')' || c == '>' || c.is_whitespace())
+      .unwrap_or(after.len());
+    let reference = &after[..end];
+    let url = urls
+      .get(reference)
+      .or_else(|| urls.get(&percent_decode(reference)));
+    match url {
+      Some(url) => out.push_str(url),
+      None => out.push_str(&rest[pos..pos + 4 + end]),
+    }
+    rest = &after[end..];
+  }
+  out.push_str(rest);
+  out
+}
+
+/// Byte offset of the first ASCII case-insensitive match of `needle`.
+pub fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+  haystack
+    .as_bytes()
+    .windows(needle.len())
+    .position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Decode `%XX` escapes; invalid sequences are kept as-is.
+pub fn percent_decode(s: &str) -> String {
+  String::from_utf8_lossy(&percent_decode_bytes(s)).into_owned()
+}
+
+/// Byte-level `%XX` decoding for values in an arbitrary charset.
+pub fn percent_decode_bytes(s: &str) -> Vec<u8> {
+  let bytes = s.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' && i + 2 < bytes.len() {
+      let hex = |b: u8| (b as char).to_digit(16);
+      if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+        out.push((hi * 16 + lo) as u8);
+        i += 3;
+        continue;
+      }
+    }
+    out.push(bytes[i]);
+    i += 1;
+  }
+  out
+}
@@ -0,0 +1,198 @@
+//! MIME header parameter decoding (`Content-Type`, `Content-Disposition`).
+//!
+//! Handles quoted strings (including `;` inside quotes), RFC 2231 charset
+//! and continuation parameters (`filename*=UTF-8''%E2%82%AC.pdf`,
+//! `filename*0*=...`) and RFC 2047 encoded words in plain values.
+
+use super::percent_decode_bytes;
+use charset::Charset;
+use mailparse::{MailHeaderMap, ParsedMail, parse_header};
+use std::collections::BTreeMap;
+
+/// A structured header value: `value; key=val; ...` with decoded parameters.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeaderParams {
+  /// Leading value, lowercased (e.g. `attachment`, `text/plain`).
+  pub value: String,
+  /// Decoded parameters keyed by lowercase name.
+  pub params: BTreeMap<String, String>,
+}
+
+/// Raw (undecoded, unfolded) value of the first header named `key`.
+pub fn raw_header(parsed: &ParsedMail<'_>, key: &str) -> Option<String> {
+  parsed
+    .headers
+    .get_first_header(key)
+    .map(|h| unfold(&String::from_utf8_lossy(h.get_value_raw())))
+}
+
+fn unfold(s: &str) -> String {
+  s.replace("\r\n", "").replace('\n', "")
+}
+
+/// Parse a raw structured header value.
+pub fn parse_header_params(raw: &str) -> HeaderParams {
+  let mut pieces = split_unquoted(raw, ';').into_iter();
+  let value = pieces
+    .next()
+    .unwrap_or_default()
+    .trim()
+    .to_ascii_lowercase();
+
+  // Segments per parameter name: (index, percent-encoded, value).
+  let mut plain: BTreeMap<String, String> = BTreeMap::new();
+  let mut extended: BTreeMap<String, Vec<(u32, bool, String)>> = BTreeMap::new();
+  for piece in pieces {
+    let Some((key, val)) = piece.split_once('=') else {
+      continue;
+    };
+    let key = key.trim().to_ascii_lowercase();
+    let val = unquote(val.trim());
+    if key.is_empty() {
+      continue;
+    }
+    let (name, encoded) = match key.strip_suffix('*') {
+      Some(n) => (n, true),
+      None => (key.as_str(), false),
+    };
+    match name.split_once('*') {
+      Some((base, idx)) => {
+        if let Ok(idx) = idx.parse::<u32>() {
+          extended
+            .entry(base.to_string())
+            .or_default()
+            .push((idx, encoded, val));
+        }
+      }
+      None if encoded => extended
+        .entry(name.to_string())
+        .or_default()
+        .push((0, true, val)),
+      None => {
+        plain.insert(name.to_string(), val);
+      }
+    }
+  }
+
+  let mut params: BTreeMap<String, String> = plain
+    .into_iter()
+    .map(|(k, v)| (k, decode_encoded_words(&v)))
+    .collect();
+  // RFC 2231 values take precedence over plain ones of the same name.
+  for (name, mut segments) in extended {
+    segments.sort_by_key(|(idx, _, _)| *idx);
+    params.insert(name, join_extended(&segments));
+  }
+  HeaderParams { value, params }
+}
+
+/// Split on `sep` outside double quotes, honouring backslash escapes.
+fn split_unquoted(s: &str, sep: char) -> Vec<String> {
+  let mut out = Vec::new();
+  let mut cur = String::new();
+  let mut in_quotes = false;
+  let mut escaped = false;
+  for c in s.chars() {
+    if escaped {
+      cur.push(c);
+      escaped = false;
+    } else if in_quotes && c == '\\' {
+      cur.push(c);
+      escaped = true;
+    } else if c == '"' {
+      cur.push(c);
+      in_quotes = !in_quotes;
+    } else if c == sep && !in_quotes {
+      out.push(std::mem::take(&mut cur));
+    } else {
+      cur.push(c);
+    }
+  }
+  out.push(cur);
+  out
+}
+
+fn unquote(s: &str) -> String {
+  let Some(inner) = s.strip_prefix('"').and_then(|r| r.strip_suffix('"')) else {
+    return s.to_string();
+  };
+  let mut out = String::with_capacity(inner.len());
+  let mut chars = inner.chars();
+  while let Some(c) = chars.next() {
+    if c == '\\' {
+      if let Some(n) = chars.next() {
+        out.push(n);
+      }
+    } else {
+      out.push(c);
+    }
+  }
+  out
+}
+
+/// Join RFC 2231 segments; the first encoded segment carries `charset'lang'`.
+fn join_extended(segments: &[(u32, bool, String)]) -> String {
+  let mut charset = None;
+  let mut bytes = Vec::new();
+  for (i, (_, encoded, val)) in segments.iter().enumerate() {
+    if *encoded {
+      let mut data = val.as_str();
+      if i == 0 {
+        let mut it = val.splitn(3, '\'');
+        if let (Some(cs), Some(_lang), Some(rest)) = (it.next(), it.next(), it.next()) {
+          charset = Some(cs.to_string());
+          data = rest;
+        }
+      }
+      bytes.extend(percent_decode_bytes(data));
+    } else {
+      bytes.extend_from_slice(val.as_bytes());
+    }
+  }
+  decode_charset(charset.as_deref(), &bytes)
+}
+
+fn decode_charset(label: Option<&str>, bytes: &[u8]) -> String {
+  match label
+    .filter(|l| !l.is_empty())
+    .and_then(|l| Charset::for_label(l.as_bytes()))
+  {
+    Some(cs) => cs.decode_without_bom_handling(bytes).0.into_owned(),
+    None => String::from_utf8_lossy(bytes).into_owned(),
+  }
+}
+
+/// Decode RFC 2047 encoded words (`=?UTF-8?B?...?=`) in a plain value.
+fn decode_encoded_words(s: &str) -> String {
+  if !s.contains("=?") {
+    return s.to_string();
+  }
+  // mailparse decodes encoded words when reading an unstructured header value.
+  match parse_header(format!("X: {s}").as_bytes()) {
+    Ok((h, _)) => h.get_value(),
+    Err(_) => s.to_string(),
+  }
+}
+
+/// `Content-Disposition` value that is safe for any file name.
+///
+/// Carries an ASCII fallback in `filename` and the exact name in RFC 5987
+/// `filename*` form.
+pub fn content_disposition(kind: &str, filename: &str) -> String {
+  let fallback: String = filename
+    .chars()
+    .map(|c| match c {
+      ' '..='~' if c != '"' && c != '\\' => c,
+      _ => '_',
+    })
+    .collect();
+  let mut encoded = String::new();
+  for b in filename.bytes() {
+    if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+      encoded.push(b as char);
+    } else {
+      encoded.push_str(&format!("%{b:02X}"));
+    }
+  }
+  format!("{kind}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
@@ -1,27 +1,92 @@
 use axum::Router;
-use fauxmail::{app::AppState, db, http};
+use fauxmail::{app::AppState, blob::BlobStore, db, http, ingest, retention, smtp, store};
 use serde_json::json;
-use sqlx::sqlite::SqlitePoolOptions;
-use tokio::task::JoinHandle;
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+  net::TcpStream,
+  task::JoinHandle,
+};
 
 async fn start_server() -> (String, JoinHandle<()>) {
-  let db_url = "sqlite://:memory:";
-  let db_url = db::ensure_sqlite_path(db_url);
-  let pool = SqlitePoolOptions::new()
-    .max_connections(5)
-    .connect(&db_url)
-    .await
-    .expect("connect memory sqlite");
-  db::run_migrations(&pool).await.expect("migrate");
-  let state = AppState { db: pool };
-  let app: Router = http::build_router(state);
+  let (base, _smtp, handle) = start_servers().await;
+  (base, handle)
+}
+
+/// Start HTTP and SMTP on ephemeral ports; returns (http base, smtp addr, http task).
+async fn start_servers() -> (String, String, JoinHandle<()>) {
+  let (base, smtp, handle, _state) = start_servers_with_state().await;
+  (base, smtp, handle)
+}
+
+/// Like `start_servers`, also returning the shared state for direct calls.
+async fn start_servers_with_state() -> (String, String, JoinHandle<()>, AppState) {
+  start_servers_on("sqlite://:memory:").await
+}
+
+/// Start both servers on the store named by a `FAUXMAIL_DATABASE` URL.
+async fn start_servers_on(db_url: &str) -> (String, String, JoinHandle<()>, AppState) {
+  serve_state(AppState {
+    store: store::open(db_url).await.expect("open store"),
+    blobs: None,
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  })
+  .await
+}
+
+/// Start both servers on an already assembled state.
+async fn serve_state(state: AppState) -> (String, String, JoinHandle<()>, AppState) {
+  let app: Router = http::build_router(state.clone());
+
+  let smtp_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let smtp_addr = smtp_listener.local_addr().unwrap();
+  let smtp_state = state.clone();
+  tokio::spawn(async move {
+    smtp::serve_smtp(smtp_state, smtp_listener).await.unwrap();
+  });
 
   let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
   let addr = listener.local_addr().unwrap();
+  let shutdown = state.shutdown.clone().cancelled_owned();
   let handle = tokio::spawn(async move {
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app)
+      .with_graceful_shutdown(shutdown)
+      .await
+      .unwrap();
   });
-  (format!("http://{addr}"), handle)
+  (
+    format!("http://{addr}"),
+    smtp_addr.to_string(),
+    handle,
+    state,
+  )
+}
+
+/// Read one (possibly multi-line) SMTP reply and return its final line.
+async fn read_reply<R: tokio::io::AsyncRead + Unpin>(reader: &mut BufReader<R>) -> String {
+  loop {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    if line.len() < 4 || line.as_bytes()[3] != b'-' {
+      return line.trim_end().to_string();
+    }
+  }
+}
+
+/// Minimal SMTP client: sends each command and collects the reply to it.
+async fn smtp_dialogue(addr: &str, commands: &[&str]) -> Vec<String> {
+  let stream = TcpStream::connect(addr).await.unwrap();
+  let (r, mut w) = stream.into_split();
+  let mut reader = BufReader::new(r);
+  let mut replies = vec![read_reply(&mut reader).await];
+  for cmd in commands {
+    w.write_all(format!("{cmd}\r\n").as_bytes()).await.unwrap();
+    replies.push(read_reply(&mut reader).await);
+  }
+  replies
 }
 
 #[tokio::test]
@@ -226,3 +291,2705 @@ async fn logs_feed_contains_send_event() {
   });
   assert!(found, "expected a REST stored log entry");
 }
+
+#[tokio::test]
+async fn smtp_session_transcript_is_linked_to_message() {
+  let (base, smtp_addr, _srv) = start_servers().await;
+
+  let replies = smtp_dialogue(
+    &smtp_addr,
+    &[
+      "EHLO client.test",
+      "MAIL FROM:<dev@example.test>",
+      "RCPT TO:<you@example.test>",
+      "DATA",
+      "Subject: Over SMTP\r\n\r\nHello\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  let stored = replies
+    .iter()
+    .find(|r| r.starts_with("250 OK id="))
+    .unwrap();
+  let id = stored.trim_start_matches("250 OK id=");
+
+  let client = reqwest::Client::new();
+  let res = client
+    .get(format!("{base}/messages/{id}/session"))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  let s: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(s["helo"], "client.test");
+  assert!(s["client_addr"].as_str().unwrap().starts_with("127.0.0.1:"));
+  let lines = s["transcript"].as_array().unwrap();
+  assert!(
+    lines
+      .iter()
+      .any(|l| l["dir"] == "client" && l["line"] == "RCPT TO:<you@example.test>")
+  );
+  assert!(
+    lines
+      .iter()
+      .any(|l| l["dir"] == "server" && l["line"] == stored.as_str())
+  );
+}
+
+#[tokio::test]
+async fn smtp_envelope_is_stored_separately_from_headers() {
+  let (base, smtp_addr, _srv) = start_servers().await;
+
+  let replies = smtp_dialogue(
+    &smtp_addr,
+    &[
+      "HELO sender.test",
+      "MAIL FROM:<bounce@example.test>",
+      "RCPT TO:<you@example.test>",
+      "RCPT TO:<hidden@example.test>",
+      "DATA",
+      "From: Dev <dev@example.test>\r\nTo: you@example.test\r\nSubject: Bcc\r\n\r\nHi\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  let id = replies
+    .iter()
+    .find_map(|r| r.strip_prefix("250 OK id="))
+    .unwrap();
+
+  let client = reqwest::Client::new();
+  let res = client
+    .get(format!("{base}/messages/{id}"))
+    .send()
+    .await
+    .unwrap();
+  let m: serde_json::Value = res.json().await.unwrap();
+  let m = &m["message"];
+  assert_eq!(m["from"], "Dev <dev@example.test>");
+  assert_eq!(m["to"], json!(["you@example.test"]));
+  assert_eq!(m["envelope_from"], "bounce@example.test");
+  assert_eq!(
+    m["envelope_to"],
+    json!(["you@example.test", "hidden@example.test"])
+  );
+  assert_eq!(m["helo"], "sender.test");
+  assert!(m["client_addr"].as_str().unwrap().starts_with("127.0.0.1:"));
+  assert!(m["headers"].get("bcc").is_none());
+  assert_eq!(
+    m["addresses"]["bcc"],
+    json!([{"name": null, "address": "hidden@example.test"}])
+  );
+}
+
+#[tokio::test]
+async fn raw_addresses_are_parsed_and_filterable() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: Dev Team <dev@example.test>\r\n",
+    "To: \"Doe, Jane\" <jane@example.test>, bob@example.test\r\n",
+    "Cc: Carol <carol@example.test>\r\n",
+    "Reply-To: support@example.test\r\n",
+    "Subject: Addresses\r\n",
+    "\r\n",
+    "Hi\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let m: serde_json::Value = client
+    .get(format!("{base}/messages/{id}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let a = &m["message"]["addresses"];
+  assert_eq!(
+    a["to"],
+    json!([
+      {"name": "Doe, Jane", "address": "jane@example.test"},
+      {"name": null, "address": "bob@example.test"},
+    ])
+  );
+  assert_eq!(a["from"][0]["name"], "Dev Team");
+  assert_eq!(a["cc"][0]["address"], "carol@example.test");
+  assert_eq!(a["reply_to"][0]["address"], "support@example.test");
+  assert_eq!(m["message"]["to"].as_array().unwrap().len(), 2);
+
+  // Cc recipients match the `to` filter; unrelated addresses do not.
+  let hits: serde_json::Value = client
+    .get(format!("{base}/messages?to=carol@example.test"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(hits.as_array().unwrap().len(), 1);
+  let misses: serde_json::Value = client
+    .get(format!("{base}/messages?to=nobody@example.test"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(misses.as_array().unwrap().len(), 0);
+
+  // An RFC 2047 encoded comma belongs to the display name.
+  let eml = concat!(
+    "From: =?UTF-8?Q?Doe=2C_Jane?= <jane@example.test>\r\n",
+    "To: =?UTF-8?Q?Smith=2C_Bob?= <bob@example.test>\r\n",
+    "Return-Path: <bounce@example.test>\r\n",
+    "Subject: Encoded\r\n",
+    "\r\n",
+    "Hi\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let m: serde_json::Value = client
+    .get(format!("{base}/messages/{id}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let a = &m["message"]["addresses"];
+  assert_eq!(
+    a["from"],
+    json!([{"name": "Doe, Jane", "address": "jane@example.test"}])
+  );
+  assert_eq!(
+    a["to"],
+    json!([{"name": "Smith, Bob", "address": "bob@example.test"}])
+  );
+  assert_eq!(m["message"]["from"], "\"Doe, Jane\" <jane@example.test>");
+  let report: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/analysis"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert!(
+    !report["findings"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .any(|f| f["rule"] == "from-return-path-mismatch")
+  );
+}
+
+#[tokio::test]
+async fn mime_tree_is_stored_with_nested_messages() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: dev@example.test\r\n",
+    "To: you@example.test\r\n",
+    "Subject: Tree\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: multipart/mixed; boundary=OUTER\r\n",
+    "\r\n",
+    "--OUTER\r\n",
+    "Content-Type: multipart/alternative; boundary=ALT\r\n",
+    "\r\n",
+    "--ALT\r\n",
+    "Content-Type: text/plain; charset=utf-8\r\n",
+    "Content-Transfer-Encoding: quoted-printable\r\n",
+    "\r\n",
+    "Caf=C3=A9\r\n",
+    "--ALT\r\n",
+    "Content-Type: text/html; charset=utf-8\r\n",
+    "\r\n",
+    "<p>Cafe</p>\r\n",
+    "--ALT--\r\n",
+    "--OUTER\r\n",
+    "Content-Type: message/rfc822\r\n",
+    "Content-Disposition: attachment\r\n",
+    "\r\n",
+    "Subject: Inner\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "Forwarded\r\n",
+    "--OUTER--\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let tree: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/parts"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(tree["path"], "1");
+  assert_eq!(tree["content_type"], "multipart/mixed");
+  let alt = &tree["children"][0];
+  assert_eq!(alt["content_type"], "multipart/alternative");
+  let text = &alt["children"][0];
+  assert_eq!(text["path"], "1.1.1");
+  assert_eq!(text["charset"], "utf-8");
+  assert_eq!(text["transfer_encoding"], "quoted-printable");
+  let fwd = &tree["children"][1];
+  assert_eq!(fwd["content_type"], "message/rfc822");
+  assert_eq!(fwd["disposition"], "attachment");
+  assert_eq!(fwd["children"][0]["path"], "1.2.1");
+  assert_eq!(fwd["children"][0]["content_type"], "text/plain");
+
+  let body = client
+    .get(format!(
+      "{base}/parts/{}/download",
+      text["id"].as_str().unwrap()
+    ))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert_eq!(body.trim_end(), "Café");
+}
+
+#[tokio::test]
+async fn deeply_nested_messages_are_cut_off() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let mut eml = String::from("From: dev@example.test\r\nTo: you@example.test\r\nSubject: Deep\r\n");
+  eml.push_str(&"Content-Type: message/rfc822\r\n\r\n".repeat(5000));
+  eml.push_str("Subject: Bottom\r\n\r\nhi\r\n");
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let mut node: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/parts"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let mut levels = 1;
+  while let Some(child) = node["children"].get(0).cloned() {
+    node = child;
+    levels += 1;
+  }
+  assert_eq!(levels, fauxmail::util::mime::MAX_EMBED_DEPTH + 1);
+  assert_eq!(node["content_type"], "message/rfc822");
+}
+
+#[tokio::test]
+async fn cid_images_are_inline_and_rewritten() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: dev@example.test\r\n",
+    "To: you@example.test\r\n",
+    "Subject: Logo\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: multipart/related; boundary=REL\r\n",
+    "\r\n",
+    "--REL\r\n",
+    "Content-Type: text/html\r\n",
+    "\r\n",
+    "<p><img src=\"cid:logo@example.test\"></p>\r\n",
+    "--REL\r\n",
+    "Content-Type: image/png\r\n",
+    "Content-ID: <logo@example.test>\r\n",
+    "Content-Transfer-Encoding: base64\r\n",
+    "\r\n",
+    "iVBORw0KGgo=\r\n",
+    "--REL--\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let att = &atts.as_array().unwrap()[0];
+  assert_eq!(att["content_id"], "logo@example.test");
+  assert_eq!(att["inline"], true);
+  let att_id = att["id"].as_str().unwrap();
+
+  let html = client
+    .get(format!("{base}/messages/{id}/body"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert!(html.contains(&format!("src=\"/attachments/{att_id}/download\"")));
+  assert!(!html.contains("cid:logo"));
+}
+
+#[tokio::test]
+async fn encoded_attachment_filenames_are_decoded() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: dev@example.test\r\n",
+    "To: you@example.test\r\n",
+    "Subject: Invoices\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: multipart/mixed; boundary=B\r\n",
+    "\r\n",
+    "--B\r\n",
+    "Content-Type: application/pdf\r\n",
+    "Content-Disposition: attachment; filename*=UTF-8''%E2%82%AC%20invoice.pdf\r\n",
+    "\r\n",
+    "one\r\n",
+    "--B\r\n",
+    "Content-Type: application/pdf\r\n",
+    "Content-Disposition: attachment;\r\n",
+    " filename*0*=ISO-8859-1''Rechnung%20M%E4rz;\r\n",
+    " filename*1=\".pdf\"\r\n",
+    "\r\n",
+    "two\r\n",
+    "--B\r\n",
+    "Content-Type: application/pdf; name=\"=?UTF-8?B?w6l0w6kucGRm?=\"\r\n",
+    "Content-Disposition: attachment\r\n",
+    "\r\n",
+    "three\r\n",
+    "--B\r\n",
+    "Content-Type: text/plain\r\n",
+    "Content-Disposition: attachment; filename=\"a;b \\\"c\\\".txt\"; size=3\r\n",
+    "\r\n",
+    "four\r\n",
+    "--B--\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let mut names: Vec<String> = atts
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|a| a["filename"].as_str().unwrap().to_string())
+    .collect();
+  names.sort();
+  assert_eq!(
+    names,
+    [
+      "Rechnung März.pdf",
+      "a;b \"c\".txt",
+      "été.pdf",
+      "€ invoice.pdf"
+    ]
+  );
+
+  let euro = atts
+    .as_array()
+    .unwrap()
+    .iter()
+    .find(|a| a["filename"] == "€ invoice.pdf")
+    .unwrap();
+  let res = client
+    .get(format!(
+      "{base}/attachments/{}/download",
+      euro["id"].as_str().unwrap()
+    ))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(
+    res.headers()["content-disposition"],
+    "inline; filename=\"_ invoice.pdf\"; filename*=UTF-8''%E2%82%AC%20invoice.pdf"
+  );
+}
+
+#[tokio::test]
+async fn message_html_is_sandboxed_and_metadata_escaped() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let payload = json!({
+      "from": "<script>alert('from')</script>@example.test",
+      "to": ["you@example.test"],
+      "subject": "<img src=x onerror=alert(1)>",
+      "html": "<p>Hi</p><script>fetch('/messages', {method: 'DELETE'})</script>",
+  });
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&payload)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  // The viewer page never inlines the body and escapes metadata.
+  let page = client
+    .get(format!("{base}/messages/{id}/html"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert!(!page.contains("<img src=x"));
+  assert!(page.contains("&lt;img src=x onerror=alert(1)&gt;"));
+  assert!(!page.contains("fetch('/messages', {method: 'DELETE'})"));
+  assert!(page.contains("sandbox=\"allow-popups"));
+
+  // Values that look like placeholders are not substituted again.
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({"from": "x{WHEN}@example.test", "to": ["you@example.test"], "subject": "{TO}"}))
+    .send()
+    .await
+    .unwrap();
+  let tricky = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let page = client
+    .get(format!("{base}/messages/{tricky}/html"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert!(page.contains("<title>{TO}</title>"));
+  assert!(page.contains("<h2>{TO}</h2>"));
+  assert!(page.contains("<strong>From:</strong> x{WHEN}@example.test &nbsp;"));
+
+  // The dashboard escapes it as well.
+  let index = client.get(format!("{base}/")).send().await.unwrap();
+  let index = index.text().await.unwrap();
+  assert!(!index.contains("<img src=x"));
+
+  // The body itself is served under a sandboxing CSP.
+  let res = client
+    .get(format!("{base}/messages/{id}/body?remote_images=false"))
+    .send()
+    .await
+    .unwrap();
+  let csp = res.headers()["content-security-policy"]
+    .to_str()
+    .unwrap()
+    .to_string();
+  assert!(csp.starts_with("sandbox "));
+  assert!(!csp.contains("allow-scripts"));
+  assert!(csp.contains("img-src 'self' data:;"));
+  assert!(res.text().await.unwrap().contains("<p>Hi</p>"));
+}
+
+#[tokio::test]
+async fn viewer_exposes_raw_source_and_ordered_headers() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "Received: from relay.example.test\r\n",
+    "From: dev@example.test\r\n",
+    "To: you@example.test\r\n",
+    "Subject: Tabs\r\n",
+    "X-Trace: b\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "Body\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let res = client
+    .get(format!("{base}/messages/{id}/raw"))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  assert_eq!(res.headers()["content-type"], "message/rfc822");
+  assert_eq!(res.text().await.unwrap(), eml);
+
+  let headers: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/headers"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let names: Vec<&str> = headers
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|h| h["name"].as_str().unwrap())
+    .collect();
+  assert_eq!(
+    names,
+    [
+      "Received",
+      "From",
+      "To",
+      "Subject",
+      "X-Trace",
+      "Content-Type"
+    ]
+  );
+
+  let page = client
+    .get(format!("{base}/messages/{id}/html"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  for tab in ["html", "text", "headers", "source", "parts", "attachments"] {
+    assert!(page.contains(&format!("data-tab=\"{tab}\"")));
+  }
+  assert!(page.contains("data-width=\"375px\""));
+
+  // JSON submissions keep no source; headers still come back.
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["a@example.test"], "headers": {"X-One": "1"}}))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let res = client
+    .get(format!("{base}/messages/{id}/raw"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 404);
+  let headers: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/headers"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(headers[0]["name"], "X-One");
+}
+
+#[tokio::test]
+async fn html_check_reports_client_support_with_lines() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let html = concat!(
+    "<html><head><style>\n",
+    "@media (max-width: 600px) { .x { display: flex; } }\n",
+    "</style></head><body>\n",
+    "<div style=\"border-radius: 4px; color: red\">Button</div>\n",
+    "<p>Your position: team lead</p>\n",
+    "<img src=\"https://cdn.example.test/logo.svg?v=2\" alt=\"\">\n",
+    "</body></html>\n",
+  );
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["you@example.test"], "html": html}))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let report: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/html-check"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(report["has_html"], true);
+  let clients = report["clients"].as_array().unwrap();
+  let outlook = clients.iter().find(|c| c["client"] == "outlook").unwrap();
+  let issue = |c: &serde_json::Value, feature: &str| {
+    c["issues"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .find(|i| i["feature"] == feature)
+      .cloned()
+  };
+  let radius = issue(outlook, "css-border-radius").unwrap();
+  assert_eq!(radius["support"], "unsupported");
+  assert_eq!(radius["lines"], json!([4]));
+  assert_eq!(issue(outlook, "css-at-media").unwrap()["lines"], json!([2]));
+  assert_eq!(
+    issue(outlook, "css-display-flex").unwrap()["lines"],
+    json!([2])
+  );
+  assert_eq!(issue(outlook, "image-svg").unwrap()["lines"], json!([6]));
+  // Text outside CSS is not mistaken for a declaration.
+  assert!(issue(outlook, "css-position").is_none());
+
+  let gmail = clients.iter().find(|c| c["client"] == "gmail").unwrap();
+  assert!(issue(gmail, "css-border-radius").is_none());
+  assert_eq!(issue(gmail, "css-at-media").unwrap()["support"], "partial");
+
+  let apple = clients
+    .iter()
+    .find(|c| c["client"] == "apple_mail")
+    .unwrap();
+  assert_eq!(apple["unsupported"], 0);
+}
+
+#[tokio::test]
+async fn oversized_json_messages_are_flagged() {
+  let (base, _smtp, _srv, state) = start_servers_with_state().await;
+  // Larger than the HTTP body limit, so ingest directly as an embedder would.
+  let req: fauxmail::http::messages::SendRequest = serde_json::from_value(json!({
+    "to": ["you@example.test"],
+    "subject": "Export",
+    "text": "x".repeat(11 * 1024 * 1024),
+  }))
+  .unwrap();
+  let id = ingest::ingest(&state, ingest::Source::Json, ingest::from_json(&req))
+    .await
+    .unwrap();
+
+  let report: serde_json::Value = reqwest::get(format!("{base}/messages/{id}/analysis"))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let oversized = report["findings"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .find(|f| f["rule"] == "oversized-message")
+    .expect("oversized-message finding");
+  assert_eq!(
+    oversized["message"],
+    "Message is 11 MB; many providers reject above 10 MB."
+  );
+}
+
+#[tokio::test]
+async fn deliverability_analysis_scores_findings() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: News <news@shop.example.test>\r\n",
+    "Return-Path: <bounce@mailer.example.net>\r\n",
+    "To: you@example.test\r\n",
+    "Subject: HUGE SALE TODAY ONLY!!\r\n",
+    "Content-Type: text/html\r\n",
+    "\r\n",
+    "<a href=\"http://192.0.2.7/login\">https://shop.example.test</a>\r\n",
+    "<img src=\"https://cdn.example.test/banner.png\">\r\n",
+    "<a href=\"https://bit.ly/x\">unsubscribe</a>\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let report: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/analysis"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let rules: Vec<&str> = report["findings"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|f| f["rule"].as_str().unwrap())
+    .collect();
+  for rule in [
+    "missing-message-id",
+    "missing-date",
+    "from-return-path-mismatch",
+    "image-only-body",
+    "missing-text-alternative",
+    "shouty-subject",
+    "suspicious-url",
+    "link-text-mismatch",
+    "missing-list-unsubscribe",
+  ] {
+    assert!(rules.contains(&rule), "missing {rule} in {rules:?}");
+  }
+  assert!(!rules.contains(&"oversized-message"));
+  assert_eq!(report["likely_spam"], true);
+
+  // A well-formed transactional message stays clean.
+  let eml = concat!(
+    "From: app@example.test\r\n",
+    "To: you@example.test\r\n",
+    "Subject: Your receipt\r\n",
+    "Date: Mon, 1 Jan 2024 00:00:00 +0000\r\n",
+    "Message-ID: <r1@example.test>\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "Thanks for your order.\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let report: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/analysis"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(report["findings"], json!([]));
+  assert_eq!(report["score"], 0.0);
+
+  // Non-ASCII text right after "http" is not a URL.
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({
+        "to": ["you@example.test"],
+        "subject": "协议",
+        "text": "see httpxy协议 here",
+        "html": "<p>httpé协议</p>",
+    }))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let res = client
+    .get(format!("{base}/messages/{id}/analysis"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let report: serde_json::Value = res.json().await.unwrap();
+  let rules: Vec<&str> = report["findings"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|f| f["rule"].as_str().unwrap())
+    .collect();
+  assert!(!rules.contains(&"suspicious-url"));
+}
+
+#[tokio::test]
+async fn links_are_extracted_and_checked() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let confirm = format!("{base}/messages");
+  let html = format!(
+    "<p><a href=\"https://track.example.test/c?u=1&amp;url=https%3A%2F%2Fapp.example.test%2Fwelcome\">Welcome</a></p>\n<a href=\"{confirm}\">Confirm your email</a>"
+  );
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({
+        "to": ["you@example.test"],
+        "html": html,
+        "text": format!("Confirm: {confirm}\nBroken: {base}/nope."),
+        "headers": {"List-Unsubscribe": "<mailto:unsub@example.test>, <https://app.example.test/unsub>"},
+    }))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let links: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/links"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let links = links.as_array().unwrap();
+  let urls: Vec<&str> = links.iter().map(|l| l["url"].as_str().unwrap()).collect();
+  assert_eq!(
+    urls,
+    [
+      "https://track.example.test/c?u=1&url=https%3A%2F%2Fapp.example.test%2Fwelcome",
+      confirm.as_str(),
+      &format!("{base}/nope"),
+      "mailto:unsub@example.test",
+      "https://app.example.test/unsub",
+    ]
+  );
+  assert_eq!(
+    links[0]["redirect_target"],
+    "https://app.example.test/welcome"
+  );
+  assert_eq!(links[1]["text"], "Confirm your email");
+  assert_eq!(links[1]["sources"], json!(["html", "text"]));
+  assert_eq!(links[3]["sources"], json!(["list-unsubscribe"]));
+  assert!(links[1]["check"].is_null());
+
+  let links: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/links?check=true"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(links[1]["check"]["status"], 200);
+  assert_eq!(links[2]["check"]["status"], 404);
+  assert!(links[3]["check"].is_null());
+}
+
+#[tokio::test]
+async fn links_skip_non_ascii_after_http() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({
+        "to": ["you@example.test"],
+        "text": "see httpxy协议 here, then http协 and https://app.example.test/ok",
+        "html": "<p>httpé https://app.example.test/ok</p>",
+    }))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let res = client
+    .get(format!("{base}/messages/{id}/links"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let links: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(links.as_array().unwrap().len(), 1);
+  assert_eq!(links[0]["url"], "https://app.example.test/ok");
+}
+
+#[tokio::test]
+async fn codes_are_extracted_and_waitable() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  // Nothing matches yet, so a short wait times out.
+  let res = client
+    .get(format!("{base}/wait?to=otp@example.test&timeout=1"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 404);
+  assert_eq!(res.text().await.unwrap(), "no matching message");
+
+  let waiter = tokio::spawn({
+    let client = client.clone();
+    let url = format!("{base}/wait?to=otp@example.test&has_code=true&timeout=10");
+    async move { client.get(url).send().await.unwrap() }
+  });
+  tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+  // A message without a code does not satisfy the wait.
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["otp@example.test"], "subject": "Welcome", "text": "Hello there"}))
+    .send()
+    .await
+    .unwrap();
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({
+        "to": ["otp@example.test"],
+        "subject": "Sign in to Example",
+        "text": "Order 2024 shipped.\nYour verification code is 482913.",
+        "html": "<p>Or <a href=\"https://app.example.test/auth/magic?token=abcdef123456\">sign in</a></p>",
+    }))
+    .send()
+    .await
+    .unwrap();
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+
+  let res = waiter.await.unwrap();
+  assert!(res.status().is_success());
+  let v: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(v["message"]["id"], id.as_str());
+  assert_eq!(v["codes"]["codes"][0]["code"], "482913");
+
+  let codes: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/codes"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let found: Vec<&str> = codes["codes"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|c| c["code"].as_str().unwrap())
+    .collect();
+  assert_eq!(found, ["482913"]);
+  assert_eq!(codes["codes"][0]["source"], "text");
+  assert_eq!(codes["magic_links"][0]["token"], "abcdef123456");
+}
+
+#[tokio::test]
+async fn wait_pages_past_newer_messages_without_codes() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+  let since = chrono::Utc::now();
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["otp@example.test"], "text": "Your code is 482913."}))
+    .send()
+    .await
+    .unwrap();
+  for i in 0..60 {
+    client
+      .post(format!("{base}/send"))
+      .json(&json!({"to": ["otp@example.test"], "text": format!("Newsletter {i}")}))
+      .send()
+      .await
+      .unwrap();
+  }
+
+  let url = format!("{base}/wait?to=otp@example.test&has_code=true&timeout=1");
+  let res = client.get(&url).send().await.unwrap();
+  assert_eq!(res.status(), 200);
+  let v: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(v["codes"]["codes"][0]["code"], "482913");
+  let res = client
+    .get(&url)
+    .query(&[("since", since.to_rfc3339())])
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn wait_survives_non_ascii_after_http() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["otp@example.test"], "text": "Your code is 482913. httpé"}))
+    .send()
+    .await
+    .unwrap();
+  // Newest, so every /wait scan extracts from it before reaching the code.
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["otp@example.test"], "text": "see httpxy协议 here"}))
+    .send()
+    .await
+    .unwrap();
+
+  let res = client
+    .get(format!(
+      "{base}/wait?to=otp@example.test&has_code=true&timeout=2"
+    ))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let v: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(v["codes"]["codes"][0]["code"], "482913");
+  let id = v["message"]["id"].as_str().unwrap();
+  let res = client
+    .get(format!("{base}/messages/{id}/codes"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn messages_are_grouped_into_threads() {
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+  let send = |eml: String| {
+    let client = client.clone();
+    let base = base.clone();
+    async move {
+      let res = client
+        .post(format!("{base}/send/raw"))
+        .body(eml)
+        .send()
+        .await
+        .unwrap();
+      res.json::<serde_json::Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string()
+    }
+  };
+  let eml = |id: &str, extra: &str, subject: &str| {
+    format!(
+      "From: a@example.test\r\nTo: b@example.test\r\nSubject: {subject}\r\nMessage-ID: <{id}>\r\n{extra}\r\nBody\r\n"
+    )
+  };
+
+  let first = send(eml("t1@example.test", "", "Ticket #1")).await;
+  // The second reply arrives before the first one.
+  let late = send(eml(
+    "t3@example.test",
+    "In-Reply-To: <t2@example.test>\r\nReferences: <t1@example.test> <t2@example.test>\r\n",
+    "Re: Re: Ticket #1",
+  ))
+  .await;
+  send(eml(
+    "t2@example.test",
+    "In-Reply-To: <t1@example.test>\r\nReferences: <t1@example.test>\r\n",
+    "Re: Ticket #1",
+  ))
+  .await;
+  let orphan = send(eml(
+    "o2@example.test",
+    "In-Reply-To: <never-captured@example.test>\r\n",
+    "Re: Something else",
+  ))
+  .await;
+
+  let threads: serde_json::Value = client
+    .get(format!("{base}/threads"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let threads = threads.as_array().unwrap();
+  assert_eq!(threads.len(), 2);
+  let ticket = threads
+    .iter()
+    .find(|t| t["subject"] == "Ticket #1")
+    .unwrap();
+  assert_eq!(ticket["message_count"], 3);
+  assert_eq!(ticket["participants"], json!(["a@example.test"]));
+
+  let thread_id = ticket["id"].as_str().unwrap();
+  let detail: serde_json::Value = client
+    .get(format!("{base}/threads/{thread_id}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let ids: Vec<&str> = detail["messages"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|m| m["id"].as_str().unwrap())
+    .collect();
+  assert_eq!(ids.len(), 3);
+  assert_eq!(ids[0], first);
+  assert!(ids.contains(&late.as_str()));
+  assert_eq!(detail["missing_references"], json!([]));
+  assert_eq!(detail["messages"][1]["in_reply_to"], "t2@example.test");
+
+  let msg: serde_json::Value = client
+    .get(format!("{base}/messages/{orphan}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let orphan_thread = msg["message"]["thread_id"].as_str().unwrap();
+  let detail: serde_json::Value = client
+    .get(format!("{base}/threads/{orphan_thread}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(
+    detail["missing_references"],
+    json!(["never-captured@example.test"])
+  );
+
+  let page = client
+    .get(format!("{base}/?view=threads"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert!(page.contains(&format!("/threads/{thread_id}")));
+  assert!(page.contains("padding-left:3.5rem"));
+}
+
+#[tokio::test]
+async fn mbox_and_zip_archives_import_and_export() {
+  use std::io::Write;
+
+  let (base, _srv) = start_server().await;
+  let client = reqwest::Client::new();
+
+  let mbox = concat!(
+    "From alice@example.test Mon Jan  1 00:00:00 2024\n",
+    "From: alice@example.test\n",
+    "To: team@example.test\n",
+    "Subject: First\n",
+    "\n",
+    "Hello\n",
+    ">From the archive\n",
+    "\n",
+    "From bob@example.test Mon Jan  1 00:01:00 2024\n",
+    "From: bob@example.test\n",
+    "To: team@example.test\n",
+    "Subject: Second\n",
+    "\n",
+    "Bye\n",
+  );
+  let res = client
+    .post(format!("{base}/import"))
+    .body(mbox)
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  let v: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(v["imported"], 2);
+  let first = v["ids"][0].as_str().unwrap().to_string();
+  let msg: serde_json::Value = client
+    .get(format!("{base}/messages/{first}"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(msg["message"]["text"], "Hello\nFrom the archive\n");
+
+  // A zip holding a loose .eml and a Maildir.
+  let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+  let opts = zip::write::SimpleFileOptions::default();
+  zip.start_file("fixtures/welcome.eml", opts).unwrap();
+  zip
+    .write_all(
+      b"From: carol@example.test\r\nTo: team@example.test\r\nSubject: Zipped\r\n\r\nHi\r\n",
+    )
+    .unwrap();
+  zip
+    .start_file("Maildir/cur/1700000000.M1P1.host:2,S", opts)
+    .unwrap();
+  zip
+    .write_all(
+      b"From: dave@example.test\r\nTo: team@example.test\r\nSubject: Maildir\r\n\r\nHi\r\n",
+    )
+    .unwrap();
+  zip.start_file("Maildir/tmp/ignored", opts).unwrap();
+  zip.write_all(b"partial").unwrap();
+  let zipped = zip.finish().unwrap().into_inner();
+  let res = client
+    .post(format!("{base}/import"))
+    .body(zipped.clone())
+    .send()
+    .await
+    .unwrap();
+  let v: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(v["imported"], 2);
+  assert_eq!(v["failed"], 0);
+
+  // Decompression stops at the budget, whatever the headers claim.
+  use fauxmail::util::archive::{UnpackError, unpack_zip};
+  let total: usize = unpack_zip(&zipped, u64::MAX)
+    .unwrap()
+    .iter()
+    .map(Vec::len)
+    .sum();
+  assert_eq!(unpack_zip(&zipped, total as u64).unwrap().len(), 2);
+  assert!(matches!(
+    unpack_zip(&zipped, total as u64 - 1),
+    Err(UnpackError::TooLarge)
+  ));
+  let mut forged = zipped.clone();
+  for at in 0..forged.len() - 4 {
+    let size_at = match &forged[at..at + 4] {
+      b"PK\x03\x04" => at + 22,
+      b"PK\x01\x02" => at + 24,
+      _ => continue,
+    };
+    forged[size_at..size_at + 4].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+  }
+  let unpacked = unpack_zip(&forged, total as u64).unwrap();
+  assert_eq!(unpacked.iter().map(Vec::len).sum::<usize>(), total);
+
+  // Export honours the list filters and escapes From lines.
+  let mbox = client
+    .get(format!("{base}/export.mbox?from=alice"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(mbox.headers()["content-type"], "application/mbox");
+  let mbox = mbox.text().await.unwrap();
+  assert!(mbox.starts_with("From alice@example.test "));
+  assert!(mbox.contains("\n>From the archive\n"));
+  assert!(!mbox.contains("Subject: Second"));
+
+  let all = client
+    .get(format!("{base}/export.mbox"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  let res = client
+    .delete(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  let v: serde_json::Value = client
+    .post(format!("{base}/import"))
+    .body(all)
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(v["imported"], 4);
+}
+
+#[tokio::test]
+async fn retention_purges_oldest_messages_per_mailbox() {
+  let (base, _smtp, _srv, state) = start_servers_with_state().await;
+  let client = reqwest::Client::new();
+
+  // The oldest message for a@ carries an attachment.
+  let eml = concat!(
+    "From: dev@example.test\r\n",
+    "To: a@example.test\r\n",
+    "Subject: oldest\r\n",
+    "Content-Type: multipart/mixed; boundary=B\r\n\r\n",
+    "--B\r\nContent-Type: text/plain\r\n\r\nhi\r\n",
+    "--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"a.txt\"\r\n\r\nA\r\n",
+    "--B--\r\n",
+  );
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml)
+    .send()
+    .await
+    .unwrap();
+  let oldest = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  for to in ["a@example.test", "a@example.test", "b@example.test"] {
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    client
+      .post(format!("{base}/send"))
+      .json(&json!({"to": [to], "text": "x"}))
+      .send()
+      .await
+      .unwrap();
+  }
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{oldest}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let att_id = atts[0]["id"].as_str().unwrap().to_string();
+
+  let policy = retention::RetentionPolicy {
+    max_messages: Some(2),
+    per_mailbox: true,
+    ..Default::default()
+  };
+  assert_eq!(retention::purge(&state, &policy).await.unwrap(), 1);
+  let res = client
+    .get(format!("{base}/messages/{oldest}"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 404);
+  let res = client
+    .get(format!("{base}/attachments/{att_id}/download"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 404);
+  let list: serde_json::Value = client
+    .get(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(list.as_array().unwrap().len(), 3);
+
+  // Messages with no recipients are limited as one group.
+  let mut unaddressed = Vec::new();
+  for subject in ["first", "second", "third"] {
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let res = client
+      .post(format!("{base}/send/raw"))
+      .body(format!(
+        "From: dev@example.test\r\nSubject: {subject}\r\n\r\nx\r\n"
+      ))
+      .send()
+      .await
+      .unwrap();
+    unaddressed.push(
+      res.json::<serde_json::Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string(),
+    );
+  }
+  assert_eq!(retention::purge(&state, &policy).await.unwrap(), 1);
+  let res = client
+    .get(format!("{base}/messages/{}", unaddressed[0]))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 404);
+  for id in &unaddressed[1..] {
+    let res = client
+      .get(format!("{base}/messages/{id}"))
+      .send()
+      .await
+      .unwrap();
+    assert_eq!(res.status(), 200);
+  }
+
+  // A global limit keeps only the newest message, logged once even with
+  // tracing events persisted too.
+  use tracing_subscriber::prelude::*;
+  let (layer, rx) = fauxmail::logging::layer();
+  fauxmail::logging::spawn_writer(state.clone(), rx);
+  let guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+  let policy = retention::RetentionPolicy {
+    max_messages: Some(1),
+    ..Default::default()
+  };
+  assert_eq!(retention::purge(&state, &policy).await.unwrap(), 4);
+  tracing::info!("purge done");
+  drop(guard);
+
+  let mut logs = Vec::new();
+  for _ in 0..50 {
+    logs = client
+      .get(format!("{base}/logs"))
+      .send()
+      .await
+      .unwrap()
+      .json::<Vec<serde_json::Value>>()
+      .await
+      .unwrap();
+    if logs.iter().any(|l| l["message"] == "purge done") {
+      break;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+  }
+  let purged = logs
+    .iter()
+    .filter(|l| l["message"] == "retention purged 4 messages")
+    .count();
+  assert_eq!(purged, 1, "{logs:?}");
+}
+
+#[tokio::test]
+async fn deleting_messages_keeps_unrelated_smtp_sessions() {
+  let path = std::env::temp_dir().join(format!("fauxmail-sessions-{}.db", uuid::Uuid::new_v4()));
+  let url = format!("sqlite://{}", path.display());
+  let (_base, smtp, _srv, state) = start_servers_on(&url).await;
+
+  let replies = smtp_dialogue(
+    &smtp,
+    &[
+      "EHLO sender.test",
+      "MAIL FROM:<a@example.test>",
+      "RCPT TO:<b@example.test>",
+      "DATA",
+      "Subject: kept briefly\r\n\r\nhi\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  let id: uuid::Uuid = replies[5].trim_start_matches("250 OK id=").parse().unwrap();
+  // A session that never sent DATA.
+  smtp_dialogue(&smtp, &["EHLO idle.test", "QUIT"]).await;
+  tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+  let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+  let sessions = || async {
+    sqlx::query_scalar::<_, String>("SELECT helo FROM smtp_sessions")
+      .fetch_all(&pool)
+      .await
+      .unwrap()
+  };
+  assert_eq!(sessions().await.len(), 2);
+  assert_eq!(state.store.delete_messages(&[id]).await.unwrap(), 1);
+  assert_eq!(sessions().await, ["idle.test"]);
+
+  // Clearing everything drops the sessions of the cleared messages too.
+  smtp_dialogue(
+    &smtp,
+    &[
+      "EHLO again.test",
+      "MAIL FROM:<a@example.test>",
+      "RCPT TO:<b@example.test>",
+      "DATA",
+      "Subject: cleared\r\n\r\nhi\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+  assert_eq!(sessions().await.len(), 2);
+  state.store.clear_messages().await.unwrap();
+  assert_eq!(sessions().await, ["idle.test"]);
+}
+
+/// Core flow every storage backend must support identically.
+async fn exercise_store(db_url: &str) {
+  let (base, smtp, _srv, _state) = start_servers_on(db_url).await;
+  let client = reqwest::Client::new();
+  client
+    .delete(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap();
+
+  let replies = smtp_dialogue(
+    &smtp,
+    &[
+      "EHLO client.test",
+      "MAIL FROM:<bounce@example.test>",
+      "RCPT TO:<ops@example.test>",
+      "DATA",
+      "From: Alice <alice@example.test>\r\nTo: ops@example.test\r\nSubject: Deploy\r\nMessage-ID: <d1@example.test>\r\nContent-Type: multipart/mixed; boundary=B\r\n\r\n--B\r\nContent-Type: text/plain\r\n\r\nshipping\r\n--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"notes.txt\"\r\n\r\nnotes\r\n--B--\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  assert!(replies[5].starts_with("250 OK id="), "{replies:?}");
+  let smtp_id = replies[5].trim_start_matches("250 OK id=").to_string();
+
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({
+      "to": ["alice@example.test"],
+      "subject": "Re: Deploy",
+      "text": "done",
+      "headers": {"In-Reply-To": "<d1@example.test>"}
+    }))
+    .send()
+    .await
+    .unwrap();
+
+  let list: serde_json::Value = client
+    .get(format!("{base}/messages?from=alice"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(list.as_array().unwrap().len(), 1);
+  assert_eq!(list[0]["envelope_from"], "bounce@example.test");
+
+  let raw = client
+    .get(format!("{base}/messages/{smtp_id}/raw"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert!(raw.starts_with("From: Alice"));
+  let session: serde_json::Value = client
+    .get(format!("{base}/messages/{smtp_id}/session"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(session["helo"], "client.test");
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{smtp_id}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let att_id = atts[0]["id"].as_str().unwrap();
+  let body = client
+    .get(format!("{base}/attachments/{att_id}/download"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert_eq!(body, "notes");
+
+  let threads: serde_json::Value = client
+    .get(format!("{base}/threads"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(threads.as_array().unwrap().len(), 1);
+  assert_eq!(threads[0]["message_count"], 2);
+  assert_eq!(threads[0]["subject"], "Deploy");
+
+  let res = client
+    .delete(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 204);
+  let list: serde_json::Value = client
+    .get(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(list, json!([]));
+}
+
+#[tokio::test]
+async fn legacy_sqlite_database_is_upgraded_in_place() {
+  let path = std::env::temp_dir().join(format!("fauxmail-legacy-{}.db", uuid::Uuid::new_v4()));
+  let url = format!("sqlite://{}", path.display());
+  let legacy = sqlx::SqlitePool::connect(&format!("{url}?mode=rwc"))
+    .await
+    .unwrap();
+  // Schema as shipped before versioning, plus a column added by ensure_column.
+  for sql in [
+    "CREATE TABLE messages (id TEXT PRIMARY KEY, received_at TEXT NOT NULL, from_addr TEXT NULL, to_recipients TEXT NOT NULL, subject TEXT NULL, text_body TEXT NULL, html_body TEXT NULL, headers_json TEXT NULL, raw_len INTEGER NOT NULL, session_id TEXT NULL)",
+    "CREATE TABLE logs (id INTEGER PRIMARY KEY AUTOINCREMENT, ts TEXT NOT NULL, level TEXT NOT NULL, message TEXT NOT NULL)",
+    "CREATE TABLE attachments (id TEXT PRIMARY KEY, message_id TEXT NOT NULL, filename TEXT NULL, content_type TEXT NOT NULL, size INTEGER NOT NULL, content BLOB NOT NULL)",
+  ] {
+    sqlx::query(sql).execute(&legacy).await.unwrap();
+  }
+  let id = uuid::Uuid::new_v4();
+  sqlx::query("INSERT INTO messages (id, received_at, from_addr, to_recipients, subject, text_body, raw_len) VALUES (?, ?, 'old@example.test', '[\"qa@example.test\"]', 'Before the upgrade', 'kept', 0)")
+    .bind(id)
+    .bind(chrono::Utc::now())
+    .execute(&legacy)
+    .await
+    .unwrap();
+  for message_id in [id, uuid::Uuid::new_v4()] {
+    sqlx::query("INSERT INTO attachments (id, message_id, filename, content_type, size, content) VALUES (?, ?, 'a.txt', 'text/plain', 1, x'41')")
+      .bind(uuid::Uuid::new_v4())
+      .bind(message_id)
+      .execute(&legacy)
+      .await
+      .unwrap();
+  }
+  legacy.close().await;
+
+  let (base, _smtp, _srv, state) = start_servers_on(&url).await;
+  let client = reqwest::Client::new();
+  let list: serde_json::Value = client
+    .get(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(list.as_array().unwrap().len(), 1);
+  assert_eq!(list[0]["subject"], "Before the upgrade");
+  assert_eq!(list[0]["thread_id"], id.to_string());
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(atts.as_array().unwrap().len(), 1);
+
+  // Reopening is a no-op; deleting the message cascades to its attachment.
+  store::open(&url).await.unwrap();
+  let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+  let version: i64 = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+  assert_eq!(version, db::migrations::latest(db::migrations::SQLITE));
+  let kept: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attachments")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+  assert_eq!(kept, 1, "orphaned attachments are dropped");
+  assert_eq!(state.store.delete_messages(&[id]).await.unwrap(), 1);
+  let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attachments")
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+  assert_eq!(left, 0);
+  pool.close().await;
+  let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn memory_store_serves_smtp_rest_and_threads() {
+  exercise_store("memory://").await;
+}
+
+/// Runs against a real server when `FAUXMAIL_TEST_POSTGRES_URL` is set.
+#[tokio::test]
+async fn postgres_store_serves_smtp_rest_and_threads() {
+  let Ok(url) = std::env::var("FAUXMAIL_TEST_POSTGRES_URL") else {
+    return;
+  };
+  exercise_store(&url).await;
+}
+
+#[tokio::test]
+async fn attachments_are_deduplicated_into_blob_store() {
+  let dir = std::env::temp_dir().join(format!("fauxmail-blobs-{}", uuid::Uuid::new_v4()));
+  let (base, _smtp, _srv, state) = serve_state(AppState {
+    store: store::open("sqlite://:memory:").await.unwrap(),
+    blobs: Some(std::sync::Arc::new(BlobStore::new(&dir, true))),
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  })
+  .await;
+  let client = reqwest::Client::new();
+
+  let eml = concat!(
+    "From: a@example.test\r\n",
+    "To: b@example.test\r\n",
+    "Subject: Report\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: multipart/mixed; boundary=B\r\n\r\n",
+    "--B\r\nContent-Type: text/plain\r\n\r\nsee attached\r\n",
+    "--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"r.txt\"\r\n\r\n0123456789\r\n",
+    "--B--\r\n"
+  );
+  let mut ids = Vec::new();
+  for _ in 0..2 {
+    let res = client
+      .post(format!("{base}/send/raw"))
+      .body(eml)
+      .send()
+      .await
+      .unwrap();
+    assert!(res.status().is_success());
+    let v: serde_json::Value = res.json().await.unwrap();
+    ids.push(v["id"].as_str().unwrap().to_string());
+  }
+
+  // One blob for the shared attachment body, one for the shared raw source.
+  let mut files = 0;
+  for shard in std::fs::read_dir(&dir).unwrap() {
+    files += std::fs::read_dir(shard.unwrap().path()).unwrap().count();
+  }
+  assert_eq!(files, 2);
+
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{}/attachments", ids[1]))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let att_id = atts[0]["id"].as_str().unwrap();
+  let row = state
+    .store
+    .get_attachment(att_id.parse().unwrap())
+    .await
+    .unwrap()
+    .unwrap();
+  assert!(row.content.is_empty());
+  assert!(row.sha256.is_some());
+
+  let url = format!("{base}/attachments/{att_id}/download");
+  let full = client.get(&url).send().await.unwrap();
+  assert_eq!(full.headers()["accept-ranges"], "bytes");
+  assert_eq!(full.text().await.unwrap(), "0123456789");
+  let part = client
+    .get(&url)
+    .header("Range", "bytes=2-5")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(part.status(), 206);
+  assert_eq!(part.headers()["content-range"], "bytes 2-5/10");
+  assert_eq!(part.text().await.unwrap(), "2345");
+  let tail = client
+    .get(&url)
+    .header("Range", "bytes=-3")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(tail.text().await.unwrap(), "789");
+  let past = client
+    .get(&url)
+    .header("Range", "bytes=10-")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(past.status(), 416);
+  assert_eq!(past.headers()["content-range"], "bytes */10");
+
+  let raw = client
+    .get(format!("{base}/messages/{}/raw", ids[0]))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(raw.text().await.unwrap(), eml);
+  let tree: serde_json::Value = client
+    .get(format!("{base}/messages/{}/parts", ids[0]))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let part_id = tree["children"][1]["id"].as_str().unwrap();
+  let body = client
+    .get(format!("{base}/parts/{part_id}/download"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert_eq!(body, "0123456789");
+
+  // Unreferenced blobs survive the grace period, then go.
+  state.store.clear_messages().await.unwrap();
+  let blobs = state.blobs.as_ref().unwrap();
+  let refs = state.store.blob_refs().await.unwrap();
+  assert!(refs.is_empty());
+  assert_eq!(
+    blobs
+      .prune(&refs, fauxmail::blob::PRUNE_GRACE)
+      .await
+      .unwrap(),
+    0
+  );
+  assert_eq!(
+    blobs.prune(&refs, std::time::Duration::ZERO).await.unwrap(),
+    2
+  );
+  std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Rejects subjects tagged `[spam]`, stamps the rest, and records commits.
+struct TagHook {
+  stored: std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>,
+}
+
+#[async_trait::async_trait]
+impl ingest::Hook for TagHook {
+  async fn before_store(
+    &self,
+    msg: &mut fauxmail::models::email::new_message::NewMessage,
+    source: ingest::Source,
+  ) -> Result<(), String> {
+    if msg.subject.as_deref().unwrap_or("").contains("[spam]") {
+      return Err("tagged as spam".into());
+    }
+    msg
+      .headers
+      .insert("x-ingest-source".into(), source.as_str().into());
+    Ok(())
+  }
+
+  async fn after_store(
+    &self,
+    _state: &AppState,
+    _msg: &fauxmail::models::email::new_message::NewMessage,
+    source: ingest::Source,
+  ) {
+    self.stored.lock().unwrap().push(source.as_str());
+  }
+}
+
+#[tokio::test]
+async fn webhook_hook_fires_on_smtp_ingest() {
+  let (tx, mut rx) = tokio::sync::mpsc::channel::<serde_json::Value>(4);
+  let receiver = Router::new().route(
+    "/hook",
+    axum::routing::post(move |axum::Json(body): axum::Json<serde_json::Value>| {
+      let tx = tx.clone();
+      async move {
+        tx.send(body).await.unwrap();
+      }
+    }),
+  );
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let hook_url = format!("http://{}/hook", listener.local_addr().unwrap());
+  tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+
+  let mut hooks = ingest::Hooks::default();
+  hooks.push(ingest::Webhook::new(hook_url));
+  let (_base, smtp_addr, _srv, _state) = serve_state(AppState {
+    store: store::open("sqlite://:memory:").await.unwrap(),
+    blobs: None,
+    hooks,
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  })
+  .await;
+
+  let replies = smtp_dialogue(
+    &smtp_addr,
+    &[
+      "EHLO client.test",
+      "MAIL FROM:<a@example.test>",
+      "RCPT TO:<b@example.test>",
+      "DATA",
+      "From: a@example.test\r\nTo: b@example.test\r\nSubject: Hooked\r\n\r\nhi\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  let id = replies[5].trim_start_matches("250 OK id=");
+  let event = tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+    .await
+    .expect("webhook delivered")
+    .unwrap();
+  assert_eq!(event["event"], "message.stored");
+  assert_eq!(event["id"], id);
+  assert_eq!(event["source"], "smtp");
+  assert_eq!(event["subject"], "Hooked");
+  assert_eq!(event["to"], json!(["b@example.test"]));
+}
+
+#[tokio::test]
+async fn ingest_runs_hooks_and_stores_atomically() {
+  let pool = sqlx::sqlite::SqlitePoolOptions::new()
+    .max_connections(1)
+    .connect("sqlite::memory:")
+    .await
+    .unwrap();
+  db::run_migrations(&pool).await.unwrap();
+  // Fail halfway through a message: after its row and recipients are written.
+  sqlx::query("CREATE TRIGGER poison BEFORE INSERT ON attachments WHEN NEW.filename = 'poison.txt' BEGIN SELECT RAISE(ABORT, 'disk full'); END")
+    .execute(&pool)
+    .await
+    .unwrap();
+  let stored = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  let mut hooks = ingest::Hooks::default();
+  hooks.push(TagHook {
+    stored: stored.clone(),
+  });
+  let (base, smtp_addr, _srv, _state) = serve_state(AppState {
+    store: std::sync::Arc::new(store::sqlite::SqliteStore::new(pool.clone())),
+    blobs: None,
+    hooks,
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  })
+  .await;
+  let client = reqwest::Client::new();
+
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["qa@example.test"], "subject": "Welcome"}))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  let id = res.json::<serde_json::Value>().await.unwrap()["id"]
+    .as_str()
+    .unwrap()
+    .to_string();
+  let headers: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/headers"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert!(
+    headers
+      .as_array()
+      .unwrap()
+      .iter()
+      .any(|h| h["name"] == "x-ingest-source" && h["value"] == "json")
+  );
+
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body("From: a@example.test\r\nTo: qa@example.test\r\nSubject: [spam] deal\r\n\r\nbuy\r\n")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 422);
+  assert_eq!(res.text().await.unwrap(), "tagged as spam");
+
+  let replies = smtp_dialogue(
+    &smtp_addr,
+    &[
+      "HELO sender.test",
+      "MAIL FROM:<a@example.test>",
+      "RCPT TO:<qa@example.test>",
+      "DATA",
+      "Subject: [spam] again\r\n\r\nbuy\r\n.",
+      "MAIL FROM:<a@example.test>",
+      "RCPT TO:<qa@example.test>",
+      "DATA",
+      "From: a@example.test\r\nTo: qa@example.test\r\nSubject: Report\r\nContent-Type: multipart/mixed; boundary=B\r\n\r\n--B\r\nContent-Type: text/plain\r\n\r\nhi\r\n--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"poison.txt\"\r\n\r\nx\r\n--B--\r\n.",
+      "QUIT",
+    ],
+  )
+  .await;
+  assert_eq!(replies[5], "550 Message rejected: tagged as spam");
+  assert!(replies[9].starts_with("451 "), "{replies:?}");
+
+  // Nothing of the failed message survived the rollback.
+  for (table, expected) in [("messages", 1), ("recipients", 1), ("parts", 0)] {
+    let n: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+    assert_eq!(n, expected, "{table}");
+  }
+  assert_eq!(*stored.lock().unwrap(), vec!["json"]);
+}
+
+#[tokio::test]
+async fn failed_ingest_removes_blobs_it_wrote() {
+  let dir = std::env::temp_dir().join(format!("fauxmail-blobs-{}", uuid::Uuid::new_v4()));
+  let pool = sqlx::sqlite::SqlitePoolOptions::new()
+    .max_connections(1)
+    .connect("sqlite::memory:")
+    .await
+    .unwrap();
+  db::run_migrations(&pool).await.unwrap();
+  sqlx::query("CREATE TRIGGER poison BEFORE INSERT ON attachments WHEN NEW.filename = 'poison.txt' BEGIN SELECT RAISE(ABORT, 'disk full'); END")
+    .execute(&pool)
+    .await
+    .unwrap();
+  let (base, _smtp, _srv, _state) = serve_state(AppState {
+    store: std::sync::Arc::new(store::sqlite::SqliteStore::new(pool.clone())),
+    blobs: Some(std::sync::Arc::new(BlobStore::new(&dir, false))),
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  })
+  .await;
+  let client = reqwest::Client::new();
+  let eml = |attachments: &[(&str, &str)]| {
+    let mut eml = String::from(
+      "From: a@example.test\r\nTo: b@example.test\r\nSubject: Files\r\nContent-Type: multipart/mixed; boundary=B\r\n\r\n--B\r\nContent-Type: text/plain\r\n\r\nhi\r\n",
+    );
+    for (name, body) in attachments {
+      eml.push_str(&format!("--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"{name}\"\r\n\r\n{body}\r\n"));
+    }
+    eml + "--B--\r\n"
+  };
+  let blob_files = || {
+    let mut files = 0;
+    for shard in std::fs::read_dir(&dir).unwrap() {
+      files += std::fs::read_dir(shard.unwrap().path()).unwrap().count();
+    }
+    files
+  };
+
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml(&[("a.txt", "shared")]))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  assert_eq!(blob_files(), 1);
+
+  // The failed message reuses the stored blob and writes a fresh one.
+  let res = client
+    .post(format!("{base}/send/raw"))
+    .body(eml(&[("a.txt", "shared"), ("poison.txt", "fresh")]))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 500);
+  assert_eq!(blob_files(), 1);
+  let messages: serde_json::Value = client
+    .get(format!("{base}/messages"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let id = messages[0]["id"].as_str().unwrap();
+  let atts: serde_json::Value = client
+    .get(format!("{base}/messages/{id}/attachments"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  let att_id = atts[0]["id"].as_str().unwrap();
+  let body = client
+    .get(format!("{base}/attachments/{att_id}/download"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  assert_eq!(body, "shared");
+  std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn metrics_count_ingest_and_smtp_traffic() {
+  let (base, smtp_addr, _srv) = start_servers().await;
+  let client = reqwest::Client::new();
+  let res = client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["qa@Example.test", "ops@other.test"], "subject": "Hi"}))
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  smtp_dialogue(
+    &smtp_addr,
+    &[
+      "EHLO client.test",
+      "MAIL FROM:<dev@example.test>",
+      "RCPT TO:<qa@example.test>",
+      "DATA",
+      "From: dev@example.test\r\nTo: qa@example.test\r\nSubject: Files\r\nContent-Type: multipart/mixed; boundary=B\r\n\r\n--B\r\nContent-Type: text/plain\r\n\r\nhi\r\n--B\r\nContent-Type: text/plain\r\nContent-Disposition: attachment; filename=\"n.txt\"\r\n\r\nnotes\r\n--B--\r\n.",
+      "BOGUS",
+      "QUIT",
+    ],
+  )
+  .await;
+
+  let res = client.get(format!("{base}/metrics")).send().await.unwrap();
+  assert!(
+    res.headers()["content-type"]
+      .to_str()
+      .unwrap()
+      .starts_with("text/plain; version=0.0.4")
+  );
+  let body = res.text().await.unwrap();
+  for line in [
+    "# TYPE fauxmail_messages_received_total counter",
+    "fauxmail_messages_received_total{source=\"json\"} 1",
+    "fauxmail_messages_received_total{source=\"smtp\"} 1",
+    "fauxmail_messages_received_by_domain_total{domain=\"example.test\"} 2",
+    "fauxmail_messages_received_by_domain_total{domain=\"other.test\"} 1",
+    "# TYPE fauxmail_ingest_duration_seconds histogram",
+    "fauxmail_ingest_duration_seconds_bucket{source=\"smtp\",le=\"+Inf\"} 1",
+    "fauxmail_ingest_duration_seconds_count{source=\"json\"} 1",
+    "fauxmail_smtp_connections_total 1",
+    "fauxmail_smtp_auth_failures_total 0",
+    "fauxmail_smtp_replies_total{code=\"220\"} 1",
+    "fauxmail_smtp_replies_total{code=\"250\"} 4",
+    "fauxmail_smtp_replies_total{code=\"354\"} 1",
+    "fauxmail_smtp_replies_total{code=\"502\"} 1",
+    "fauxmail_attachment_bytes_received_total 5",
+    "fauxmail_messages 2",
+    "fauxmail_attachment_bytes 5",
+  ] {
+    assert!(
+      body.lines().any(|l| l == line),
+      "missing {line:?} in\n{body}"
+    );
+  }
+  assert!(body.contains("\nfauxmail_database_size_bytes "));
+
+  // Past the label cap, new domains fold into `other`, once per message.
+  let fresh = fauxmail::metrics::MAX_DOMAIN_LABELS - 2;
+  let to: Vec<String> = (0..fresh + 5).map(|i| format!("qa@d{i:02}.test")).collect();
+  for _ in 0..2 {
+    let res = client
+      .post(format!("{base}/send"))
+      .json(&json!({"to": to, "subject": "Spread"}))
+      .send()
+      .await
+      .unwrap();
+    assert!(res.status().is_success());
+  }
+  let body = client
+    .get(format!("{base}/metrics"))
+    .send()
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+  let domains: Vec<&str> = body
+    .lines()
+    .filter(|l| l.starts_with("fauxmail_messages_received_by_domain_total{"))
+    .collect();
+  assert_eq!(domains.len(), fauxmail::metrics::MAX_DOMAIN_LABELS + 1);
+  assert!(domains.contains(&"fauxmail_messages_received_by_domain_total{domain=\"d00.test\"} 2"));
+  assert!(domains.contains(&"fauxmail_messages_received_by_domain_total{domain=\"other\"} 2"));
+  assert!(!body.contains(&format!("domain=\"d{fresh}.test\"")));
+}
+
+#[tokio::test]
+async fn tracing_events_are_persisted_and_filterable() {
+  use tracing_subscriber::prelude::*;
+
+  let (base, _smtp, _srv, state) = start_servers_with_state().await;
+  let client = reqwest::Client::new();
+  let (layer, rx) = fauxmail::logging::layer();
+  tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+    tracing::warn!(target: "fauxmail::smtp", peer = "127.0.0.1:9", attempts = 3, "smtp connection error");
+    tracing::info!(target: "sqlx::query", "SELECT 1");
+    tracing::error!(target: "fauxmail::http", "boom");
+  });
+  fauxmail::logging::spawn_writer(state.clone(), rx);
+  for subject in ["one", "two", "three"] {
+    client
+      .post(format!("{base}/send"))
+      .json(&json!({"to": ["qa@example.test"], "subject": subject}))
+      .send()
+      .await
+      .unwrap();
+  }
+
+  let get = |query: &'static str| {
+    let client = client.clone();
+    let base = base.clone();
+    async move {
+      let res = client
+        .get(format!("{base}/logs{query}"))
+        .send()
+        .await
+        .unwrap();
+      assert!(res.status().is_success(), "{query}");
+      res.json::<Vec<serde_json::Value>>().await.unwrap()
+    }
+  };
+  let mut warnings = Vec::new();
+  for _ in 0..50 {
+    warnings = get("?level=warn").await;
+    if warnings.len() == 2 {
+      break;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+  }
+  assert_eq!(warnings.len(), 2, "{warnings:?}");
+  assert_eq!(warnings[0]["level"], "WARN");
+  assert_eq!(warnings[0]["target"], "fauxmail::smtp");
+  assert_eq!(warnings[0]["message"], "smtp connection error");
+  assert_eq!(
+    warnings[0]["fields"],
+    json!({"peer": "127.0.0.1:9", "attempts": 3})
+  );
+  assert_eq!(warnings[1]["level"], "ERROR");
+
+  let all = get("").await;
+  assert_eq!(all.len(), 5, "sqlx events are not persisted: {all:?}");
+  assert!(
+    all
+      .windows(2)
+      .all(|w| w[0]["id"].as_i64() < w[1]["id"].as_i64())
+  );
+  assert_eq!(get("?q=127.0.0.1").await.len(), 1);
+  assert_eq!(get("?q=STORED%20message").await.len(), 3);
+
+  // Newest page first, each page oldest first.
+  let newest = get("?limit=2").await;
+  assert_eq!(newest[1]["id"], all[4]["id"]);
+  let older = get("?limit=2&page=2").await;
+  assert_eq!(older[0]["id"], all[1]["id"]);
+  // Tailing pages forward from an id.
+  let since = all[1]["id"].as_i64().unwrap();
+  let tail: Vec<serde_json::Value> = client
+    .get(format!("{base}/logs?since_id={since}&limit=2"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(tail.len(), 2);
+  assert_eq!(tail[0]["id"], all[2]["id"]);
+
+  assert!(get("?until=2000-01-01T00:00:00Z").await.is_empty());
+  assert_eq!(get("?since=2000-01-01T00:00:00Z").await.len(), 5);
+  let res = client
+    .get(format!("{base}/logs?level=loud"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 400);
+}
+
+#[tokio::test]
+async fn probes_report_readiness_and_info() {
+  let state = AppState {
+    store: store::open("sqlite://:memory:").await.unwrap(),
+    blobs: None,
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: std::sync::Arc::new(fauxmail::app::ServerInfo {
+      database: "sqlite://:memory:".into(),
+      ..Default::default()
+    }),
+    auth: Default::default(),
+    shutdown: Default::default(),
+  };
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let base = format!("http://{}", listener.local_addr().unwrap());
+  let app = http::build_router(state.clone());
+  tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+  let client = reqwest::Client::new();
+
+  let res = client.get(format!("{base}/healthz")).send().await.unwrap();
+  assert_eq!(res.status(), 200);
+  assert_eq!(res.text().await.unwrap(), "ok");
+
+  // HTTP is up but SMTP is not listening yet.
+  let res = client.get(format!("{base}/readyz")).send().await.unwrap();
+  assert_eq!(res.status(), 503);
+  let body: serde_json::Value = res.json().await.unwrap();
+  assert_eq!(
+    body,
+    json!({"ready": false, "database": true, "smtp": false})
+  );
+
+  let smtp_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let smtp_addr = smtp_listener.local_addr().unwrap();
+  tokio::spawn(smtp::serve_smtp(state.clone(), smtp_listener));
+  let mut status = 0;
+  for _ in 0..50 {
+    status = client
+      .get(format!("{base}/readyz"))
+      .send()
+      .await
+      .unwrap()
+      .status()
+      .as_u16();
+    if status == 200 {
+      break;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+  }
+  assert_eq!(status, 200);
+
+  client
+    .post(format!("{base}/send"))
+    .json(&json!({"to": ["qa@example.test"], "subject": "Counted"}))
+    .send()
+    .await
+    .unwrap();
+  let info: serde_json::Value = client
+    .get(format!("{base}/info"))
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+  assert_eq!(info["smtp_addr"], smtp_addr.to_string());
+  assert_eq!(info["database"], "sqlite://:memory:");
+  assert_eq!(info["messages"], 1);
+  assert_eq!(info["features"]["blob_store"], false);
+  assert_eq!(info["features"]["ingest_hooks"], 0);
+}
+
+#[tokio::test]
+async fn shutdown_closes_smtp_sessions_and_drains_http() {
+  let (base, smtp_addr, http, state) = start_servers_with_state().await;
+  let client = reqwest::Client::new();
+
+  let stream = TcpStream::connect(&smtp_addr).await.unwrap();
+  let (r, mut w) = stream.into_split();
+  let mut reader = BufReader::new(r);
+  assert!(read_reply(&mut reader).await.starts_with("220"));
+  w.write_all(b"EHLO test\r\nMAIL FROM:<a@x.test>\r\nRCPT TO:<b@x.test>\r\nDATA\r\n")
+    .await
+    .unwrap();
+  for _ in 0..4 {
+    read_reply(&mut reader).await;
+  }
+  w.write_all(b"Subject: partial\r\n\r\nnever finished\r\n")
+    .await
+    .unwrap();
+
+  let wait = tokio::spawn(
+    client
+      .get(format!("{base}/wait?q=nothing&timeout=30"))
+      .send(),
+  );
+  tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+  state.shutdown.cancel();
+
+  assert!(read_reply(&mut reader).await.starts_with("421"));
+  let res = wait.await.unwrap().unwrap();
+  assert_eq!(res.status(), 503);
+  tokio::time::timeout(std::time::Duration::from_secs(5), http)
+    .await
+    .expect("http server drained")
+    .unwrap();
+  assert!(TcpStream::connect(&smtp_addr).await.is_err());
+  let count = state
+    .store
+    .list_messages(&Default::default())
+    .await
+    .unwrap()
+    .len();
+  assert_eq!(count, 0, "interrupted DATA is not stored");
+}
+
+#[tokio::test]
+async fn dashboard_login_rejects_cross_site_writes() {
+  use fauxmail::auth::{AuthConfig, Scope};
+  let auth = AuthConfig::default()
+    .with_token("root", &[Scope::Admin])
+    .with_dashboard_login("qa", "secret", &[Scope::Admin]);
+  let (base, _smtp, _srv, _state) = serve_state(AppState {
+    store: store::open("sqlite://:memory:").await.unwrap(),
+    blobs: None,
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: std::sync::Arc::new(auth),
+    shutdown: Default::default(),
+  })
+  .await;
+  let client = reqwest::Client::new();
+  let eml = "From: a@example.test\r\nTo: qa@example.test\r\nSubject: Forged\r\n\r\nhi\r\n";
+  let host = base.trim_start_matches("http://");
+  let post = |headers: &[(&str, String)], basic: bool| {
+    let mut req = client
+      .post(format!("{base}/send/raw"))
+      .header("content-type", "text/plain")
+      .body(eml);
+    req = if basic {
+      req.basic_auth("qa", Some("secret"))
+    } else {
+      req.bearer_auth("root")
+    };
+    for (name, value) in headers {
+      req = req.header(*name, value);
+    }
+    req.send()
+  };
+
+  // A form on another site posting with the cached dashboard login.
+  for headers in [
+    vec![("sec-fetch-site", "cross-site".to_string())],
+    vec![("sec-fetch-site", "same-site".to_string())],
+    vec![("origin", "http://evil.test".to_string())],
+    vec![("origin", "null".to_string())],
+  ] {
+    let res = post(&headers, true).await.unwrap();
+    assert_eq!(res.status(), 403, "{headers:?}");
+    assert_eq!(res.text().await.unwrap(), "cross-site request rejected");
+  }
+
+  // Same-origin dashboard requests, plain clients and bearer tokens pass.
+  for (headers, basic) in [
+    (vec![("sec-fetch-site", "same-origin".to_string())], true),
+    (vec![("origin", format!("http://{host}"))], true),
+    (vec![], true),
+    (vec![("sec-fetch-site", "cross-site".to_string())], false),
+  ] {
+    let res = post(&headers, basic).await.unwrap();
+    assert!(res.status().is_success(), "{headers:?}");
+  }
+
+  // Following a link from another site still opens the dashboard.
+  let res = client
+    .get(format!("{base}/"))
+    .basic_auth("qa", Some("secret"))
+    .header("sec-fetch-site", "cross-site")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+}
+
+#[tokio::test]
+async fn api_tokens_and_dashboard_login_enforce_scopes() {
+  use fauxmail::auth::{AuthConfig, Scope};
+  let auth = AuthConfig::default()
+    .with_token("reader", &[Scope::Read])
+    .with_token("root", &[Scope::Admin])
+    .with_dashboard_login("qa", "secret", &[Scope::Read]);
+  let (base, _smtp, _srv, _state) = serve_state(AppState {
+    store: store::open("sqlite://:memory:").await.unwrap(),
+    blobs: None,
+    hooks: Default::default(),
+    metrics: Default::default(),
+    server: Default::default(),
+    auth: std::sync::Arc::new(auth),
+    shutdown: Default::default(),
+  })
+  .await;
+  let client = reqwest::Client::new();
+  let mail = json!({"to": ["a@x.test"], "subject": "hi", "text": "x"});
+
+  let res = client.get(format!("{base}/healthz")).send().await.unwrap();
+  assert_eq!(res.status(), 200);
+  let res = client.get(format!("{base}/messages")).send().await.unwrap();
+  assert_eq!(res.status(), 401);
+  assert_eq!(
+    res.headers()["www-authenticate"],
+    "Basic realm=\"fauxmail\""
+  );
+  let res = client
+    .get(format!("{base}/messages"))
+    .bearer_auth("wrong")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 401);
+
+  // Read-only token: lookups pass, sending and deleting do not.
+  let res = client
+    .get(format!("{base}/messages"))
+    .bearer_auth("reader")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let res = client
+    .post(format!("{base}/send"))
+    .bearer_auth("reader")
+    .json(&mail)
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 403);
+  let res = client
+    .delete(format!("{base}/messages"))
+    .bearer_auth("reader")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 403);
+
+  // Dashboard login.
+  let res = client
+    .get(format!("{base}/"))
+    .basic_auth("qa", Some("secret"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 200);
+  let res = client
+    .get(format!("{base}/"))
+    .basic_auth("qa", Some("nope"))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 401);
+
+  // Managed send-only token.
+  let res = client
+    .post(format!("{base}/tokens"))
+    .bearer_auth("root")
+    .json(&json!({"name": "ci", "scopes": ["send"]}))
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 201);
+  let created: serde_json::Value = res.json().await.unwrap();
+  let token = created["token"].as_str().unwrap().to_string();
+  let id = created["id"].as_str().unwrap().to_string();
+  assert_eq!(created["scopes"], json!(["send"]));
+  let res = client
+    .post(format!("{base}/send"))
+    .bearer_auth(&token)
+    .json(&mail)
+    .send()
+    .await
+    .unwrap();
+  assert!(res.status().is_success());
+  let res = client
+    .get(format!("{base}/messages"))
+    .bearer_auth(&token)
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 403);
+  let res = client
+    .get(format!("{base}/tokens"))
+    .bearer_auth("reader")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 403);
+
+  let listed: serde_json::Value = client
+    .get(format!("{base}/tokens"))
+    .bearer_auth("root")
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(listed.as_array().unwrap().len(), 1);
+  assert_eq!(listed[0]["name"], "ci");
+  assert!(listed[0].get("token").is_none());
+
+  let res = client
+    .delete(format!("{base}/tokens/{id}"))
+    .bearer_auth("root")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 204);
+  let res = client
+    .post(format!("{base}/send"))
+    .bearer_auth(&token)
+    .json(&mail)
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(res.status(), 401);
+
+  let info: serde_json::Value = client
+    .get(format!("{base}/info"))
+    .bearer_auth("reader")
+    .send()
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+  assert_eq!(info["features"]["api_auth"], true);
+}